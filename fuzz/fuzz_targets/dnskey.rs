@@ -0,0 +1,22 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zns::{parser::FromBytes, reader::Reader};
+use zns_daemon::auth::{
+    dnskey::DNSKeyRData,
+    pubkeys::{EcdsaPublicKey, Ed25519PublicKey, PublicKey, RsaPublicKey},
+};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = DNSKeyRData::from_bytes(&mut Reader::new(data));
+
+    if let Ok(key) = RsaPublicKey::from_dnskey(data) {
+        let _ = key.to_dnskey();
+    }
+    if let Ok(key) = Ed25519PublicKey::from_dnskey(data) {
+        let _ = key.to_dnskey();
+    }
+    if let Ok(key) = EcdsaPublicKey::from_dnskey(data) {
+        let _ = key.to_dnskey();
+    }
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zns_daemon::auth::pubkeys::{EcdsaPublicKey, Ed25519PublicKey, PublicKey, RsaPublicKey};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(key) = RsaPublicKey::from_openssh(data) {
+        let _ = key.to_dnskey();
+    }
+    if let Ok(key) = Ed25519PublicKey::from_openssh(data) {
+        let _ = key.to_dnskey();
+    }
+    if let Ok(key) = EcdsaPublicKey::from_openssh(data) {
+        let _ = key.to_dnskey();
+    }
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zns_daemon::auth::sig::Sig;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(sig) = Sig::new(data) {
+        let _ = sig.verify_ssh(
+            "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIAABAgMEBQYHCAkKCwwNDg8QERITFBUWFxgZGhscHR4f",
+        );
+    }
+});
@@ -0,0 +1,70 @@
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ring::signature::{self, UnparsedPublicKey, VerificationAlgorithm};
+use zns::{
+    dnssec::{key_tag, parse_key_record},
+    errors::ZNSError,
+    parser::{FromBytes, ToBytes},
+    reader::Reader,
+    structs::{Message, RRType, SigRData, Type},
+};
+
+fn key_error(message: &str) -> ZNSError {
+    ZNSError::Key {
+        message: message.to_string(),
+    }
+}
+
+/// Verifies the SIG(0) the server appended to a response
+/// https://datatracker.ietf.org/doc/html/rfc2931#section-3.1
+pub fn verify_response(
+    key_content: &str,
+    request: &[u8],
+    response: &[u8],
+) -> Result<SigRData, Box<dyn Error>> {
+    let (name, key_rdata) = parse_key_record(key_content)?;
+
+    let (unsigned, sig_rr) = Message::split_last_additional(response)?;
+    if sig_rr._type != Type::Type(RRType::SIG) {
+        Err(key_error("Response is not signed"))?
+    }
+
+    let rdata: Vec<u8> = sig_rr.rdata.into();
+    let mut sig = SigRData::from_bytes(&mut Reader::new(&rdata))?;
+    let signature = std::mem::take(&mut sig.signature);
+
+    if sig.signer != name || sig.key_tag != key_tag(&key_rdata) || sig.algorithm != key_rdata[3] {
+        Err(key_error("Response is signed with another key"))?
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    if (sig.signature_inception as u64) > now || (sig.signature_expiration as u64) < now {
+        Err(key_error("Signature is not valid at this time"))?
+    }
+
+    let mut data = SigRData::to_bytes(sig.clone());
+    data.extend(request);
+    data.extend(unsigned);
+
+    let (algorithm, public_key): (&'static dyn VerificationAlgorithm, Vec<u8>) = match sig.algorithm
+    {
+        15 => (&signature::ED25519, key_rdata[4..].to_vec()),
+        13 | 14 => {
+            let mut point = vec![0x04];
+            point.extend(&key_rdata[4..]);
+            match sig.algorithm {
+                13 => (&signature::ECDSA_P256_SHA256_FIXED, point),
+                _ => (&signature::ECDSA_P384_SHA384_FIXED, point),
+            }
+        }
+        other => Err(key_error(&format!("Unsupported algorithm: {}", other)))?,
+    };
+
+    UnparsedPublicKey::new(algorithm, public_key)
+        .verify(&data, &signature)
+        .map_err(|_| key_error("Signature of the response is invalid"))?;
+
+    sig.signature = signature;
+    Ok(sig)
+}
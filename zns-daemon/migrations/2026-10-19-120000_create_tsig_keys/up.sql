@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE tsig_keys (
+  name TEXT NOT NULL PRIMARY KEY,
+  algorithm TEXT NOT NULL,
+  secret BYTEA NOT NULL,
+  zone TEXT NOT NULL
+)
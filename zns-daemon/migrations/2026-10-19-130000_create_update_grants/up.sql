@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE update_grants (
+  id SERIAL PRIMARY KEY,
+  identity TEXT NOT NULL,
+  rule TEXT NOT NULL,
+  name TEXT NOT NULL DEFAULT '',
+  types TEXT NOT NULL DEFAULT ''
+)
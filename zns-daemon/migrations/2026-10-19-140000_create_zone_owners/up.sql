@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE zone_owners (
+  zone TEXT NOT NULL,
+  kind TEXT NOT NULL,
+  name TEXT NOT NULL,
+  PRIMARY KEY (zone, kind, name)
+)
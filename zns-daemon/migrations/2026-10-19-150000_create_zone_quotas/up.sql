@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE zone_quotas (
+  zone TEXT NOT NULL PRIMARY KEY,
+  max_records INTEGER,
+  max_rdata_bytes INTEGER,
+  max_names INTEGER
+)
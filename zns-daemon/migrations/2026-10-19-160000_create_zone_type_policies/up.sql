@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE zone_type_policies (
+  zone TEXT NOT NULL PRIMARY KEY,
+  allowed TEXT,
+  denied TEXT
+)
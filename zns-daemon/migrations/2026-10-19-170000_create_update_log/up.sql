@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE update_log (
+  id SERIAL PRIMARY KEY,
+  zone TEXT NOT NULL,
+  signer TEXT NOT NULL,
+  key TEXT NOT NULL,
+  client TEXT NOT NULL,
+  time BIGINT NOT NULL,
+  added TEXT NOT NULL DEFAULT '',
+  deleted TEXT NOT NULL DEFAULT ''
+);
+
+CREATE INDEX update_log_zone ON update_log (lower(zone));
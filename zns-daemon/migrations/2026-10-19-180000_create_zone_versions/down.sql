@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE zone_version_records;
+DROP TABLE zone_versions
@@ -0,0 +1,21 @@
+-- Your SQL goes here
+CREATE TABLE zone_versions (
+  zone TEXT NOT NULL,
+  serial BIGINT NOT NULL,
+  time BIGINT NOT NULL,
+  PRIMARY KEY (zone, serial)
+);
+
+CREATE TABLE zone_version_records (
+  zone TEXT NOT NULL,
+  serial BIGINT NOT NULL,
+  name TEXT NOT NULL,
+  type INT NOT NULL,
+  class INT NOT NULL,
+  ttl INT NOT NULL,
+  rdlength INT NOT NULL,
+  rdata BYTEA NOT NULL,
+
+  PRIMARY KEY (zone, serial, name, type, class, rdlength, rdata),
+  FOREIGN KEY (zone, serial) REFERENCES zone_versions (zone, serial) ON DELETE CASCADE
+);
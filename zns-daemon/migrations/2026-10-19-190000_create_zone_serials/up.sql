@@ -0,0 +1,5 @@
+-- Your SQL goes here
+CREATE TABLE zone_serials (
+  zone TEXT PRIMARY KEY,
+  serial BIGINT NOT NULL
+);
@@ -0,0 +1,333 @@
+use std::{
+    fmt::Display,
+    net::IpAddr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use diesel::PgConnection;
+use zns::{
+    errors::ZNSError,
+    labelstring::LabelString,
+    structs::{Class, Message, RR},
+};
+
+use crate::{
+    auth::Signer,
+    db::models::{get_update_log, insert_update_log, UpdateLogRecord},
+};
+
+/// Applied update of a zone
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogEntry {
+    pub zone: String,
+    /// Signer name of the SIG(0) or name of the TSIG key
+    pub signer: String,
+    pub key: String,
+    pub client: String,
+    /// Unix timestamp in seconds
+    pub time: u64,
+    pub added: Vec<String>,
+    pub deleted: Vec<String>,
+}
+
+impl From<UpdateLogRecord> for LogEntry {
+    fn from(record: UpdateLogRecord) -> Self {
+        let lines = |records: &str| records.lines().map(str::to_string).collect();
+        LogEntry {
+            zone: record.zone,
+            signer: record.signer,
+            key: record.key,
+            client: record.client,
+            time: record.time.max(0) as u64,
+            added: lines(&record.added),
+            deleted: lines(&record.deleted),
+        }
+    }
+}
+
+impl Display for LogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} by {} ({}) from {}",
+            format_time(self.time),
+            self.zone,
+            self.signer,
+            self.key,
+            self.client
+        )?;
+        for record in &self.deleted {
+            write!(f, "\n  - {}", record)?;
+        }
+        for record in &self.added {
+            write!(f, "\n  + {}", record)?;
+        }
+        Ok(())
+    }
+}
+
+/// Number of changed records in the TXT record of an update, the others are only counted
+const TXT_RECORDS: usize = 8;
+
+impl LogEntry {
+    /// RDATA of a TXT record with a character-string per field and per record, up to
+    /// `TXT_RECORDS` records. Strings longer than 255 octets are truncated.
+    pub fn to_txt(&self) -> Vec<u8> {
+        let records = self
+            .deleted
+            .iter()
+            .map(|record| format!("-{}", record))
+            .chain(self.added.iter().map(|record| format!("+{}", record)));
+        let hidden = (self.deleted.len() + self.added.len()).saturating_sub(TXT_RECORDS);
+        let strings = [
+            format!("time={}", format_time(self.time)),
+            format!("zone={}", self.zone),
+            format!("signer={}", self.signer),
+            format!("key={}", self.key),
+            format!("client={}", self.client),
+        ]
+        .into_iter()
+        .chain(records.take(TXT_RECORDS))
+        .chain((hidden > 0).then(|| format!("{} more records", hidden)));
+
+        let mut rdata = vec![];
+        for string in strings {
+            let bytes = &string.as_bytes()[..string.len().min(255)];
+            rdata.push(bytes.len() as u8);
+            rdata.extend(bytes);
+        }
+        rdata
+    }
+}
+
+/// Record in the generic presentation format
+/// https://datatracker.ietf.org/doc/html/rfc3597#section-5
+pub fn format_record(rr: &RR) -> String {
+    let rdata: Vec<u8> = rr.rdata.clone().into();
+    let hex: String = rdata.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{} {} {} {} \\# {} {}",
+        rr.name,
+        rr.ttl,
+        rr.class,
+        rr._type,
+        rdata.len(),
+        hex
+    )
+    .trim_end()
+    .to_string()
+}
+
+/// UTC time of a unix timestamp, e.g. `2024-03-03 22:04:59`
+pub fn format_time(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+
+    // Civil date from the number of days since 1970-01-01
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Records the update of the zone, which is signed by the signer
+pub fn log_update(
+    message: &Message,
+    zone: &LabelString,
+    zone_class: &Class,
+    signer: &Signer,
+    client: IpAddr,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    let (added, deleted): (Vec<&RR>, Vec<&RR>) = message
+        .authority
+        .iter()
+        .partition(|rr| &rr.class == zone_class);
+    log_changes(zone, &added, &deleted, signer, client, connection)
+}
+
+/// Records the records which the signer added to and deleted from the zone
+pub fn log_changes(
+    zone: &LabelString,
+    added: &[&RR],
+    deleted: &[&RR],
+    signer: &Signer,
+    client: IpAddr,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    let lines = |records: &[&RR]| {
+        records
+            .iter()
+            .map(|rr| format_record(rr))
+            .collect::<Vec<String>>()
+            .join("\n")
+    };
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })?
+        .as_secs();
+
+    insert_update_log(
+        &UpdateLogRecord {
+            zone: zone.to_string(),
+            signer: signer.name.to_string(),
+            key: signer.key.clone(),
+            client: client.to_string(),
+            time: time as i64,
+            added: lines(added),
+            deleted: lines(deleted),
+        },
+        connection,
+    )
+}
+
+/// Latest updates of the zone and all zones below it, newest first
+pub fn get_log(
+    zone: &LabelString,
+    limit: u32,
+    connection: &mut PgConnection,
+) -> Result<Vec<LogEntry>, ZNSError> {
+    Ok(get_update_log(zone, limit as i64, connection)?
+        .into_iter()
+        .map(LogEntry::from)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use zns::{
+        structs::{RData, RRClass, RRType, Type},
+        test_utils::{get_message, get_rr},
+    };
+
+    use crate::{config::Config, db::lib::tests::get_test_connection};
+
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format_time(0), "1970-01-01 00:00:00");
+        assert_eq!(format_time(1709503499), "2024-03-03 22:04:59");
+        assert_eq!(format_time(951782400), "2000-02-29 00:00:00");
+
+        let rr = RR {
+            name: LabelString::from("bob.user.zeus.gent"),
+            _type: Type::Type(RRType::A),
+            class: Class::Class(RRClass::IN),
+            ttl: 300,
+            rdlength: 4,
+            rdata: RData::Vec(vec![10, 0, 0, 1]),
+        };
+        assert_eq!(
+            format_record(&rr),
+            "bob.user.zeus.gent 300 IN A \\# 4 0a000001"
+        );
+
+        let delete = RR {
+            class: Class::Class(RRClass::ANY),
+            ttl: 0,
+            rdlength: 0,
+            rdata: RData::Vec(vec![]),
+            ..rr
+        };
+        assert_eq!(format_record(&delete), "bob.user.zeus.gent 0 ANY A \\# 0");
+    }
+
+    #[test]
+    fn test_log_update() {
+        let mut connection = get_test_connection();
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let home = zone.prepend("home".to_string());
+        let signer = Signer {
+            name: zone.clone(),
+            key: String::from("dnskey 1234"),
+        };
+
+        let mut message = get_message(Some(home.clone()));
+        message.authority = vec![
+            RR {
+                name: home.clone(),
+                ..get_rr(None)
+            },
+            RR {
+                name: home.clone(),
+                class: Class::Class(RRClass::NONE),
+                ttl: 0,
+                ..get_rr(None)
+            },
+        ];
+        let client = IpAddr::from([10, 0, 0, 1]);
+        log_update(
+            &message,
+            &home,
+            &Class::Class(RRClass::IN),
+            &signer,
+            client,
+            &mut connection,
+        )
+        .unwrap();
+
+        let log = get_log(&zone, 10, &mut connection).unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].zone, home.to_string());
+        assert_eq!(log[0].key, "dnskey 1234");
+        assert_eq!(log[0].client, "10.0.0.1");
+        assert_eq!(log[0].added.len(), 1);
+        assert_eq!(log[0].deleted.len(), 1);
+        assert!(log[0].deleted[0].contains(" NONE "));
+
+        let txt = log[0].to_txt();
+        assert_eq!(txt[0] as usize, "time=".len() + 19);
+
+        // Only the first records of a large update are in the TXT record
+        let large = LogEntry {
+            added: vec![String::from("x").repeat(300); 1000],
+            ..log[0].clone()
+        };
+        let txt = large.to_txt();
+        assert!(txt.len() < 5000);
+        assert!(txt.ends_with(b"993 more records"));
+        assert!(
+            get_log(&home.prepend("www".to_string()), 10, &mut connection)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(get_log(
+            &Config::get()
+                .authoritative_zone
+                .prepend("alice".to_string()),
+            10,
+            &mut connection
+        )
+        .unwrap()
+        .is_empty());
+
+        // Wildcards of LIKE in the zone are matched literally
+        assert!(get_log(
+            &Config::get().authoritative_zone.prepend("bo_".to_string()),
+            10,
+            &mut connection
+        )
+        .unwrap()
+        .is_empty());
+    }
+}
@@ -1,10 +1,23 @@
-use zns::{errors::ZNSError, parser::FromBytes, reader::Reader};
+use zns::{
+    errors::ZNSError,
+    parser::{FromBytes, ToBytes},
+    reader::Reader,
+    structs::{RRType, Type},
+};
 
 use super::sig::Algorithm;
 
+// https://datatracker.ietf.org/doc/html/rfc2535#section-3.1.2
+const KEY_NO_AUTH: u16 = 0x8000;
+
+/// Flags of the KEY/DNSKEY records generated by zns-cli
+pub const DEFAULT_FLAGS: u16 = 256;
+pub const PROTOCOL_DNSSEC: u8 = 3;
+const PROTOCOL_ANY: u8 = 255;
+
 /// https://datatracker.ietf.org/doc/html/rfc4034#section-2
+/// KEY records (https://datatracker.ietf.org/doc/html/rfc2535#section-3.1) share the same format.
 #[derive(Debug)]
-#[allow(dead_code)]
 pub struct DNSKeyRData {
     pub flags: u16,
     pub protocol: u8,
@@ -23,3 +36,52 @@ impl FromBytes for DNSKeyRData {
         })
     }
 }
+
+impl ToBytes for DNSKeyRData {
+    fn to_bytes(rdata: Self) -> Vec<u8> {
+        let mut result = u16::to_be_bytes(rdata.flags).to_vec();
+        result.push(rdata.protocol);
+        result.push(rdata.algorithm as u8);
+        result.extend(rdata.public_key);
+        result
+    }
+}
+
+impl DNSKeyRData {
+    /// Checks if the key of a DNSKEY or KEY record may be used to validate a SIG(0)
+    pub fn allows_authentication(&self, rr_type: &Type) -> bool {
+        match rr_type {
+            Type::Type(RRType::DNSKEY) => self.protocol == PROTOCOL_DNSSEC,
+            Type::Type(RRType::KEY) => {
+                [PROTOCOL_DNSSEC, PROTOCOL_ANY].contains(&self.protocol)
+                    && self.flags & KEY_NO_AUTH == 0
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allows_authentication() {
+        let key = |flags: u16, protocol: u8| DNSKeyRData {
+            flags,
+            protocol,
+            algorithm: Algorithm::ED25519,
+            public_key: vec![],
+        };
+
+        let dnskey = Type::Type(RRType::DNSKEY);
+        let keytype = Type::Type(RRType::KEY);
+
+        assert!(key(256, 3).allows_authentication(&dnskey));
+        assert!(!key(256, 255).allows_authentication(&dnskey));
+        assert!(key(256, 3).allows_authentication(&keytype));
+        assert!(key(512, 255).allows_authentication(&keytype));
+        assert!(!key(KEY_NO_AUTH | 256, 3).allows_authentication(&keytype));
+        assert!(!key(256, 3).allows_authentication(&Type::Type(RRType::A)));
+    }
+}
@@ -1,11 +1,16 @@
 use diesel::PgConnection;
 use dnskey::DNSKeyRData;
-use reqwest::header::ACCEPT;
+use owners::{zone_owners, Owner};
+use providers::{KeyProvider, Provider};
+use pubkeys::ssh_fingerprint;
+use replay::ReplayCache;
 use sig::Sig;
+use tsig::{has_tsig, verify_tsig};
 
 use crate::{config::Config, db::models::get_from_database};
 
 use zns::{
+    dnssec::key_tag,
     errors::ZNSError,
     labelstring::LabelString,
     parser::FromBytes,
@@ -13,47 +18,130 @@ use zns::{
     structs::{Class, Message, RRClass, RRType, Type},
 };
 
-mod dnskey;
-mod pubkeys;
-mod sig;
+pub mod dnskey;
+pub mod owners;
+mod providers;
+pub mod pubkeys;
+mod replay;
+mod server_key;
+pub mod sig;
+mod transfer;
+mod tsig;
 
+pub use providers::ProviderConfig;
+pub use server_key::ServerKey;
+pub use transfer::{transfer_allowed, TransferRule};
+pub use tsig::{add_tsig_key, list_tsig_keys, remove_tsig_key, RequestTsig};
+
+/// Key which signed a request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signer {
+    /// Signer name of the SIG(0) or name of the TSIG key
+    pub name: LabelString,
+    /// Identity of the key: the username and SSH fingerprint, the DNSKEY tag or `tsig`
+    pub key: String,
+}
+
+/// Verifies the SIG(0) or TSIG of a request for the zone.
+/// Returns the key which signed the request, or `None` if the signature is not valid.
 pub async fn verify_authorization(
     message: &Message,
     zone: &LabelString,
     raw: &[u8],
     connection: &mut PgConnection,
-) -> Result<bool, ZNSError> {
+) -> Result<Option<Signer>, ZNSError> {
+    if has_tsig(message) {
+        return if zone.len() > Config::get().authoritative_zone.len() {
+            verify_tsig(zone, raw, connection).map(|name| {
+                Some(Signer {
+                    name,
+                    key: String::from("tsig"),
+                })
+            })
+        } else {
+            Err(ZNSError::NotAuth {
+                message: String::from("Invalid zone"),
+            })
+        };
+    }
+
     let sig = message
         .additional
         .last()
         .filter(|rr| rr._type == Type::Type(RRType::SIG))
         .map_or(
             Err(ZNSError::Refused {
-                message: "No KEY record found at the end of additional section".to_string(),
+                message: "No SIG or TSIG record found at the end of additional section".to_string(),
             }),
-            |rr| Sig::new(rr, raw),
+            |_| Sig::new(raw),
         )?;
 
+    sig.check_validity()?;
+
+    let owners = zone_owners(zone, connection)?;
+
+    // The signer must be the zone itself, a name in the zone with its own KEY/DNSKEY record
+    // or a key which owns the zone
+    if !sig.signer().is_subdomain_of(zone) && !owners.contains(&Owner::Key(sig.signer().clone())) {
+        return Err(ZNSError::Refused {
+            message: format!(
+                "Signer {} is not authorized for zone {}",
+                sig.signer(),
+                zone
+            ),
+        });
+    }
+
+    // A key in a subzone which has other owners than the zone itself, e.g. a subzone
+    // handed to a friend, must not be able to update the zone
+    if sig.signer() != zone
+        && sig.signer().is_subdomain_of(zone)
+        && !owners.contains(&Owner::Key(sig.signer().clone()))
+        && zone_owners(sig.signer(), connection)?
+            .iter()
+            .any(|owner| !owners.contains(owner))
+    {
+        return Err(ZNSError::Refused {
+            message: format!(
+                "Signer {} has other owners than zone {}",
+                sig.signer(),
+                zone
+            ),
+        });
+    }
+
     if zone.len() > Config::get().authoritative_zone.len() {
-        let ssh_verified = match &Config::get().zauth_url {
-            Some(url) => {
-                let username = &zone.as_slice()
-                    [zone.as_slice().len() - Config::get().authoritative_zone.as_slice().len() - 1];
-
-                validate_ssh(&username.to_lowercase(), url, &sig)
-                    .await
-                    .map_err(|e| ZNSError::Servfail {
-                        message: e.to_string(),
-                    })?
-            }
-            None => false,
-        };
+        // Keys of the key providers are only valid for the zones of the user itself
+        let ssh_key = if sig.signer() == zone {
+            let usernames: Vec<&str> = owners
+                .iter()
+                .filter_map(|owner| match owner {
+                    Owner::User(username) => Some(username.as_str()),
+                    Owner::Key(_) => None,
+                })
+                .collect();
 
-        if ssh_verified {
-            Ok(true)
+            validate_ssh(&usernames, &sig).await?
         } else {
-            Ok(validate_dnskey(zone, &sig, connection).await?)
+            None
+        };
+
+        let key = match ssh_key {
+            Some(key) => Some(key),
+            None => validate_dnskey(sig.signer(), &sig, connection)
+                .await?
+                .then(|| format!("dnskey {}", sig.key_tag())),
+        };
+
+        // Reject replays of previously accepted messages
+        if key.is_some() {
+            ReplayCache::get().insert(sig.signed_data(), sig.expiration())?;
         }
+
+        Ok(key.map(|key| Signer {
+            name: sig.signer().clone(),
+            key,
+        }))
     } else {
         Err(ZNSError::NotAuth {
             message: String::from("Invalid zone"),
@@ -61,50 +149,188 @@ pub async fn verify_authorization(
     }
 }
 
-async fn validate_ssh(
-    username: &String,
-    zauth_url: &String,
-    sig: &Sig,
-) -> Result<bool, reqwest::Error> {
-    let client = reqwest::Client::new();
-    Ok(client
-        .get(format!("{}/users/{}/keys", zauth_url, username))
-        .header(ACCEPT, "application/json")
-        .send()
-        .await?
-        .json::<Vec<String>>()
-        .await?
-        .iter()
-        .any(|key| match sig.verify_ssh(key) {
-            Ok(value) => value,
-            Err(e) => {
-                eprintln!("{}", e);
-                false
+/// Tries the configured key providers in order, until one of them has a key of one of the users
+/// which validates the signature. Returns the username and fingerprint of that key.
+async fn validate_ssh(usernames: &[&str], sig: &Sig) -> Result<Option<String>, ZNSError> {
+    let mut error = None;
+
+    for provider in Provider::all() {
+        for username in usernames {
+            match provider.keys(username).await {
+                Ok(keys) => {
+                    if let Some(key) = keys.iter().find(|key| match sig.verify_ssh(key) {
+                        Ok(value) => value,
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            false
+                        }
+                    }) {
+                        let fingerprint = ssh_fingerprint(key).unwrap_or_default();
+                        return Ok(Some(format!("ssh {} {}", username, fingerprint)));
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    error = Some(e);
+                }
             }
-        }))
+        }
+    }
+
+    // Only fail if no provider could validate the signature because of an error
+    error.map_or(Ok(None), Err)
 }
 
 async fn validate_dnskey(
-    zone: &LabelString,
+    signer: &LabelString,
     sig: &Sig,
     connection: &mut PgConnection,
 ) -> Result<bool, ZNSError> {
-    Ok(get_from_database(
-        zone,
+    let mut keys = get_from_database(
+        signer,
         Some(Type::Type(RRType::DNSKEY)),
         Class::Class(RRClass::IN),
         connection,
-    )?
-    .iter()
-    .any(|rr| {
+    )?;
+    keys.extend(get_from_database(
+        signer,
+        Some(Type::Type(RRType::KEY)),
+        Class::Class(RRClass::IN),
+        connection,
+    )?);
+
+    Ok(keys.iter().any(|rr| {
         let data: Vec<u8> = rr.rdata.clone().into();
+        if key_tag(&data) != sig.key_tag() {
+            return false;
+        }
+
         let mut reader = Reader::new(&data);
-        DNSKeyRData::from_bytes(&mut reader).is_ok_and(|dnskey| match sig.verify_dnskey(dnskey) {
-            Ok(value) => value,
-            Err(e) => {
-                eprintln!("{}", e);
-                false
-            }
+        DNSKeyRData::from_bytes(&mut reader).is_ok_and(|dnskey| {
+            dnskey.allows_authentication(&rr._type)
+                && match sig.verify_dnskey(dnskey) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        false
+                    }
+                }
         })
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use ring::signature::Ed25519KeyPair;
+    use zns::{
+        structs::{RData, RR},
+        test_utils::get_message,
+    };
+
+    use crate::db::{lib::tests::get_test_connection, models::insert_into_database};
+
+    use super::sig::tests::{get_key_rdata, get_keypair, get_sig, sign_message};
+    use super::*;
+
+    fn get_key_rr(name: &LabelString, rdata: Vec<u8>) -> RR {
+        RR {
+            name: name.clone(),
+            _type: Type::Type(RRType::KEY),
+            class: Class::Class(RRClass::IN),
+            ttl: 300,
+            rdlength: rdata.len() as u16,
+            rdata: RData::Vec(rdata),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_dnskey() {
+        let mut connection = get_test_connection();
+        let keypair = get_keypair();
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let signer = zone.prepend("router".to_string());
+
+        let rdata = get_key_rdata(&keypair);
+        let tag = key_tag(&rdata);
+        assert!(insert_into_database(&get_key_rr(&signer, rdata), &mut connection).is_ok());
+
+        let sig = get_sig(&sign_message(get_message(None), &keypair, &signer, tag)).unwrap();
+        assert!(validate_dnskey(&signer, &sig, &mut connection)
+            .await
+            .unwrap());
+        assert!(!validate_dnskey(&zone, &sig, &mut connection).await.unwrap());
+
+        // Key tag does not match any key
+        let sig = get_sig(&sign_message(get_message(None), &keypair, &signer, tag ^ 1)).unwrap();
+        assert!(!validate_dnskey(&signer, &sig, &mut connection)
+            .await
+            .unwrap());
+
+        let other = Ed25519KeyPair::from_seed_unchecked(&[8; 32]).unwrap();
+        let sig = get_sig(&sign_message(get_message(None), &other, &signer, tag)).unwrap();
+        assert!(!validate_dnskey(&signer, &sig, &mut connection)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_signer_outside_zone() {
+        let mut connection = get_test_connection();
+        let keypair = get_keypair();
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let signer = Config::get()
+            .authoritative_zone
+            .prepend("alice".to_string());
+
+        let rdata = get_key_rdata(&keypair);
+        let tag = key_tag(&rdata);
+        assert!(insert_into_database(&get_key_rr(&signer, rdata), &mut connection).is_ok());
+
+        let message = get_message(Some(zone.clone()));
+        let datagram = sign_message(message, &keypair, &signer, tag);
+        let message = Message::from_bytes(&mut Reader::new(&datagram)).unwrap();
+
+        assert!(matches!(
+            verify_authorization(&message, &zone, &datagram, &mut connection).await,
+            Err(ZNSError::Refused { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_signer_in_granted_subzone() {
+        let mut connection = get_test_connection();
+        let keypair = get_keypair();
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let friend = zone.prepend("friend".to_string());
+
+        let rdata = get_key_rdata(&keypair);
+        let tag = key_tag(&rdata);
+        assert!(insert_into_database(&get_key_rr(&friend, rdata), &mut connection).is_ok());
+
+        let message = get_message(Some(zone.clone()));
+        let datagram = sign_message(message, &keypair, &friend, tag);
+        let message = Message::from_bytes(&mut Reader::new(&datagram)).unwrap();
+
+        // Without other owners, a key in the zone may update it
+        assert!(
+            verify_authorization(&message, &zone, &datagram, &mut connection)
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        // Once the subzone is handed to a friend, its keys can no longer update the zone
+        owners::add_zone_owner(
+            &friend,
+            &Owner::User(String::from("alice")),
+            &mut connection,
+        )
+        .unwrap();
+        let datagram = sign_message(get_message(Some(zone.clone())), &keypair, &friend, tag);
+        let message = Message::from_bytes(&mut Reader::new(&datagram)).unwrap();
+        assert!(matches!(
+            verify_authorization(&message, &zone, &datagram, &mut connection).await,
+            Err(ZNSError::Refused { .. })
+        ));
+    }
+}
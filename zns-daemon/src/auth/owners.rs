@@ -0,0 +1,263 @@
+use std::{fmt::Display, str::FromStr};
+
+use diesel::PgConnection;
+use zns::{errors::ZNSError, labelstring::LabelString};
+
+use crate::{
+    config::Config,
+    db::models::{delete_zone_owner, get_zone_owners, insert_zone_owner, ZoneOwnerRecord},
+};
+
+const USER: &str = "user";
+const KEY: &str = "key";
+
+/// Owner of a zone, who is allowed to update the zone and all zones below it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Owner {
+    /// User of which the keys are provided by the key providers (e.g. Zauth)
+    User(String),
+    /// Name of a TSIG key or the signer name of a KEY/DNSKEY record
+    Key(LabelString),
+}
+
+impl FromStr for Owner {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().split_once(':') {
+            Some((USER, name)) if !name.is_empty() => Ok(Owner::User(name.to_lowercase())),
+            Some((KEY, name)) if !name.is_empty() => {
+                Ok(Owner::Key(LabelString::from(name.trim_end_matches('.'))))
+            }
+            _ => Err(format!(
+                "owner must be `user:<username>` or `key:<key name>`: {}",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for Owner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Owner::User(name) => write!(f, "{}:{}", USER, name),
+            Owner::Key(name) => write!(f, "{}:{}", KEY, name),
+        }
+    }
+}
+
+impl Owner {
+    fn to_record(&self, zone: &LabelString) -> ZoneOwnerRecord {
+        let (kind, name) = match self {
+            Owner::User(name) => (USER, name.clone()),
+            Owner::Key(name) => (KEY, name.to_string()),
+        };
+        ZoneOwnerRecord {
+            zone: zone.to_string(),
+            kind: kind.to_string(),
+            name,
+        }
+    }
+}
+
+/// Zone directly below the authoritative zone which contains the given zone, e.g. `bob.user.zeus.gent`
+pub fn user_zone(zone: &LabelString) -> Option<LabelString> {
+    let authoritative_zone = &Config::get().authoritative_zone;
+    (zone.len() > authoritative_zone.len() && zone.is_subdomain_of(authoritative_zone))
+        .then(|| zone.as_slice()[zone.len() - authoritative_zone.len() - 1..].into())
+}
+
+/// Owners of the zone: the owners in the database of the zone and all its parents,
+/// and the user after whom the zone below the authoritative zone is named.
+/// Shared zones, which have owners in the database for the zone below the authoritative zone,
+/// are not owned by the user with the same name.
+pub fn zone_owners(
+    zone: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<Vec<Owner>, ZNSError> {
+    let Some(user_zone) = user_zone(zone) else {
+        return Ok(vec![]);
+    };
+
+    let zones: Vec<LabelString> = (0..=zone.len() - user_zone.len())
+        .map(|i| zone.as_slice()[i..].into())
+        .collect();
+
+    let records = get_zone_owners(&zones, connection)?;
+
+    let mut owners = vec![];
+    if !records
+        .iter()
+        .any(|record| LabelString::from(&record.zone) == user_zone)
+    {
+        owners.push(Owner::User(user_zone.as_slice()[0].to_lowercase()));
+    }
+
+    for record in records {
+        let owner = match record.kind.as_str() {
+            USER => Owner::User(record.name.to_lowercase()),
+            KEY => Owner::Key(LabelString::from(record.name.trim_end_matches('.'))),
+            kind => {
+                return Err(ZNSError::Servfail {
+                    message: format!("Invalid owner kind for {}: {}", record.zone, kind),
+                })
+            }
+        };
+        if !owners.contains(&owner) {
+            owners.push(owner);
+        }
+    }
+
+    Ok(owners)
+}
+
+fn check_zone(zone: &LabelString) -> Result<(), ZNSError> {
+    match user_zone(zone) {
+        Some(_) => Ok(()),
+        None => Err(ZNSError::Refused {
+            message: format!(
+                "Zone {} is not below {}",
+                zone,
+                Config::get().authoritative_zone
+            ),
+        }),
+    }
+}
+
+/// Creates a shared zone, which is only owned by the given owners
+pub fn create_shared_zone(
+    zone: &LabelString,
+    owners: &[Owner],
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    check_zone(zone)?;
+    if owners.is_empty() {
+        return Err(ZNSError::Refused {
+            message: String::from("A shared zone needs at least one owner"),
+        });
+    }
+    if !get_zone_owners(std::slice::from_ref(zone), connection)?.is_empty() {
+        return Err(ZNSError::Refused {
+            message: format!("Zone {} already has owners", zone),
+        });
+    }
+
+    owners
+        .iter()
+        .try_for_each(|owner| insert_zone_owner(&owner.to_record(zone), connection))
+}
+
+/// Allows the owner to update the zone and all zones below it.
+/// The first owner added to the zone of a user is stored together with the user, who would
+/// otherwise lose the zone as it becomes a shared zone.
+pub fn add_zone_owner(
+    zone: &LabelString,
+    owner: &Owner,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    check_zone(zone)?;
+    if user_zone(zone).as_ref() == Some(zone)
+        && get_zone_owners(std::slice::from_ref(zone), connection)?.is_empty()
+    {
+        let user = Owner::User(zone.as_slice()[0].to_lowercase());
+        if &user != owner {
+            insert_zone_owner(&user.to_record(zone), connection)?;
+        }
+    }
+    insert_zone_owner(&owner.to_record(zone), connection)
+}
+
+/// Removes an owner of the zone, owners of parent zones are not affected
+pub fn remove_zone_owner(
+    zone: &LabelString,
+    owner: &Owner,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    match delete_zone_owner(&owner.to_record(zone), connection)? {
+        0 => Err(ZNSError::Refused {
+            message: format!("{} is not an owner of {}", owner, zone),
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::lib::tests::get_test_connection;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_owner() {
+        assert_eq!("user:Bob".parse(), Ok(Owner::User(String::from("bob"))));
+        assert_eq!(
+            "key:router.bob.user.zeus.gent.".parse(),
+            Ok(Owner::Key(LabelString::from("router.bob.user.zeus.gent")))
+        );
+        assert!("user:".parse::<Owner>().is_err());
+        assert!("bob".parse::<Owner>().is_err());
+    }
+
+    #[test]
+    fn test_zone_owners() {
+        let mut connection = get_test_connection();
+        let bob = Config::get().authoritative_zone.prepend("bob".to_string());
+        let friend = bob.prepend("friend".to_string());
+        let alice = Owner::User(String::from("alice"));
+
+        assert_eq!(
+            zone_owners(&friend, &mut connection).unwrap(),
+            vec![Owner::User(String::from("bob"))]
+        );
+        assert!(
+            zone_owners(&Config::get().authoritative_zone, &mut connection)
+                .unwrap()
+                .is_empty()
+        );
+
+        // Subzone handed to a friend
+        add_zone_owner(&friend, &alice, &mut connection).unwrap();
+        assert_eq!(
+            zone_owners(&friend.prepend("www".to_string()), &mut connection).unwrap(),
+            vec![Owner::User(String::from("bob")), alice.clone()]
+        );
+        assert_eq!(
+            zone_owners(&bob, &mut connection).unwrap(),
+            vec![Owner::User(String::from("bob"))]
+        );
+
+        remove_zone_owner(&friend, &alice, &mut connection).unwrap();
+        assert!(remove_zone_owner(&friend, &alice, &mut connection).is_err());
+
+        // Owners added to the zone of a user do not replace the user
+        add_zone_owner(&bob, &alice, &mut connection).unwrap();
+        assert_eq!(
+            zone_owners(&friend, &mut connection).unwrap(),
+            vec![Owner::User(String::from("bob")), alice.clone()]
+        );
+    }
+
+    #[test]
+    fn test_shared_zone() {
+        let mut connection = get_test_connection();
+        let project = Config::get()
+            .authoritative_zone
+            .prepend("project".to_string());
+        let owners = vec![
+            Owner::User(String::from("alice")),
+            Owner::Key(project.prepend("ci".to_string())),
+        ];
+
+        assert!(create_shared_zone(&project, &[], &mut connection).is_err());
+        assert!(
+            create_shared_zone(&Config::get().authoritative_zone, &owners, &mut connection)
+                .is_err()
+        );
+
+        create_shared_zone(&project, &owners, &mut connection).unwrap();
+        let result = zone_owners(&project, &mut connection).unwrap();
+        assert_eq!(result.len(), owners.len());
+        assert!(owners.iter().all(|owner| result.contains(owner)));
+        assert!(create_shared_zone(&project, &owners, &mut connection).is_err());
+    }
+}
@@ -0,0 +1,57 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use tokio::fs;
+use zns::errors::ZNSError;
+
+use super::{parse_authorized_keys, KeyProvider};
+
+/// Directory with a file per user in the `authorized_keys` format: `<directory>/<username>`
+pub struct DirectoryKeys {
+    path: PathBuf,
+}
+
+impl DirectoryKeys {
+    pub fn new(path: &Path) -> Self {
+        DirectoryKeys {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl KeyProvider for DirectoryKeys {
+    async fn keys(&self, username: &str) -> Result<Vec<String>, ZNSError> {
+        match fs::read_to_string(self.path.join(username)).await {
+            Ok(content) => Ok(parse_authorized_keys(&content)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(vec![]),
+            Err(e) => Err(ZNSError::Servfail {
+                message: format!("Could not read keys of {}: {}", username, e),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_keys() {
+        let path = env::temp_dir().join("zns-directory-keys");
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("bob"), "ssh-ed25519 AAAA bob@laptop\n").unwrap();
+
+        let provider = DirectoryKeys::new(&path);
+        assert_eq!(
+            provider.keys("bob").await.unwrap(),
+            vec!["ssh-ed25519 AAAA"]
+        );
+        assert!(provider.keys("alice").await.unwrap().is_empty());
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -0,0 +1,61 @@
+use std::path::{Path, PathBuf};
+
+use tokio::fs;
+use zns::errors::ZNSError;
+
+use super::{parse_authorized_keys, KeyProvider};
+
+/// File with lines of the format `<username> <authorized_keys line>`
+pub struct FileKeys {
+    path: PathBuf,
+}
+
+impl FileKeys {
+    pub fn new(path: &Path) -> Self {
+        FileKeys {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+fn user_keys(content: &str, username: &str) -> Vec<String> {
+    let lines: Vec<&str> = content
+        .lines()
+        .filter_map(|line| line.trim().split_once(char::is_whitespace))
+        .filter(|(user, _)| user.eq_ignore_ascii_case(username))
+        .map(|(_, key)| key)
+        .collect();
+
+    parse_authorized_keys(&lines.join("\n"))
+}
+
+impl KeyProvider for FileKeys {
+    async fn keys(&self, username: &str) -> Result<Vec<String>, ZNSError> {
+        fs::read_to_string(&self.path)
+            .await
+            .map(|content| user_keys(&content, username))
+            .map_err(|e| ZNSError::Servfail {
+                message: format!("Could not read {}: {}", self.path.display(), e),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_keys() {
+        let content = "# Keys of all users\n\
+                       bob ssh-ed25519 AAAA bob@laptop\n\
+                       alice ssh-rsa BBBB\n\
+                       Bob ecdsa-sha2-nistp256 CCCC\n";
+
+        assert_eq!(
+            user_keys(content, "bob"),
+            vec!["ssh-ed25519 AAAA", "ecdsa-sha2-nistp256 CCCC"]
+        );
+        assert_eq!(user_keys(content, "alice"), vec!["ssh-rsa BBBB"]);
+        assert!(user_keys(content, "eve").is_empty());
+    }
+}
@@ -0,0 +1,324 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use reqwest::{header::ACCEPT, StatusCode};
+use zns::errors::ZNSError;
+
+use super::{parse_authorized_keys, KeyProvider, USER_PLACEHOLDER};
+
+/// Format of the keys returned by the endpoint
+#[derive(Clone)]
+pub enum Format {
+    /// JSON list of keys, as returned by Zauth
+    Json,
+    /// One key per line, like `https://github.com/<user>.keys`
+    AuthorizedKeys,
+}
+
+impl Format {
+    /// Media type requested from the endpoint
+    fn accept(&self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::AuthorizedKeys => "text/plain",
+        }
+    }
+}
+
+/// Fetches keys of users from an HTTP endpoint and caches them
+#[derive(Clone)]
+pub struct HttpKeys {
+    client: reqwest::Client,
+    url: String,
+    format: Format,
+    cache: Arc<Mutex<HashMap<String, CacheEntry>>>,
+    ttl: Duration,
+    negative_ttl: Duration,
+    stale_ttl: Duration,
+}
+
+struct CacheEntry {
+    /// None if the user does not exist
+    keys: Option<Vec<String>>,
+    fetched: Instant,
+    refreshing: bool,
+}
+
+fn http_error(e: reqwest::Error) -> ZNSError {
+    ZNSError::Servfail {
+        message: format!("Key provider: {}", e),
+    }
+}
+
+impl HttpKeys {
+    /// `url` should contain `{user}`, which is replaced by the username
+    pub fn new(
+        url: &str,
+        format: Format,
+        timeout: Duration,
+        ttl: Duration,
+        negative_ttl: Duration,
+        stale_ttl: Duration,
+    ) -> Self {
+        HttpKeys {
+            client: reqwest::Client::builder()
+                .connect_timeout(timeout)
+                .timeout(timeout)
+                .build()
+                .expect("Could not create HTTP client"),
+            url: url.to_string(),
+            format,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            ttl,
+            negative_ttl,
+            stale_ttl,
+        }
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, CacheEntry>>, ZNSError> {
+        self.cache.lock().map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })
+    }
+
+    /// Fetches the keys of a user and caches them, errors are not cached
+    async fn fetch(&self, username: &str) -> Result<Vec<String>, ZNSError> {
+        let response = self
+            .client
+            .get(self.url.replace(USER_PLACEHOLDER, username))
+            .header(ACCEPT, self.format.accept())
+            .send()
+            .await
+            .map_err(http_error)?;
+
+        let keys = if response.status() == StatusCode::NOT_FOUND {
+            None
+        } else {
+            let response = response.error_for_status().map_err(http_error)?;
+            Some(match self.format {
+                Format::Json => response.json::<Vec<String>>().await.map_err(http_error)?,
+                Format::AuthorizedKeys => {
+                    parse_authorized_keys(&response.text().await.map_err(http_error)?)
+                }
+            })
+        };
+
+        let max_age = (self.ttl + self.stale_ttl).max(self.negative_ttl);
+        let mut cache = self.lock()?;
+        cache.retain(|_, entry| entry.fetched.elapsed() < max_age);
+        cache.insert(
+            username.to_string(),
+            CacheEntry {
+                keys: keys.clone(),
+                fetched: Instant::now(),
+                refreshing: false,
+            },
+        );
+
+        Ok(keys.unwrap_or_default())
+    }
+}
+
+impl KeyProvider for HttpKeys {
+    /// Keys are served from the cache if possible.
+    /// Expired keys are still used for `stale_ttl` while they are refreshed in the background,
+    /// so authentication keeps working during short outages of the endpoint.
+    async fn keys(&self, username: &str) -> Result<Vec<String>, ZNSError> {
+        let cached = self.lock()?.get_mut(username).and_then(|entry| {
+            let age = entry.fetched.elapsed();
+            match &entry.keys {
+                Some(keys) if age < self.ttl => Some((keys.clone(), false)),
+                None if age < self.negative_ttl => Some((vec![], false)),
+                Some(keys) if age < self.ttl + self.stale_ttl => {
+                    let refresh = !entry.refreshing;
+                    entry.refreshing = true;
+                    Some((keys.clone(), refresh))
+                }
+                _ => None,
+            }
+        });
+
+        match cached {
+            Some((keys, refresh)) => {
+                if refresh {
+                    let provider = self.clone();
+                    let username = username.to_string();
+                    tokio::spawn(async move {
+                        if let Err(e) = provider.fetch(&username).await {
+                            eprintln!("Could not refresh keys of {}: {}", username, e);
+                            if let Some(entry) = provider
+                                .lock()
+                                .ok()
+                                .as_mut()
+                                .and_then(|cache| cache.get_mut(&username))
+                            {
+                                entry.refreshing = false;
+                            }
+                        }
+                    });
+                }
+                Ok(keys)
+            }
+            None => self.fetch(username).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Response of the mock server, `None` never answers
+    type MockResponse = Arc<Mutex<Option<(u16, String)>>>;
+
+    /// Returns the URL of the server, the number of requests and the last request
+    async fn mock_server(response: MockResponse) -> (String, Arc<AtomicUsize>, Arc<Mutex<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let last_request = Arc::new(Mutex::new(String::new()));
+        let last = last_request.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                let response = response.lock().unwrap().clone();
+                let last = last.clone();
+                tokio::spawn(async move {
+                    let mut buf = [0; 1024];
+                    let length = stream.read(&mut buf).await.unwrap_or(0);
+                    *last.lock().unwrap() = String::from_utf8_lossy(&buf[..length]).to_lowercase();
+                    match response {
+                        Some((status, body)) => {
+                            let http = format!(
+                                "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                                status,
+                                body.len(),
+                                body
+                            );
+                            let _ = stream.write_all(http.as_bytes()).await;
+                        }
+                        None => tokio::time::sleep(Duration::from_secs(10)).await,
+                    }
+                });
+            }
+        });
+
+        (url, requests, last_request)
+    }
+
+    fn ok(keys: &str) -> Option<(u16, String)> {
+        Some((200, keys.to_string()))
+    }
+
+    #[tokio::test]
+    async fn test_cache() {
+        let response = Arc::new(Mutex::new(ok(r#"["ssh-ed25519 AAAA"]"#)));
+        let (url, requests, request) = mock_server(response.clone()).await;
+        let zauth = HttpKeys::new(
+            &format!("{}/users/{{user}}/keys", url),
+            Format::Json,
+            Duration::from_secs(1),
+            Duration::from_secs(60),
+            Duration::from_secs(60),
+            Duration::from_secs(60),
+        );
+
+        assert_eq!(zauth.keys("bob").await.unwrap(), vec!["ssh-ed25519 AAAA"]);
+        assert_eq!(zauth.keys("bob").await.unwrap(), vec!["ssh-ed25519 AAAA"]);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert!(request.lock().unwrap().contains("accept: application/json"));
+
+        // Unknown users are cached as well
+        *response.lock().unwrap() = Some((404, String::new()));
+        assert!(zauth.keys("alice").await.unwrap().is_empty());
+        assert!(zauth.keys("alice").await.unwrap().is_empty());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        // Errors are not cached
+        *response.lock().unwrap() = Some((500, String::new()));
+        assert!(zauth.keys("eve").await.is_err());
+        assert!(zauth.keys("eve").await.is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_stale() {
+        let response = Arc::new(Mutex::new(ok(r#"["ssh-ed25519 AAAA"]"#)));
+        let (url, requests, _) = mock_server(response.clone()).await;
+        let zauth = HttpKeys::new(
+            &format!("{}/users/{{user}}/keys", url),
+            Format::Json,
+            Duration::from_secs(1),
+            Duration::ZERO,
+            Duration::ZERO,
+            Duration::from_secs(60),
+        );
+
+        assert_eq!(zauth.keys("bob").await.unwrap(), vec!["ssh-ed25519 AAAA"]);
+
+        // Zauth is down, the expired keys are still used
+        *response.lock().unwrap() = Some((503, String::new()));
+        assert_eq!(zauth.keys("bob").await.unwrap(), vec!["ssh-ed25519 AAAA"]);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        // Background refresh replaces the keys when Zauth is back
+        *response.lock().unwrap() = ok(r#"["ssh-ed25519 BBBB"]"#);
+        assert_eq!(zauth.keys("bob").await.unwrap(), vec!["ssh-ed25519 AAAA"]);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        assert_eq!(zauth.keys("bob").await.unwrap(), vec!["ssh-ed25519 BBBB"]);
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let (url, _, _) = mock_server(Arc::new(Mutex::new(None))).await;
+        let zauth = HttpKeys::new(
+            &format!("{}/users/{{user}}/keys", url),
+            Format::Json,
+            Duration::from_millis(100),
+            Duration::from_secs(60),
+            Duration::from_secs(60),
+            Duration::ZERO,
+        );
+
+        let start = Instant::now();
+        assert!(zauth.keys("bob").await.is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_authorized_keys() {
+        let response = Arc::new(Mutex::new(ok(
+            "ssh-ed25519 AAAA bob@laptop\n\necdsa-sha2-nistp256 BBBB\n",
+        )));
+        let (url, _, request) = mock_server(response).await;
+        let provider = HttpKeys::new(
+            &format!("{}/{{user}}.keys", url),
+            Format::AuthorizedKeys,
+            Duration::from_secs(1),
+            Duration::from_secs(60),
+            Duration::from_secs(60),
+            Duration::ZERO,
+        );
+
+        assert_eq!(
+            provider.keys("bob").await.unwrap(),
+            vec!["ssh-ed25519 AAAA", "ecdsa-sha2-nistp256 BBBB"]
+        );
+        assert!(request.lock().unwrap().contains("accept: text/plain"));
+    }
+}
@@ -0,0 +1,189 @@
+mod directory;
+mod file;
+mod http;
+
+use std::{path::PathBuf, str::FromStr, sync::OnceLock, time::Duration};
+
+use zns::errors::ZNSError;
+
+use crate::config::Config;
+
+use self::{
+    directory::DirectoryKeys,
+    file::FileKeys,
+    http::{Format, HttpKeys},
+};
+
+use super::pubkeys::{SSH_ECDSA_P256, SSH_ECDSA_P384, SSH_ED25519, SSH_RSA};
+
+const USER_PLACEHOLDER: &str = "{user}";
+
+static PROVIDERS: OnceLock<Vec<Provider>> = OnceLock::new();
+
+/// Source of the SSH public keys of users, which are used to validate SIG(0) signatures
+pub trait KeyProvider {
+    /// Public keys of the user in `<type> <base64>` format, empty if the user is unknown
+    async fn keys(&self, username: &str) -> Result<Vec<String>, ZNSError>;
+}
+
+/// Key provider as configured in `ZNS_KEY_PROVIDERS`
+#[derive(Debug, PartialEq)]
+pub enum ProviderConfig {
+    Zauth,
+    Directory(PathBuf),
+    File(PathBuf),
+    Url(String),
+}
+
+impl FromStr for ProviderConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().split_once(':') {
+            None if s.trim() == "zauth" => Ok(ProviderConfig::Zauth),
+            Some(("directory", path)) => Ok(ProviderConfig::Directory(PathBuf::from(path))),
+            Some(("file", path)) => Ok(ProviderConfig::File(PathBuf::from(path))),
+            Some(("url", url)) if url.contains(USER_PLACEHOLDER) => {
+                Ok(ProviderConfig::Url(url.to_string()))
+            }
+            Some(("url", _)) => Err(format!("url must contain {}", USER_PLACEHOLDER)),
+            _ => Err(format!("unknown key provider: {}", s)),
+        }
+    }
+}
+
+pub enum Provider {
+    Http(HttpKeys),
+    Directory(DirectoryKeys),
+    File(FileKeys),
+}
+
+impl Provider {
+    fn new(config: &ProviderConfig) -> Self {
+        match config {
+            ProviderConfig::Zauth => {
+                let zauth_url = Config::get()
+                    .zauth_url
+                    .as_ref()
+                    .expect("ZAUTH_URL must be set for the zauth key provider");
+                Provider::Http(HttpKeys::new(
+                    &format!("{}/users/{}/keys", zauth_url, USER_PLACEHOLDER),
+                    Format::Json,
+                    Duration::from_secs(Config::get().zauth_timeout),
+                    Duration::from_secs(Config::get().zauth_cache_ttl),
+                    Duration::from_secs(Config::get().zauth_negative_ttl),
+                    Duration::from_secs(Config::get().zauth_stale_ttl),
+                ))
+            }
+            ProviderConfig::Directory(path) => Provider::Directory(DirectoryKeys::new(path)),
+            ProviderConfig::File(path) => Provider::File(FileKeys::new(path)),
+            ProviderConfig::Url(url) => Provider::Http(HttpKeys::new(
+                url,
+                Format::AuthorizedKeys,
+                Duration::from_secs(Config::get().key_url_timeout),
+                Duration::from_secs(Config::get().key_url_cache_ttl),
+                Duration::from_secs(Config::get().key_url_negative_ttl),
+                Duration::from_secs(Config::get().key_url_stale_ttl),
+            )),
+        }
+    }
+
+    /// The configured providers, in the order they should be tried
+    pub fn all() -> &'static [Provider] {
+        PROVIDERS.get_or_init(|| {
+            Config::get()
+                .key_providers
+                .iter()
+                .map(Provider::new)
+                .collect()
+        })
+    }
+}
+
+impl KeyProvider for Provider {
+    async fn keys(&self, username: &str) -> Result<Vec<String>, ZNSError> {
+        // The username is used in paths and URLs
+        if username.is_empty()
+            || !username
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Ok(vec![]);
+        }
+
+        match self {
+            Provider::Http(provider) => provider.keys(username).await,
+            Provider::Directory(provider) => provider.keys(username).await,
+            Provider::File(provider) => provider.keys(username).await,
+        }
+    }
+}
+
+/// Parses keys in the `authorized_keys` format, options and comments are ignored.
+/// https://man.openbsd.org/sshd#AUTHORIZED_KEYS_FILE_FORMAT
+fn parse_authorized_keys(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_ascii_whitespace().collect();
+            let position = fields.iter().position(|field| {
+                [SSH_ED25519, SSH_RSA, SSH_ECDSA_P256, SSH_ECDSA_P384].contains(field)
+            })?;
+            fields
+                .get(position + 1)
+                .map(|key| format!("{} {}", fields[position], key))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provider_config() {
+        assert_eq!("zauth".parse(), Ok(ProviderConfig::Zauth));
+        assert_eq!(
+            "directory:/etc/zns/keys".parse(),
+            Ok(ProviderConfig::Directory(PathBuf::from("/etc/zns/keys")))
+        );
+        assert_eq!(
+            "file:keys.txt".parse(),
+            Ok(ProviderConfig::File(PathBuf::from("keys.txt")))
+        );
+        assert_eq!(
+            "url:https://github.com/{user}.keys".parse(),
+            Ok(ProviderConfig::Url(String::from(
+                "https://github.com/{user}.keys"
+            )))
+        );
+        assert!("url:https://github.com/keys"
+            .parse::<ProviderConfig>()
+            .is_err());
+        assert!("ldap".parse::<ProviderConfig>().is_err());
+    }
+
+    #[test]
+    fn test_parse_authorized_keys() {
+        let content = "# comment\n\
+                       ssh-ed25519 AAAA bob@laptop\n\
+                       \n\
+                       no-pty,from=\"10.0.0.1\" ssh-rsa BBBB\n\
+                       unknown-type CCCC\n\
+                       ssh-ed25519\n";
+
+        assert_eq!(
+            parse_authorized_keys(content),
+            vec!["ssh-ed25519 AAAA", "ssh-rsa BBBB"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_username() {
+        let provider = Provider::Directory(DirectoryKeys::new(&PathBuf::from("/etc")));
+        assert!(provider.keys("../etc").await.unwrap().is_empty());
+        assert!(provider.keys("passwd").await.is_ok());
+    }
+}
@@ -0,0 +1,169 @@
+use ring::signature;
+use zns::{errors::ZNSError, reader::Reader};
+
+use crate::auth::sig::Algorithm;
+
+use super::{read_ssh_string, PublicKey, SSH_ECDSA_P256, SSH_ECDSA_P384};
+
+pub struct EcdsaPublicKey {
+    // Uncompressed point: 0x04 | x | y
+    point: Vec<u8>,
+}
+
+// https://datatracker.ietf.org/doc/html/rfc5656#section-3.1
+const UNCOMPRESSED_POINT: u8 = 0x04;
+
+impl PublicKey for EcdsaPublicKey {
+    fn from_openssh(key: &[u8]) -> Result<Self, ZNSError>
+    where
+        Self: Sized,
+    {
+        let mut reader = Reader::new(key);
+        let key_type = read_ssh_string(&mut reader)?;
+        // Size of the coordinates of a point on the curve
+        let coordinate_size = match key_type.as_str() {
+            SSH_ECDSA_P256 => 32,
+            SSH_ECDSA_P384 => 48,
+            _ => {
+                return Err(ZNSError::Key {
+                    message: String::from("ssh key type does not match identifier"),
+                })
+            }
+        };
+
+        // The curve identifier must be the one in the key type, e.g. `nistp256`
+        let curve = read_ssh_string(&mut reader)?;
+        if key_type.strip_prefix("ecdsa-sha2-") != Some(curve.as_str()) {
+            return Err(ZNSError::Key {
+                message: format!("ECDSA curve {} does not match key type {}", curve, key_type),
+            });
+        }
+
+        let point_size = reader.read_u32()?;
+        let point = reader.read(point_size as usize)?;
+
+        if point.first() != Some(&UNCOMPRESSED_POINT) {
+            return Err(ZNSError::Key {
+                message: String::from("Only uncompressed ECDSA points are supported"),
+            });
+        }
+        if point.len() != 1 + 2 * coordinate_size {
+            return Err(ZNSError::Key {
+                message: format!("Invalid ECDSA point length for {}: {}", curve, point.len()),
+            });
+        }
+
+        Ok(EcdsaPublicKey { point })
+    }
+
+    fn from_dnskey(key: &[u8]) -> Result<Self, ZNSError>
+    where
+        Self: Sized,
+    {
+        // https://datatracker.ietf.org/doc/html/rfc6605#section-4
+        let mut point = vec![UNCOMPRESSED_POINT];
+        point.extend(key);
+        Ok(EcdsaPublicKey { point })
+    }
+
+    fn to_dnskey(&self) -> Vec<u8> {
+        self.point[1..].to_vec()
+    }
+
+    fn verify(
+        &self,
+        data: &[u8],
+        signature: &[u8],
+        algorithm: &Algorithm,
+    ) -> Result<bool, ZNSError> {
+        let signature_type = match algorithm {
+            Algorithm::ECDSAP256SHA256 => Ok(&signature::ECDSA_P256_SHA256_FIXED),
+            Algorithm::ECDSAP384SHA384 => Ok(&signature::ECDSA_P384_SHA384_FIXED),
+            _ => Err(ZNSError::Key {
+                message: String::from("EcdsaPublicKey: invalid verify algorithm"),
+            }),
+        }?;
+
+        let pkey = ring::signature::UnparsedPublicKey::new(signature_type, &self.point);
+
+        Ok(pkey.verify(data, signature).is_ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::prelude::*;
+    use ring::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_from_openssh() {
+        // Generated with `ssh-keygen -t ecdsa -b 256`
+        let key = BASE64_STANDARD.decode("AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBLWsnZFpSpG8G59kJ+1kTXEwdvV3zScBDnCdU9sO0PGEnwqF4aFWs+u/7uc1H6qMwg+D79CZdj8w1H7B/KSDlWY=").unwrap();
+
+        let pkey = EcdsaPublicKey::from_openssh(&key).unwrap();
+        assert_eq!(pkey.point.len(), 65);
+        assert_eq!(pkey.to_dnskey().len(), 64);
+        assert!(EcdsaPublicKey::from_openssh(&key[..key.len() - 1]).is_err());
+    }
+
+    /// Key blob with the given key type, curve identifier and point
+    fn ssh_key(key_type: &str, curve: &str, point: &[u8]) -> Vec<u8> {
+        let mut key = vec![];
+        for field in [key_type.as_bytes(), curve.as_bytes(), point] {
+            key.extend((field.len() as u32).to_be_bytes());
+            key.extend(field);
+        }
+        key
+    }
+
+    #[test]
+    fn test_from_openssh_invalid() {
+        let mut p256 = vec![UNCOMPRESSED_POINT];
+        p256.extend([1; 64]);
+        let mut p384 = vec![UNCOMPRESSED_POINT];
+        p384.extend([1; 96]);
+
+        assert!(EcdsaPublicKey::from_openssh(&ssh_key(SSH_ECDSA_P256, "nistp256", &p256)).is_ok());
+        assert!(EcdsaPublicKey::from_openssh(&ssh_key(SSH_ECDSA_P384, "nistp384", &p384)).is_ok());
+
+        // Curve identifier does not match the key type
+        assert!(EcdsaPublicKey::from_openssh(&ssh_key(SSH_ECDSA_P256, "nistp384", &p256)).is_err());
+        assert!(EcdsaPublicKey::from_openssh(&ssh_key(SSH_ECDSA_P384, "nistp256", &p384)).is_err());
+
+        // Point of the other curve
+        assert!(EcdsaPublicKey::from_openssh(&ssh_key(SSH_ECDSA_P256, "nistp256", &p384)).is_err());
+        assert!(EcdsaPublicKey::from_openssh(&ssh_key(SSH_ECDSA_P384, "nistp384", &p256)).is_err());
+        assert!(
+            EcdsaPublicKey::from_openssh(&ssh_key(SSH_ECDSA_P256, "nistp256", &p256[..33]))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let keypair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+                .unwrap();
+
+        let data = b"zeus";
+        let signature = keypair.sign(&rng, data).unwrap();
+
+        let pkey = EcdsaPublicKey::from_dnskey(&keypair.public_key().as_ref()[1..]).unwrap();
+        assert!(pkey
+            .verify(data, signature.as_ref(), &Algorithm::ECDSAP256SHA256)
+            .unwrap());
+        assert!(!pkey
+            .verify(b"other", signature.as_ref(), &Algorithm::ECDSAP256SHA256)
+            .unwrap());
+        assert!(pkey
+            .verify(data, signature.as_ref(), &Algorithm::ED25519)
+            .is_err());
+    }
+}
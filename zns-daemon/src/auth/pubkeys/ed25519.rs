@@ -15,7 +15,7 @@ impl PublicKey for Ed25519PublicKey {
     {
         let mut reader = Reader::new(key);
         Ed25519PublicKey::verify_ssh_type(&mut reader, SSH_ED25519)?;
-        reader.read_i32()?;
+        reader.read_u32()?;
         Ok(Ed25519PublicKey {
             data: reader.read(reader.unread_bytes())?,
         })
@@ -28,6 +28,10 @@ impl PublicKey for Ed25519PublicKey {
         Ok(Ed25519PublicKey { data: key.to_vec() })
     }
 
+    fn to_dnskey(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+
     fn verify(
         &self,
         data: &[u8],
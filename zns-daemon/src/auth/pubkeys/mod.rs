@@ -1,9 +1,13 @@
+mod ecdsa;
 mod ed25519;
 mod rsa;
 use std::str::from_utf8;
 
+use base64::prelude::*;
+use ring::digest;
 use zns::{errors::ZNSError, reader::Reader};
 
+pub use self::ecdsa::EcdsaPublicKey;
 pub use self::ed25519::Ed25519PublicKey;
 pub use self::rsa::RsaPublicKey;
 
@@ -11,14 +15,32 @@ use super::sig::Algorithm;
 
 pub const SSH_ED25519: &str = "ssh-ed25519";
 pub const SSH_RSA: &str = "ssh-rsa";
+pub const SSH_ECDSA_P256: &str = "ecdsa-sha2-nistp256";
+pub const SSH_ECDSA_P384: &str = "ecdsa-sha2-nistp384";
+
+/// Fingerprint of a key in the `authorized_keys` format, as shown by `ssh-keygen -l`
+pub fn ssh_fingerprint(key: &str) -> Option<String> {
+    let data = key.split_ascii_whitespace().nth(1)?;
+    let bin = BASE64_STANDARD.decode(data).ok()?;
+    Some(format!(
+        "SHA256:{}",
+        BASE64_STANDARD_NO_PAD.encode(digest::digest(&digest::SHA256, &bin))
+    ))
+}
+
+fn read_ssh_string(reader: &mut Reader) -> Result<String, ZNSError> {
+    let size = reader.read_u32()?;
+    let read = reader.read(size as usize)?;
+    from_utf8(&read)
+        .map(str::to_string)
+        .map_err(|e| ZNSError::Key {
+            message: format!("Could not convert type name bytes to string: {}", e),
+        })
+}
 
 pub trait PublicKey {
     fn verify_ssh_type(reader: &mut Reader, key_type: &str) -> Result<(), ZNSError> {
-        let type_size = reader.read_i32()?;
-        let read = reader.read(type_size as usize)?;
-        let algo_type = from_utf8(&read).map_err(|e| ZNSError::Key {
-            message: format!("Could not convert type name bytes to string: {}", e),
-        })?;
+        let algo_type = read_ssh_string(reader)?;
 
         if algo_type == key_type {
             Ok(())
@@ -37,6 +59,9 @@ pub trait PublicKey {
     where
         Self: Sized;
 
+    /// Public key field of the corresponding DNSKEY RDATA
+    fn to_dnskey(&self) -> Vec<u8>;
+
     fn verify(
         &self,
         data: &[u8],
@@ -44,3 +69,21 @@ pub trait PublicKey {
         algorithm: &Algorithm,
     ) -> Result<bool, ZNSError>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ssh_fingerprint() {
+        assert_eq!(
+            ssh_fingerprint(
+                "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIE4S1a0smtfqqAqbnUmlu+28Rn6lbQNRMuLWXTH5XTgB bob"
+            ),
+            Some(String::from(
+                "SHA256:o6wszQlgIa38MzZTvHjvMfnVi0UpH6MhlkHoq4b/UAQ"
+            ))
+        );
+        assert_eq!(ssh_fingerprint("ssh-ed25519"), None);
+    }
+}
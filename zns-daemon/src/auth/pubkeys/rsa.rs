@@ -16,6 +16,14 @@ struct RsaAsn1<'a> {
     e: Option<asn1::BigInt<'a>>,
 }
 
+fn strip_leading_zeros(data: &[u8]) -> &[u8] {
+    let start = data
+        .iter()
+        .position(|byte| *byte != 0)
+        .unwrap_or(data.len());
+    &data[start..]
+}
+
 impl PublicKey for RsaPublicKey {
     fn from_openssh(key: &[u8]) -> Result<Self, ZNSError>
     where
@@ -23,9 +31,9 @@ impl PublicKey for RsaPublicKey {
     {
         let mut reader = Reader::new(key);
         RsaPublicKey::verify_ssh_type(&mut reader, SSH_RSA)?;
-        let e_size = reader.read_i32()?;
+        let e_size = reader.read_u32()?;
         let e = reader.read(e_size as usize)?;
-        let n_size = reader.read_i32()?;
+        let n_size = reader.read_u32()?;
         let n = reader.read(n_size as usize)?;
         Ok(RsaPublicKey { e, n })
     }
@@ -34,14 +42,35 @@ impl PublicKey for RsaPublicKey {
     where
         Self: Sized,
     {
+        // https://datatracker.ietf.org/doc/html/rfc3110#section-2
         let mut reader = Reader::new(key);
-        let e_len = reader.read_u8()?;
+        let e_len = match reader.read_u8()? {
+            0 => reader.read_u16()?,
+            len => len as u16,
+        };
         let e = reader.read(e_len as usize)?;
         let mut n = reader.read(reader.unread_bytes())?;
         n.insert(0, 0);
         Ok(RsaPublicKey { e, n })
     }
 
+    fn to_dnskey(&self) -> Vec<u8> {
+        // https://datatracker.ietf.org/doc/html/rfc3110#section-2
+        let e = strip_leading_zeros(&self.e);
+        let n = strip_leading_zeros(&self.n);
+
+        let mut result = vec![];
+        if e.len() <= u8::MAX as usize {
+            result.push(e.len() as u8);
+        } else {
+            result.push(0);
+            result.extend(u16::to_be_bytes(e.len() as u16));
+        }
+        result.extend(e);
+        result.extend(n);
+        result
+    }
+
     fn verify(
         &self,
         data: &[u8],
@@ -69,3 +98,31 @@ impl PublicKey for RsaPublicKey {
         Ok(pkey.verify(data, signature).is_ok())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dnskey_exponent_length() {
+        let short = RsaPublicKey {
+            e: vec![1, 0, 1],
+            n: vec![0, 0xC3, 0x55],
+        };
+        let rdata = short.to_dnskey();
+        assert_eq!(rdata, [3, 1, 0, 1, 0xC3, 0x55]);
+        assert_eq!(RsaPublicKey::from_dnskey(&rdata).unwrap().e, short.e);
+
+        let long = RsaPublicKey {
+            e: vec![1; 300],
+            n: vec![0xC3, 0x55],
+        };
+        let rdata = long.to_dnskey();
+        assert_eq!(rdata[..3], [0, 1, 44]);
+        assert_eq!(RsaPublicKey::from_dnskey(&rdata).unwrap().e, long.e);
+
+        assert!(RsaPublicKey::from_dnskey(&[]).is_err());
+        assert!(RsaPublicKey::from_dnskey(&[0, 1]).is_err());
+        assert!(RsaPublicKey::from_dnskey(&[4, 1, 0, 1]).is_err());
+    }
+}
@@ -0,0 +1,80 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ring::digest;
+use zns::errors::ZNSError;
+
+use crate::config::Config;
+
+static REPLAY_CACHE: OnceLock<ReplayCache> = OnceLock::new();
+
+/// Hashes of the signed data of accepted messages, kept until the signatures expire.
+/// SIG(0) signatures are not used as key, an ECDSA signature can be altered into another valid
+/// signature of the same data. TSIG MACs can be used, as they are determined by the signed data.
+pub struct ReplayCache {
+    seen: Mutex<HashMap<Vec<u8>, u64>>,
+}
+
+impl ReplayCache {
+    pub fn get() -> &'static ReplayCache {
+        REPLAY_CACHE.get_or_init(|| ReplayCache {
+            seen: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Registers the signed data of a message, fails if the same data was already signed
+    pub fn insert(&self, signed_data: &[u8], expiration: u64) -> Result<(), ZNSError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| ZNSError::Servfail {
+                message: e.to_string(),
+            })?
+            .as_secs();
+
+        self.insert_at(signed_data, expiration + Config::get().sig_max_skew, now)
+    }
+
+    fn insert_at(&self, signed_data: &[u8], expiration: u64, now: u64) -> Result<(), ZNSError> {
+        let mut seen = self.seen.lock().map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })?;
+
+        seen.retain(|_, seen_expiration| *seen_expiration >= now);
+
+        let hash = digest::digest(&digest::SHA256, signed_data)
+            .as_ref()
+            .to_vec();
+        match seen.entry(hash) {
+            Entry::Occupied(_) => Err(ZNSError::Refused {
+                message: String::from("signed message has already been used"),
+            }),
+            Entry::Vacant(entry) => {
+                entry.insert(expiration);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay() {
+        let cache = ReplayCache {
+            seen: Mutex::new(HashMap::new()),
+        };
+
+        assert!(cache.insert_at(&[1, 2, 3], 100, 50).is_ok());
+        assert!(cache.insert_at(&[1, 2, 4], 100, 50).is_ok());
+        assert!(cache.insert_at(&[1, 2, 3], 100, 60).is_err());
+
+        // Expired signatures are removed
+        assert!(cache.insert_at(&[1, 2, 3], 200, 101).is_ok());
+        assert_eq!(cache.seen.lock().unwrap().len(), 1);
+    }
+}
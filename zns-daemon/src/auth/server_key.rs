@@ -0,0 +1,274 @@
+use std::{
+    collections::HashMap,
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::prelude::*;
+use ring::{
+    rand::SystemRandom,
+    signature::{
+        EcdsaKeyPair, Ed25519KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING,
+        ECDSA_P384_SHA384_FIXED_SIGNING,
+    },
+};
+use zns::{
+    dnssec::{key_tag, parse_key_record},
+    errors::ZNSError,
+    labelstring::LabelString,
+    parser::ToBytes,
+    structs::{Class, RData, RRClass, RRType, SigRData, Type, RR},
+};
+
+use super::sig::Algorithm;
+
+enum SigningKey {
+    Ed25519(Ed25519KeyPair),
+    Ecdsa(EcdsaKeyPair),
+}
+
+/// Key used by the server to sign responses with SIG(0)
+pub struct ServerKey {
+    name: LabelString,
+    algorithm: Algorithm,
+    key_tag: u16,
+    key: SigningKey,
+}
+
+fn key_error(message: &str) -> ZNSError {
+    ZNSError::Key {
+        message: format!("Server key: {}", message),
+    }
+}
+
+impl ServerKey {
+    /// Reads a key pair as generated by `dnssec-keygen` or `zns-cli`:
+    /// the public KEY/DNSKEY record in `<path>.key` and the private key in `<path>.private`.
+    pub fn from_files(path: &str) -> Result<Self, ZNSError> {
+        let public =
+            fs::read_to_string(format!("{}.key", path)).map_err(|e| key_error(&e.to_string()))?;
+        let private = fs::read_to_string(format!("{}.private", path))
+            .map_err(|e| key_error(&e.to_string()))?;
+
+        ServerKey::from_strings(&public, &private)
+    }
+
+    fn from_strings(public: &str, private: &str) -> Result<Self, ZNSError> {
+        let (name, rdata) = parse_key_record(public).map_err(|e| key_error(&e.to_string()))?;
+        let public_key = rdata[4..].to_vec();
+
+        let private_fields: HashMap<&str, &str> = private
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .collect();
+
+        let algorithm = Algorithm::from(rdata[3])?;
+        if private_fields
+            .get("Algorithm")
+            .and_then(|value| value.split_ascii_whitespace().next())
+            != Some(&rdata[3].to_string())
+        {
+            return Err(key_error(
+                "algorithm of public and private key do not match",
+            ));
+        }
+
+        let private_key = BASE64_STANDARD
+            .decode(
+                private_fields
+                    .get("PrivateKey")
+                    .ok_or_else(|| key_error("PrivateKey field is missing"))?,
+            )
+            .map_err(|e| key_error(&e.to_string()))?;
+
+        let key = match algorithm {
+            Algorithm::ED25519 => {
+                Ed25519KeyPair::from_seed_and_public_key(&private_key, &public_key)
+                    .map(SigningKey::Ed25519)
+                    .map_err(|e| key_error(&e.to_string()))
+            }
+            Algorithm::ECDSAP256SHA256 | Algorithm::ECDSAP384SHA384 => {
+                let signing_algorithm = match algorithm {
+                    Algorithm::ECDSAP256SHA256 => &ECDSA_P256_SHA256_FIXED_SIGNING,
+                    _ => &ECDSA_P384_SHA384_FIXED_SIGNING,
+                };
+                let mut point = vec![0x04];
+                point.extend(&public_key);
+                EcdsaKeyPair::from_private_key_and_public_key(
+                    signing_algorithm,
+                    &private_key,
+                    &point,
+                    &SystemRandom::new(),
+                )
+                .map(SigningKey::Ecdsa)
+                .map_err(|e| key_error(&e.to_string()))
+            }
+            _ => Err(key_error("only ED25519 and ECDSA keys are supported")),
+        }?;
+
+        Ok(ServerKey {
+            name,
+            algorithm,
+            key_tag: key_tag(&rdata),
+            key,
+        })
+    }
+
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>, ZNSError> {
+        match &self.key {
+            SigningKey::Ed25519(keypair) => Ok(keypair.sign(data).as_ref().to_vec()),
+            SigningKey::Ecdsa(keypair) => keypair
+                .sign(&SystemRandom::new(), data)
+                .map(|signature| signature.as_ref().to_vec())
+                .map_err(|e| ZNSError::Servfail {
+                    message: e.to_string(),
+                }),
+        }
+    }
+
+    /// Appends a SIG(0) to a request
+    pub fn sign_request(&self, request: &mut Vec<u8>, validity: u32) -> Result<(), ZNSError> {
+        self.sign_response(&[], request, validity)
+    }
+
+    /// Appends a SIG(0) to the response, covering both the request and the response.
+    /// https://datatracker.ietf.org/doc/html/rfc2931#section-3.1
+    pub fn sign_response(
+        &self,
+        request: &[u8],
+        response: &mut Vec<u8>,
+        validity: u32,
+    ) -> Result<(), ZNSError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| ZNSError::Servfail {
+                message: e.to_string(),
+            })?
+            .as_secs() as u32;
+
+        let mut rdata = SigRData {
+            type_covered: 0,
+            algorithm: self.algorithm.clone() as u8,
+            labels: 0,
+            original_ttl: 0,
+            signature_expiration: now.wrapping_add(validity),
+            signature_inception: now.wrapping_sub(validity),
+            key_tag: self.key_tag,
+            signer: self.name.clone(),
+            signature: vec![],
+        };
+
+        let mut data = SigRData::to_bytes(rdata.clone());
+        data.extend(request);
+        data.extend(response.iter());
+        rdata.signature = self.sign(&data)?;
+
+        let rdata = SigRData::to_bytes(rdata);
+        let arcount = u16::from_be_bytes([response[10], response[11]]) + 1;
+        response[10..12].copy_from_slice(&u16::to_be_bytes(arcount));
+        response.extend(RR::to_bytes(RR {
+            name: vec![].into(),
+            _type: Type::Type(RRType::SIG),
+            class: Class::Class(RRClass::ANY),
+            ttl: 0,
+            rdlength: rdata.len() as u16,
+            rdata: RData::Vec(rdata),
+        }));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::signature::{self, KeyPair, UnparsedPublicKey};
+    use zns::{parser::FromBytes, reader::Reader, structs::Message, test_utils::get_message};
+
+    use super::*;
+
+    fn verify_response(
+        request: &[u8],
+        response: &[u8],
+        algorithm: &'static dyn signature::VerificationAlgorithm,
+        public_key: &[u8],
+    ) -> bool {
+        let (unsigned, sig_rr) = Message::split_last_additional(response).unwrap();
+        assert_eq!(sig_rr._type, Type::Type(RRType::SIG));
+
+        let rdata: Vec<u8> = sig_rr.rdata.into();
+        let mut sig = SigRData::from_bytes(&mut Reader::new(&rdata)).unwrap();
+        let signature = std::mem::take(&mut sig.signature);
+
+        let mut data = SigRData::to_bytes(sig);
+        data.extend(request);
+        data.extend(unsigned);
+
+        UnparsedPublicKey::new(algorithm, public_key)
+            .verify(&data, &signature)
+            .is_ok()
+    }
+
+    #[test]
+    fn test_sign_ed25519() {
+        let keypair = Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap();
+        let public = format!(
+            "; This is a key\nuser.zeus.gent. IN KEY 256 3 15 {}\n",
+            BASE64_STANDARD.encode(keypair.public_key())
+        );
+        let private = format!(
+            "Private-key-format: v1.3\nAlgorithm: 15 (ED25519)\nPrivateKey: {}\n",
+            BASE64_STANDARD.encode([7; 32])
+        );
+
+        let server_key = ServerKey::from_strings(&public, &private).unwrap();
+        assert_eq!(server_key.name, LabelString::from("user.zeus.gent"));
+
+        let request = Message::to_bytes(get_message(None));
+        let mut response = Message::to_bytes(get_message(None));
+        server_key
+            .sign_response(&request, &mut response, 300)
+            .unwrap();
+
+        assert!(verify_response(
+            &request,
+            &response,
+            &signature::ED25519,
+            keypair.public_key().as_ref()
+        ));
+        assert!(!verify_response(
+            &response,
+            &response,
+            &signature::ED25519,
+            keypair.public_key().as_ref()
+        ));
+    }
+
+    #[test]
+    fn test_sign_ecdsa() {
+        let public_key = "vXxzuIsum0ztpiAistqL4TGTpbVu3Cbn33hC4kzQtesGBa2nvag6xqK4DX4xQED6R/8WuDushc7bAURRu3znGg==";
+        let public = format!("user.zeus.gent. 3600 IN DNSKEY 256 3 13 {}", public_key);
+        let private = "Private-key-format: v1.3\nAlgorithm: 13 (ECDSAP256SHA256)\nPrivateKey: Hy49TFtqeYgfLj1MW2p5iB8uPUxbanmIHy49TFtqeYg=\n";
+
+        let server_key = ServerKey::from_strings(&public, private).unwrap();
+
+        let request = Message::to_bytes(get_message(None));
+        let mut response = Message::to_bytes(get_message(None));
+        server_key
+            .sign_response(&request, &mut response, 300)
+            .unwrap();
+
+        let mut point = vec![0x04];
+        point.extend(BASE64_STANDARD.decode(public_key).unwrap());
+        assert!(verify_response(
+            &request,
+            &response,
+            &signature::ECDSA_P256_SHA256_FIXED,
+            &point
+        ));
+
+        // Private key does not belong to the public key
+        let other = "Private-key-format: v1.3\nAlgorithm: 13 (ECDSAP256SHA256)\nPrivateKey: Hy49TFtqeYgfLj1MW2p5iB8uPUxbanmIHy49TFtqeYc=\n";
+        assert!(ServerKey::from_strings(&public, other).is_err());
+    }
+}
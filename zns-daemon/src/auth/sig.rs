@@ -4,38 +4,37 @@ use base64::prelude::*;
 use int_enum::IntEnum;
 
 use zns::{
-    errors::ZNSError, labelstring::LabelString, parser::FromBytes, reader::Reader, structs::RR,
+    dnssec::key_tag,
+    errors::ZNSError,
+    labelstring::LabelString,
+    parser::{FromBytes, ToBytes},
+    reader::Reader,
+    structs::{Message, RRType, SigRData, Type},
 };
 
+use crate::config::Config;
+
 use super::{
-    dnskey::DNSKeyRData,
-    pubkeys::{Ed25519PublicKey, PublicKey, RsaPublicKey, SSH_ED25519, SSH_RSA},
+    dnskey::{DNSKeyRData, DEFAULT_FLAGS, PROTOCOL_DNSSEC},
+    pubkeys::{
+        EcdsaPublicKey, Ed25519PublicKey, PublicKey, RsaPublicKey, SSH_ECDSA_P256, SSH_ECDSA_P384,
+        SSH_ED25519, SSH_RSA,
+    },
 };
 
 pub struct Sig {
     raw_data: Vec<u8>,
     key_rdata: SigRData,
-}
-
-#[allow(dead_code)]
-#[derive(Debug)]
-struct SigRData {
-    type_covered: u16,
-    algo: Algorithm,
-    labels: u8,
-    original_ttl: u32,
-    signature_expiration: u32,
-    signature_inception: u32,
-    key_tag: u16,
-    signer: LabelString,
-    signature: Vec<u8>,
+    algorithm: Algorithm,
 }
 
 /// https://www.iana.org/assignments/dns-sec-alg-numbers/dns-sec-alg-numbers.xhtml
 #[repr(u8)]
-#[derive(IntEnum, Debug, PartialEq)]
+#[derive(IntEnum, Debug, Clone, PartialEq)]
 pub enum Algorithm {
     ED25519 = 15,
+    ECDSAP384SHA384 = 14,
+    ECDSAP256SHA256 = 13,
     RSASHA512 = 10,
     RSASHA256 = 8,
 }
@@ -49,38 +48,42 @@ impl Algorithm {
     }
 }
 
-impl FromBytes for SigRData {
-    fn from_bytes(reader: &mut Reader) -> Result<Self, ZNSError> {
-        if reader.unread_bytes() < 18 {
-            Err(ZNSError::Parse {
-                object: String::from("KeyRData"),
-                message: String::from("invalid rdata"),
-            })
-        } else {
-            Ok(SigRData {
-                type_covered: reader.read_u16()?,
-                algo: Algorithm::from(reader.read_u8()?)?,
-                labels: reader.read_u8()?,
-                original_ttl: reader.read_u32()?,
-                signature_expiration: reader.read_u32()?,
-                signature_inception: reader.read_u32()?,
-                key_tag: reader.read_u16()?,
-                signer: LabelString::from_bytes(reader)?,
-                signature: reader.read(reader.unread_bytes())?,
-            })
-        }
-    }
-}
-
 impl Sig {
-    pub fn new(rr: &RR, datagram: &[u8]) -> Result<Self, ZNSError> {
-        let mut request = datagram[0..datagram.len() - 11 - rr.rdlength as usize].to_vec();
-        request[11] -= 1; // Decrease arcount
+    /// Parses the SIG(0) record at the end of the additional section.
+    /// The signed data is the SIG RDATA without signature, followed by the message without the SIG record.
+    /// https://datatracker.ietf.org/doc/html/rfc2931#section-3.1
+    pub fn new(datagram: &[u8]) -> Result<Self, ZNSError> {
+        let (request, rr) = Message::split_last_additional(datagram)?;
+
+        if rr._type != Type::Type(RRType::SIG) {
+            return Err(ZNSError::Refused {
+                message: String::from("Last record of additional section is not a SIG record"),
+            });
+        }
 
-        let data: Vec<u8> = rr.rdata.clone().into();
+        let data: Vec<u8> = rr.rdata.into();
         let mut reader = Reader::new(&data);
         let key_rdata = SigRData::from_bytes(&mut reader)?;
+        let algorithm = Algorithm::from(key_rdata.algorithm)?;
 
+        let signed_len =
+            data.len()
+                .checked_sub(key_rdata.signature.len())
+                .ok_or(ZNSError::Formerr {
+                    message: String::from("Invalid SIG record"),
+                })?;
+        let mut raw_data = data[..signed_len].to_vec();
+        raw_data.extend(request);
+
+        Ok(Sig {
+            raw_data,
+            key_rdata,
+            algorithm,
+        })
+    }
+
+    /// Checks the validity period of the signature with the limits from the config
+    pub fn check_validity(&self) -> Result<(), ZNSError> {
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .map_err(|e| ZNSError::Servfail {
@@ -88,58 +91,321 @@ impl Sig {
             })?
             .as_secs();
 
-        if (key_rdata.signature_inception as u64) > now {
+        self.check_validity_at(
+            now,
+            Config::get().sig_max_validity,
+            Config::get().sig_max_skew,
+        )
+    }
+
+    fn check_validity_at(
+        &self,
+        now: u64,
+        max_validity: u64,
+        max_skew: u64,
+    ) -> Result<(), ZNSError> {
+        let inception = self.key_rdata.signature_inception as u64;
+        let expiration = self.expiration();
+
+        if inception > now.saturating_add(max_skew) {
             return Err(ZNSError::Refused {
                 message: String::from("invalid signature inception time"),
             });
         }
 
-        if (key_rdata.signature_expiration as u64) < now {
+        if expiration.saturating_add(max_skew) < now {
             return Err(ZNSError::Refused {
                 message: String::from("signature has expired"),
             });
         }
 
-        let mut raw_data = data[0..data.len() - key_rdata.signature.len()].to_vec();
-        raw_data.extend(request);
+        if expiration < inception || expiration - inception > max_validity {
+            return Err(ZNSError::Refused {
+                message: format!(
+                    "signature validity period is longer than {} seconds",
+                    max_validity
+                ),
+            });
+        }
 
-        Ok(Sig {
-            raw_data,
-            key_rdata,
-        })
+        Ok(())
+    }
+
+    pub fn expiration(&self) -> u64 {
+        self.key_rdata.signature_expiration as u64
+    }
+
+    /// SIG RDATA without the signature followed by the message, as covered by the signature
+    pub fn signed_data(&self) -> &[u8] {
+        &self.raw_data
+    }
+
+    pub fn key_tag(&self) -> u16 {
+        self.key_rdata.key_tag
+    }
+
+    pub fn signer(&self) -> &LabelString {
+        &self.key_rdata.signer
     }
 
     fn verify(&self, key: impl PublicKey) -> Result<bool, ZNSError> {
-        key.verify(
-            &self.raw_data,
-            &self.key_rdata.signature,
-            &self.key_rdata.algo,
-        )
+        key.verify(&self.raw_data, &self.key_rdata.signature, &self.algorithm)
     }
 
     pub fn verify_ssh(&self, key: &str) -> Result<bool, ZNSError> {
-        let key_split: Vec<&str> = key.split_ascii_whitespace().collect();
-        let bin = BASE64_STANDARD.decode(key_split[1]).unwrap();
+        let mut key_split = key.split_ascii_whitespace();
+        let (key_type, key_data) = key_split
+            .next()
+            .zip(key_split.next())
+            .ok_or(ZNSError::Key {
+                message: String::from("Invalid ssh key format"),
+            })?;
+        let bin = BASE64_STANDARD
+            .decode(key_data)
+            .map_err(|e| ZNSError::Key {
+                message: format!("Invalid ssh key encoding: {}", e),
+            })?;
 
-        match (key_split[0], &self.key_rdata.algo) {
-            (SSH_ED25519, Algorithm::ED25519) => self.verify(Ed25519PublicKey::from_openssh(&bin)?),
+        match (key_type, &self.algorithm) {
+            (SSH_ED25519, Algorithm::ED25519) => {
+                self.verify_tagged(Ed25519PublicKey::from_openssh(&bin)?)
+            }
             (SSH_RSA, Algorithm::RSASHA512 | Algorithm::RSASHA256) => {
-                self.verify(RsaPublicKey::from_openssh(&bin)?)
+                self.verify_tagged(RsaPublicKey::from_openssh(&bin)?)
+            }
+            (SSH_ECDSA_P256, Algorithm::ECDSAP256SHA256)
+            | (SSH_ECDSA_P384, Algorithm::ECDSAP384SHA384) => {
+                self.verify_tagged(EcdsaPublicKey::from_openssh(&bin)?)
             }
             _ => Ok(false),
         }
     }
 
+    /// Only verifies the signature if the key tag of the key,
+    /// as it would be in the KEY record generated by zns-cli, matches the key tag of the SIG.
+    fn verify_tagged(&self, key: impl PublicKey) -> Result<bool, ZNSError> {
+        let rdata = DNSKeyRData {
+            flags: DEFAULT_FLAGS,
+            protocol: PROTOCOL_DNSSEC,
+            algorithm: self.algorithm.clone(),
+            public_key: key.to_dnskey(),
+        };
+
+        if key_tag(&DNSKeyRData::to_bytes(rdata)) == self.key_rdata.key_tag {
+            self.verify(key)
+        } else {
+            Ok(false)
+        }
+    }
+
     pub fn verify_dnskey(&self, key: DNSKeyRData) -> Result<bool, ZNSError> {
-        if self.key_rdata.algo != key.algorithm {
+        if self.algorithm != key.algorithm {
             Ok(false)
         } else {
-            match self.key_rdata.algo {
+            match self.algorithm {
                 Algorithm::RSASHA512 | Algorithm::RSASHA256 => {
                     self.verify(RsaPublicKey::from_dnskey(&key.public_key)?)
                 }
                 Algorithm::ED25519 => self.verify(Ed25519PublicKey::from_dnskey(&key.public_key)?),
+                Algorithm::ECDSAP256SHA256 | Algorithm::ECDSAP384SHA384 => {
+                    self.verify(EcdsaPublicKey::from_dnskey(&key.public_key)?)
+                }
             }
         }
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use zns::{
+        structs::{Class, RData, RRClass, RR},
+        test_utils::get_message,
+    };
+
+    use super::*;
+
+    pub fn get_keypair() -> Ed25519KeyPair {
+        Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap()
+    }
+
+    pub fn get_key_rdata(keypair: &Ed25519KeyPair) -> Vec<u8> {
+        DNSKeyRData::to_bytes(DNSKeyRData {
+            flags: DEFAULT_FLAGS,
+            protocol: PROTOCOL_DNSSEC,
+            algorithm: Algorithm::ED25519,
+            public_key: keypair.public_key().as_ref().to_vec(),
+        })
+    }
+
+    /// Appends a SIG(0) record to the message, valid for the next 5 minutes
+    pub fn sign_message(
+        message: Message,
+        keypair: &Ed25519KeyPair,
+        signer: &LabelString,
+        key_tag: u16,
+    ) -> Vec<u8> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
+
+        sign_message_at(message, keypair, signer, key_tag, now - 10, now + 300)
+    }
+
+    /// Appends a SIG(0) record to the message with the given validity period
+    pub fn sign_message_at(
+        message: Message,
+        keypair: &Ed25519KeyPair,
+        signer: &LabelString,
+        key_tag: u16,
+        inception: u32,
+        expiration: u32,
+    ) -> Vec<u8> {
+        let mut rdata = vec![0, 0, Algorithm::ED25519 as u8, 0, 0, 0, 0, 0];
+        rdata.extend(u32::to_be_bytes(expiration));
+        rdata.extend(u32::to_be_bytes(inception));
+        rdata.extend(u16::to_be_bytes(key_tag));
+        rdata.extend(LabelString::to_bytes(signer.clone()));
+
+        let request = Message::to_bytes(message);
+        let mut data = rdata.clone();
+        data.extend(&request);
+        rdata.extend(keypair.sign(&data).as_ref());
+
+        let mut datagram = request;
+        let arcount = u16::from_be_bytes([datagram[10], datagram[11]]) + 1;
+        datagram[10..12].copy_from_slice(&u16::to_be_bytes(arcount));
+        datagram.extend(RR::to_bytes(RR {
+            name: vec![].into(),
+            _type: Type::Type(RRType::SIG),
+            class: Class::Class(RRClass::ANY),
+            ttl: 0,
+            rdlength: rdata.len() as u16,
+            rdata: RData::Vec(rdata),
+        }));
+        datagram
+    }
+
+    pub fn get_sig(datagram: &[u8]) -> Result<Sig, ZNSError> {
+        Sig::new(datagram)
+    }
+
+    #[test]
+    fn test_new_malformed() {
+        let keypair = get_keypair();
+        let signer = LabelString::from("bob.user.zeus.gent");
+        let datagram = sign_message(get_message(None), &keypair, &signer, 0);
+        assert!(Sig::new(&datagram).is_ok());
+
+        // Truncated messages
+        for len in [0, 5, 12, datagram.len() - 1] {
+            assert!(Sig::new(&datagram[..len]).is_err());
+        }
+
+        // SIG is not the last record
+        let mut trailing = datagram.clone();
+        trailing.push(0);
+        assert!(Sig::new(&trailing).is_err());
+
+        // Additional count does not include the SIG
+        let mut arcount = datagram.clone();
+        arcount[11] -= 1;
+        assert!(Sig::new(&arcount).is_err());
+
+        // Last record is not a SIG
+        assert!(Sig::new(&Message::to_bytes(get_message(None))).is_err());
+    }
+
+    #[test]
+    fn test_new_owner_not_root() {
+        let keypair = get_keypair();
+        let signer = LabelString::from("bob.user.zeus.gent");
+        let datagram = sign_message(get_message(None), &keypair, &signer, 0);
+
+        let (request, mut rr) = Message::split_last_additional(&datagram).unwrap();
+        rr.name = signer.clone();
+
+        let mut renamed = request.clone();
+        renamed[11] += 1;
+        renamed.extend(RR::to_bytes(rr));
+
+        let sig = Sig::new(&renamed).unwrap();
+        assert_eq!(sig.raw_data, get_sig(&datagram).unwrap().raw_data);
+    }
+
+    #[test]
+    fn test_signed_data() {
+        let keypair = get_keypair();
+        let signer = LabelString::from("bob.user.zeus.gent");
+        let datagram = sign_message(get_message(None), &keypair, &signer, 0);
+
+        // Another signature of the same message has the same signed data
+        let mut modified = datagram.clone();
+        *modified.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            get_sig(&modified).unwrap().signed_data(),
+            get_sig(&datagram).unwrap().signed_data()
+        );
+    }
+
+    #[test]
+    fn test_check_validity() {
+        let keypair = get_keypair();
+        let signer = LabelString::from("bob.user.zeus.gent");
+        let sig = get_sig(&sign_message(get_message(None), &keypair, &signer, 0)).unwrap();
+
+        // Signature is valid from now - 10 until now + 300
+        let now = sig.expiration() - 300;
+        assert!(sig.check_validity_at(now, 3600, 0).is_ok());
+        assert!(sig.check_validity_at(now - 20, 3600, 0).is_err());
+        assert!(sig.check_validity_at(now - 20, 3600, 60).is_ok());
+        assert!(sig.check_validity_at(now + 301, 3600, 0).is_err());
+        assert!(sig.check_validity_at(now + 301, 3600, 60).is_ok());
+        assert!(sig.check_validity_at(now, 300, 0).is_err());
+    }
+
+    #[test]
+    fn test_verify_dnskey() {
+        let keypair = get_keypair();
+        let signer = LabelString::from("bob.user.zeus.gent");
+        let rdata = get_key_rdata(&keypair);
+        let datagram = sign_message(get_message(None), &keypair, &signer, key_tag(&rdata));
+
+        let sig = get_sig(&datagram).unwrap();
+        assert_eq!(sig.signer(), &signer);
+        assert_eq!(sig.key_tag(), key_tag(&rdata));
+
+        let dnskey = DNSKeyRData::from_bytes(&mut Reader::new(&rdata)).unwrap();
+        assert!(sig.verify_dnskey(dnskey).unwrap());
+
+        let other = DNSKeyRData::from_bytes(&mut Reader::new(&get_key_rdata(
+            &Ed25519KeyPair::from_seed_unchecked(&[8; 32]).unwrap(),
+        )))
+        .unwrap();
+        assert!(!sig.verify_dnskey(other).unwrap());
+    }
+
+    #[test]
+    fn test_verify_ssh_key_tag() {
+        let keypair = get_keypair();
+        let signer = LabelString::from("bob.user.zeus.gent");
+
+        let mut openssh = vec![];
+        for field in [SSH_ED25519.as_bytes(), keypair.public_key().as_ref()] {
+            openssh.extend(u32::to_be_bytes(field.len() as u32));
+            openssh.extend(field);
+        }
+        let ssh_key = format!("{} {}", SSH_ED25519, BASE64_STANDARD.encode(openssh));
+
+        let tag = key_tag(&get_key_rdata(&keypair));
+        let sig = get_sig(&sign_message(get_message(None), &keypair, &signer, tag)).unwrap();
+        assert!(sig.verify_ssh(&ssh_key).unwrap());
+
+        let sig = get_sig(&sign_message(get_message(None), &keypair, &signer, tag ^ 1)).unwrap();
+        assert!(!sig.verify_ssh(&ssh_key).unwrap());
+
+        assert!(sig.verify_ssh(SSH_ED25519).is_err());
+        assert!(sig.verify_ssh("ssh-ed25519 not*base64").is_err());
+    }
+}
@@ -0,0 +1,309 @@
+use std::{fs, net::IpAddr, str::FromStr};
+
+use diesel::PgConnection;
+use zns::{
+    dnssec::{key_tag, parse_key_record},
+    errors::ZNSError,
+    labelstring::LabelString,
+    parser::FromBytes,
+    reader::Reader,
+    structs::{Message, RRType, Type},
+    tsig::Tsig,
+};
+
+use crate::{catalog::is_catalog_zone, config::Config, db::models::get_tsig_key};
+
+use super::{
+    dnskey::DNSKeyRData,
+    replay::ReplayCache,
+    sig::Sig,
+    tsig::{has_tsig, now},
+};
+
+/// Rule of `ZNS_TRANSFER_ACL` which allows zone transfers of every zone in the authoritative
+/// zone, without owning the zones
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransferRule {
+    /// Clients in the IP prefix, `ip:<address>[/<length>]`
+    Prefix { address: IpAddr, length: u8 },
+    /// Requests signed with the TSIG key from the database, `tsig:<name>`
+    Tsig(LabelString),
+    /// Requests signed with SIG(0) by the public key in `<path>.key`, `key:<path>`
+    Key { name: LabelString, rdata: Vec<u8> },
+}
+
+impl FromStr for TransferRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().split_once(':') {
+            Some(("ip", prefix)) => {
+                let (address, length) = match prefix.split_once('/') {
+                    Some((address, length)) => (address, Some(length)),
+                    None => (prefix, None),
+                };
+                let address: IpAddr = address
+                    .parse()
+                    .map_err(|_| format!("invalid IP prefix: {}", prefix))?;
+                let max = if address.is_ipv4() { 32 } else { 128 };
+                let length = length
+                    .map(|length| length.parse().ok().filter(|length| *length <= max))
+                    .unwrap_or(Some(max))
+                    .ok_or_else(|| format!("invalid IP prefix: {}", prefix))?;
+                Ok(TransferRule::Prefix { address, length })
+            }
+            Some(("tsig", name)) if !name.is_empty() => Ok(TransferRule::Tsig(LabelString::from(
+                name.trim_end_matches('.'),
+            ))),
+            Some(("key", path)) => {
+                let public = fs::read_to_string(format!("{}.key", path))
+                    .map_err(|e| format!("{}.key: {}", path, e))?;
+                let (name, rdata) =
+                    parse_key_record(&public).map_err(|e| format!("{}.key: {}", path, e))?;
+                Ok(TransferRule::Key { name, rdata })
+            }
+            _ => Err(format!("unknown transfer rule: {}", s)),
+        }
+    }
+}
+
+impl TransferRule {
+    fn allows(
+        &self,
+        message: &Message,
+        raw: &[u8],
+        client: IpAddr,
+        connection: &mut PgConnection,
+    ) -> Result<bool, ZNSError> {
+        match self {
+            TransferRule::Prefix { address, length } => Ok(in_prefix(client, address, *length)),
+            TransferRule::Tsig(name) => {
+                if !has_tsig(message) {
+                    return Ok(false);
+                }
+                let tsig = Tsig::from_message(raw)?;
+                if &tsig.name != name {
+                    return Ok(false);
+                }
+                let time = now()?;
+                if get_tsig_key(name, connection)?
+                    .is_none_or(|(key, _)| tsig.verify(&key, None, time).is_err())
+                {
+                    return Ok(false);
+                }
+                ReplayCache::get()
+                    .insert(tsig.mac(), tsig.rdata.time_signed + tsig.rdata.fudge as u64)?;
+                Ok(true)
+            }
+            TransferRule::Key { name, rdata } => {
+                if !message
+                    .additional
+                    .last()
+                    .is_some_and(|rr| rr._type == Type::Type(RRType::SIG))
+                {
+                    return Ok(false);
+                }
+                let sig = Sig::new(raw)?;
+                if sig.signer() != name || sig.key_tag() != key_tag(rdata) {
+                    return Ok(false);
+                }
+                if sig.check_validity().is_err()
+                    || !sig.verify_dnskey(DNSKeyRData::from_bytes(&mut Reader::new(rdata))?)?
+                {
+                    return Ok(false);
+                }
+                ReplayCache::get().insert(sig.signed_data(), sig.expiration())?;
+                Ok(true)
+            }
+        }
+    }
+}
+
+fn in_prefix(client: IpAddr, address: &IpAddr, length: u8) -> bool {
+    let (client, address, bits) = match (client, address) {
+        (IpAddr::V4(client), IpAddr::V4(address)) => {
+            (u32::from(client) as u128, u32::from(*address) as u128, 32)
+        }
+        (IpAddr::V6(client), IpAddr::V6(address)) => {
+            (u128::from(client), u128::from(*address), 128)
+        }
+        _ => return false,
+    };
+    let mask = u128::MAX.checked_shl((bits - length) as u32).unwrap_or(0);
+    client & mask == address & mask
+}
+
+/// Checks if a zone transfer of the zone is allowed by one of the rules of `ZNS_TRANSFER_ACL`.
+/// The rules apply to the authoritative zone and all zones in it, and to the catalog zone.
+pub fn transfer_allowed(
+    message: &Message,
+    zone: &LabelString,
+    raw: &[u8],
+    client: IpAddr,
+    connection: &mut PgConnection,
+) -> Result<bool, ZNSError> {
+    is_allowed(
+        &Config::get().transfer_acl,
+        message,
+        zone,
+        raw,
+        client,
+        connection,
+    )
+}
+
+fn is_allowed(
+    rules: &[TransferRule],
+    message: &Message,
+    zone: &LabelString,
+    raw: &[u8],
+    client: IpAddr,
+    connection: &mut PgConnection,
+) -> Result<bool, ZNSError> {
+    if !zone.is_subdomain_of(&Config::get().authoritative_zone) && !is_catalog_zone(zone) {
+        return Ok(false);
+    }
+    for rule in rules {
+        if rule.allows(message, raw, client, connection)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::signature::Ed25519KeyPair;
+    use zns::{
+        parser::ToBytes,
+        test_utils::get_message,
+        tsig::{sign_request, TsigKey},
+    };
+
+    use crate::db::lib::tests::get_test_connection;
+
+    use super::super::{
+        sig::tests::{get_key_rdata, get_keypair, sign_message, sign_message_at},
+        tsig::tests::insert_tsig_key,
+    };
+    use super::*;
+
+    #[test]
+    fn test_parse_rule() {
+        assert_eq!(
+            "ip:10.0.0.0/8".parse(),
+            Ok(TransferRule::Prefix {
+                address: IpAddr::from([10, 0, 0, 0]),
+                length: 8
+            })
+        );
+        assert_eq!(
+            "ip:2001:db8::1".parse(),
+            Ok(TransferRule::Prefix {
+                address: "2001:db8::1".parse().unwrap(),
+                length: 128
+            })
+        );
+        assert_eq!(
+            "tsig:transfer.zeus.gent.".parse(),
+            Ok(TransferRule::Tsig(LabelString::from("transfer.zeus.gent")))
+        );
+        assert!("ip:10.0.0.0/33".parse::<TransferRule>().is_err());
+        assert!("ip:ns.zeus.gent".parse::<TransferRule>().is_err());
+        assert!("key:/nonexistent/key".parse::<TransferRule>().is_err());
+        assert!("any".parse::<TransferRule>().is_err());
+    }
+
+    #[test]
+    fn test_in_prefix() {
+        let prefix = IpAddr::from([10, 1, 0, 0]);
+        assert!(in_prefix(IpAddr::from([10, 1, 2, 3]), &prefix, 16));
+        assert!(!in_prefix(IpAddr::from([10, 2, 2, 3]), &prefix, 16));
+        assert!(in_prefix(IpAddr::from([192, 0, 2, 1]), &prefix, 0));
+        assert!(!in_prefix(IpAddr::from([10, 1, 0, 1]), &prefix, 32));
+        assert!(!in_prefix("::1".parse().unwrap(), &prefix, 0));
+
+        let prefix: IpAddr = "2001:db8::".parse().unwrap();
+        assert!(in_prefix("2001:db8::53".parse().unwrap(), &prefix, 32));
+        assert!(!in_prefix("2001:db9::53".parse().unwrap(), &prefix, 32));
+    }
+
+    #[test]
+    fn test_is_allowed() {
+        let mut connection = get_test_connection();
+        let zone = Config::get().authoritative_zone.clone();
+        let user_zone = zone.prepend("bob".to_string());
+        let client = IpAddr::from([192, 0, 2, 1]);
+        let message = get_message(Some(zone.clone()));
+        let raw = Message::to_bytes(message.clone());
+        let key = insert_tsig_key(&zone, &mut connection);
+
+        let mut allowed = |rule: &TransferRule, zone: &LabelString, raw: &[u8]| {
+            let message = Message::from_bytes(&mut Reader::new(raw)).unwrap();
+            is_allowed(
+                std::slice::from_ref(rule),
+                &message,
+                zone,
+                raw,
+                client,
+                &mut connection,
+            )
+            .is_ok_and(|allowed| allowed)
+        };
+
+        let prefix = TransferRule::Prefix {
+            address: IpAddr::from([192, 0, 2, 0]),
+            length: 24,
+        };
+        assert!(allowed(&prefix, &zone, &raw));
+        assert!(allowed(&prefix, &user_zone, &raw));
+        assert!(!allowed(&prefix, &LabelString::from("zeus.gent"), &raw));
+
+        // TSIG key of the secondary
+        let mut signed = raw.clone();
+        sign_request(&mut signed, &key, now().unwrap()).unwrap();
+        let other = TsigKey {
+            name: LabelString::from("other"),
+            ..key.clone()
+        };
+        let mut other_signed = raw.clone();
+        sign_request(&mut other_signed, &other, now().unwrap()).unwrap();
+        let rule = TransferRule::Tsig(key.name.clone());
+        assert!(allowed(&rule, &user_zone, &signed));
+        assert!(!allowed(&rule, &user_zone, &raw));
+        assert!(!allowed(&rule, &user_zone, &other_signed));
+        // Replays of an accepted request are refused
+        assert!(!allowed(&rule, &user_zone, &signed));
+
+        // Dedicated SIG(0) transfer key
+        let keypair = get_keypair();
+        let rdata = get_key_rdata(&keypair);
+        let name = LabelString::from("transfer.zeus.gent");
+        let rule = TransferRule::Key {
+            name: name.clone(),
+            rdata: rdata.clone(),
+        };
+        let signed = sign_message(message.clone(), &keypair, &name, key_tag(&rdata));
+        assert!(allowed(&rule, &user_zone, &signed));
+        assert!(!allowed(&rule, &user_zone, &signed));
+        assert!(!allowed(&rule, &user_zone, &raw));
+
+        let other = Ed25519KeyPair::from_seed_unchecked(&[8; 32]).unwrap();
+        let signed = sign_message(message.clone(), &other, &name, key_tag(&rdata));
+        assert!(!allowed(&rule, &user_zone, &signed));
+
+        // An expired signature does not match the rule, but the later rules are still checked
+        let expired = sign_message_at(message, &keypair, &name, key_tag(&rdata), 1000, 2000);
+        let parsed = Message::from_bytes(&mut Reader::new(&expired)).unwrap();
+        assert!(is_allowed(
+            &[rule.clone(), prefix],
+            &parsed,
+            &user_zone,
+            &expired,
+            client,
+            &mut get_test_connection(),
+        )
+        .unwrap());
+        assert!(!allowed(&rule, &user_zone, &expired));
+    }
+}
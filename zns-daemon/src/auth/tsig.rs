@@ -0,0 +1,388 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use diesel::PgConnection;
+use ring::rand::{SecureRandom, SystemRandom};
+use zns::{
+    errors::ZNSError,
+    labelstring::LabelString,
+    structs::{Message, RRType, Type},
+    tsig::{Tsig, TsigAlgorithm, TsigError, TsigKey, TsigStream},
+};
+
+use crate::db::models::{delete_tsig_key, get_tsig_key, get_tsig_keys, insert_tsig_key};
+
+use super::{
+    owners::{zone_owners, Owner},
+    replay::ReplayCache,
+};
+
+pub(super) fn now() -> Result<u64, ZNSError> {
+    Ok(SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })?
+        .as_secs())
+}
+
+pub fn has_tsig(message: &Message) -> bool {
+    message
+        .additional
+        .last()
+        .is_some_and(|rr| rr._type == Type::Type(RRType::TSIG))
+}
+
+/// TSIG of a request, the response is signed with the same key
+pub struct RequestTsig {
+    tsig: Tsig,
+    key: Option<TsigKey>,
+    error: TsigError,
+    /// Signer of the messages of the response, once the first message is signed
+    stream: Option<TsigStream>,
+}
+
+impl RequestTsig {
+    /// Validates the TSIG at the end of the request, if there is one
+    pub fn from_request(
+        message: &Message,
+        raw: &[u8],
+        connection: &mut PgConnection,
+    ) -> Result<Option<Self>, ZNSError> {
+        if !has_tsig(message) {
+            return Ok(None);
+        }
+
+        let tsig = Tsig::from_message(raw)?;
+        let key = get_tsig_key(&tsig.name, connection)?.map(|(key, _)| key);
+        let error = match &key {
+            Some(key) => match tsig.verify(key, None, now()?) {
+                Ok(()) => TsigError::NOERROR,
+                Err(error) => error,
+            },
+            None => TsigError::BADKEY,
+        };
+
+        Ok(Some(RequestTsig {
+            tsig,
+            key,
+            error,
+            stream: None,
+        }))
+    }
+
+    pub fn error(&self) -> TsigError {
+        self.error
+    }
+
+    /// Appends a TSIG to the next message of the response, the MAC of each message covers the
+    /// MAC of the previous one.
+    /// Responses to requests with an unknown key or invalid MAC are not signed.
+    /// https://datatracker.ietf.org/doc/html/rfc8945#section-5.3.2
+    pub fn sign_response(&mut self, response: &mut Vec<u8>) -> Result<(), ZNSError> {
+        match (&self.key, self.error) {
+            (Some(key), TsigError::NOERROR) => self
+                .stream
+                .get_or_insert_with(|| TsigStream::new(key.clone(), self.tsig.mac()))
+                .sign(response, now()?, TsigError::NOERROR, vec![]),
+            // The client can adjust its clock with the time of the server in other data
+            (Some(key), TsigError::BADTIME) => TsigStream::new(key.clone(), self.tsig.mac()).sign(
+                response,
+                self.tsig.rdata.time_signed,
+                TsigError::BADTIME,
+                u64::to_be_bytes(now()?)[2..].to_vec(),
+            ),
+            (_, error) => self.tsig.append_error(response, error),
+        }
+    }
+}
+
+/// Checks if the request is signed with a TSIG key for the zone or a key which owns the zone,
+/// returns the name of the key
+pub fn verify_tsig(
+    zone: &LabelString,
+    raw: &[u8],
+    connection: &mut PgConnection,
+) -> Result<LabelString, ZNSError> {
+    let tsig = Tsig::from_message(raw)?;
+
+    let (key, key_zone) = get_tsig_key(&tsig.name, connection)?.ok_or(ZNSError::NotAuth {
+        message: format!("Unknown TSIG key: {}", tsig.name),
+    })?;
+
+    tsig.verify(&key, None, now()?)
+        .map_err(|error| ZNSError::NotAuth {
+            message: format!("TSIG verification failed: {:?}", error),
+        })?;
+
+    if !zone.is_subdomain_of(&key_zone)
+        && !zone_owners(zone, connection)?.contains(&Owner::Key(key.name.clone()))
+    {
+        return Err(ZNSError::Refused {
+            message: format!("TSIG key {} is not authorized for zone {}", key.name, zone),
+        });
+    }
+
+    ReplayCache::get().insert(tsig.mac(), tsig.rdata.time_signed + tsig.rdata.fudge as u64)?;
+
+    Ok(key.name)
+}
+
+/// Stores a TSIG key which can be used for the zone and all zones below it.
+/// Without a secret, a random secret of the length of the HMAC output is generated.
+pub fn add_tsig_key(
+    name: &LabelString,
+    zone: &LabelString,
+    algorithm: &str,
+    secret: Option<Vec<u8>>,
+    connection: &mut PgConnection,
+) -> Result<TsigKey, ZNSError> {
+    let algorithm =
+        TsigAlgorithm::from_name(&algorithm.to_lowercase()).ok_or(ZNSError::Refused {
+            message: format!("Unsupported TSIG algorithm: {}", algorithm),
+        })?;
+    if get_tsig_key(name, connection)?.is_some() {
+        return Err(ZNSError::Refused {
+            message: format!("TSIG key {} already exists", name),
+        });
+    }
+
+    let secret = match secret {
+        Some(secret) if secret.is_empty() => {
+            return Err(ZNSError::Refused {
+                message: String::from("TSIG secret is empty"),
+            })
+        }
+        Some(secret) => secret,
+        None => {
+            let mut secret = vec![
+                0;
+                match algorithm {
+                    TsigAlgorithm::HmacSha256 => 32,
+                    TsigAlgorithm::HmacSha384 => 48,
+                    TsigAlgorithm::HmacSha512 => 64,
+                }
+            ];
+            SystemRandom::new()
+                .fill(&mut secret)
+                .map_err(|e| ZNSError::Servfail {
+                    message: e.to_string(),
+                })?;
+            secret
+        }
+    };
+
+    let key = TsigKey {
+        name: name.clone(),
+        algorithm,
+        secret,
+    };
+    insert_tsig_key(&key, zone, connection)?;
+    Ok(key)
+}
+
+/// Removes a TSIG key of the zone
+pub fn remove_tsig_key(
+    name: &LabelString,
+    zone: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    match delete_tsig_key(name, zone, connection)? {
+        0 => Err(ZNSError::Refused {
+            message: format!("{} is not a TSIG key of {}", name, zone),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// TSIG keys of the zone and all zones below it, with the zone of each key
+pub fn list_tsig_keys(
+    zone: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<Vec<(TsigKey, LabelString)>, ZNSError> {
+    get_tsig_keys(zone, connection)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use diesel::RunQueryDsl;
+    use zns::{
+        parser::{FromBytes, ToBytes},
+        reader::Reader,
+        test_utils::get_message,
+        tsig::{sign_request, TsigAlgorithm},
+    };
+
+    use crate::{
+        auth::owners::add_zone_owner,
+        config::Config,
+        db::{
+            lib::tests::get_test_connection,
+            models::{schema::tsig_keys, TsigKeyRecord},
+        },
+    };
+
+    use super::*;
+
+    pub fn insert_tsig_key(zone: &LabelString, connection: &mut PgConnection) -> TsigKey {
+        let key = TsigKey {
+            name: zone.prepend("tsig".to_string()),
+            algorithm: TsigAlgorithm::HmacSha256,
+            secret: vec![7; 32],
+        };
+
+        diesel::insert_into(tsig_keys::table)
+            .values(TsigKeyRecord {
+                name: key.name.to_string(),
+                algorithm: String::from("hmac-sha256"),
+                secret: key.secret.clone(),
+                zone: zone.to_string(),
+            })
+            .execute(connection)
+            .unwrap();
+
+        key
+    }
+
+    #[test]
+    fn test_manage_tsig_keys() {
+        let mut connection = get_test_connection();
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let name = zone.prepend("certbot".to_string());
+
+        assert!(add_tsig_key(&name, &zone, "hmac-md5", None, &mut connection).is_err());
+
+        let key = add_tsig_key(&name, &zone, "hmac-sha384", None, &mut connection).unwrap();
+        assert_eq!(key.algorithm, TsigAlgorithm::HmacSha384);
+        assert_eq!(key.secret.len(), 48);
+        assert!(add_tsig_key(&name, &zone, "hmac-sha256", None, &mut connection).is_err());
+
+        let other = zone.prepend("router".to_string());
+        let other_key = add_tsig_key(
+            &other,
+            &zone.prepend("home".to_string()),
+            "hmac-sha256",
+            Some(vec![7; 32]),
+            &mut connection,
+        )
+        .unwrap();
+        assert_eq!(other_key.secret, vec![7; 32]);
+
+        // The key can be used for the zone
+        let (_, raw) = get_request(&zone, &key, now().unwrap());
+        assert_eq!(verify_tsig(&zone, &raw, &mut connection).unwrap(), name);
+
+        let keys: Vec<LabelString> = list_tsig_keys(&zone, &mut connection)
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key.name)
+            .collect();
+        assert_eq!(keys, vec![name.clone(), other.clone()]);
+
+        // Keys can only be removed from their own zone
+        assert!(remove_tsig_key(&other, &zone, &mut connection).is_err());
+        remove_tsig_key(&name, &zone, &mut connection).unwrap();
+        assert!(remove_tsig_key(&name, &zone, &mut connection).is_err());
+        assert_eq!(list_tsig_keys(&zone, &mut connection).unwrap().len(), 1);
+    }
+
+    fn get_request(zone: &LabelString, key: &TsigKey, time_signed: u64) -> (Message, Vec<u8>) {
+        let mut raw = Message::to_bytes(get_message(Some(zone.clone())));
+        sign_request(&mut raw, key, time_signed).unwrap();
+        (Message::from_bytes(&mut Reader::new(&raw)).unwrap(), raw)
+    }
+
+    #[test]
+    fn test_verify_tsig() {
+        let mut connection = get_test_connection();
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let key = insert_tsig_key(&zone, &mut connection);
+
+        let (_, raw) = get_request(&zone, &key, now().unwrap());
+        assert_eq!(verify_tsig(&zone, &raw, &mut connection).unwrap(), key.name);
+
+        // Replay
+        assert!(verify_tsig(&zone, &raw, &mut connection).is_err());
+
+        let (_, raw) = get_request(&zone, &key, now().unwrap() - 1);
+        let other = Config::get()
+            .authoritative_zone
+            .prepend("alice".to_string());
+        assert!(matches!(
+            verify_tsig(&other, &raw, &mut connection),
+            Err(ZNSError::Refused { .. })
+        ));
+
+        // Key which owns the zone
+        add_zone_owner(&other, &Owner::Key(key.name.clone()), &mut connection).unwrap();
+        let (_, raw) = get_request(&zone, &key, now().unwrap() - 2);
+        assert_eq!(
+            verify_tsig(&other, &raw, &mut connection).unwrap(),
+            key.name
+        );
+
+        let unknown = TsigKey {
+            name: LabelString::from("unknown"),
+            ..key.clone()
+        };
+        let (_, raw) = get_request(&zone, &unknown, now().unwrap());
+        assert!(matches!(
+            verify_tsig(&zone, &raw, &mut connection),
+            Err(ZNSError::NotAuth { .. })
+        ));
+    }
+
+    #[test]
+    fn test_request_tsig() {
+        let mut connection = get_test_connection();
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let key = insert_tsig_key(&zone, &mut connection);
+
+        let (message, raw) = get_request(&zone, &key, now().unwrap());
+        let mut tsig = RequestTsig::from_request(&message, &raw, &mut connection)
+            .unwrap()
+            .unwrap();
+        assert_eq!(tsig.error(), TsigError::NOERROR);
+
+        // Every message of a response with multiple messages is signed
+        let mut stream = TsigStream::new(key.clone(), Tsig::from_message(&raw).unwrap().mac());
+        for _ in 0..3 {
+            let mut response = Message::to_bytes(get_message(Some(zone.clone())));
+            tsig.sign_response(&mut response).unwrap();
+            assert_eq!(stream.verify(&response, now().unwrap()), Ok(()));
+        }
+
+        // Request from the past
+        let (message, raw) = get_request(&zone, &key, now().unwrap() - 1000);
+        let mut tsig = RequestTsig::from_request(&message, &raw, &mut connection)
+            .unwrap()
+            .unwrap();
+        assert_eq!(tsig.error(), TsigError::BADTIME);
+
+        let mut response = Message::to_bytes(get_message(Some(zone.clone())));
+        tsig.sign_response(&mut response).unwrap();
+        let error = Tsig::from_message(&response).unwrap();
+        assert_eq!(error.rdata.error, TsigError::BADTIME as u16);
+        assert_eq!(error.rdata.other.len(), 6);
+
+        // Invalid MAC
+        let (message, mut raw) = get_request(&zone, &key, now().unwrap());
+        raw[3] ^= 1;
+        let mut tsig = RequestTsig::from_request(&message, &raw, &mut connection)
+            .unwrap()
+            .unwrap();
+        assert_eq!(tsig.error(), TsigError::BADSIG);
+
+        let mut response = Message::to_bytes(get_message(Some(zone.clone())));
+        tsig.sign_response(&mut response).unwrap();
+        assert!(Tsig::from_message(&response).unwrap().mac().is_empty());
+
+        let message = get_message(Some(zone));
+        assert!(RequestTsig::from_request(
+            &message,
+            &Message::to_bytes(message.clone()),
+            &mut connection
+        )
+        .unwrap()
+        .is_none());
+    }
+}
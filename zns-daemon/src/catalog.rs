@@ -0,0 +1,174 @@
+use std::collections::BTreeSet;
+
+use diesel::PgConnection;
+use ring::digest;
+use zns::{
+    errors::ZNSError,
+    labelstring::LabelString,
+    parser::ToBytes,
+    structs::{Class, RData, RRClass, RRType, SoaRData, Type, RR},
+};
+
+use crate::{
+    auth::owners::user_zone, config::Config, db::models::get_names_below, history::get_apex_serial,
+};
+
+/// Version of the catalog zone schema
+/// https://datatracker.ietf.org/doc/html/rfc9432#section-4.2.1
+const CATALOG_VERSION: &str = "2";
+
+/// Name of the catalog zone in `ZNS_CATALOG_ZONE`, if it is enabled
+fn catalog_zone() -> Option<&'static LabelString> {
+    Config::get().catalog_zone.as_ref()
+}
+
+/// Whether the name is the apex of the catalog zone
+pub fn is_catalog_zone(name: &LabelString) -> bool {
+    catalog_zone().is_some_and(|zone| name.len() == zone.len() && name.is_subdomain_of(zone))
+}
+
+/// Whether the name is in the catalog zone
+pub fn in_catalog_zone(name: &LabelString) -> bool {
+    catalog_zone().is_some_and(|zone| name.is_subdomain_of(zone))
+}
+
+/// The zones directly below the authoritative zone which have records
+fn get_member_zones(connection: &mut PgConnection) -> Result<BTreeSet<String>, ZNSError> {
+    Ok(
+        get_names_below(&Config::get().authoritative_zone, connection)?
+            .into_iter()
+            .filter_map(|name| user_zone(&LabelString::from(&name)))
+            .filter(|zone| !in_catalog_zone(zone))
+            .map(|zone| zone.to_string().to_lowercase())
+            .collect(),
+    )
+}
+
+/// Unique label of a member zone, which stays the same as long as the zone exists
+/// https://datatracker.ietf.org/doc/html/rfc9432#section-4.1
+fn member_id(zone: &str) -> String {
+    digest::digest(&digest::SHA256, zone.as_bytes()).as_ref()[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn catalog_rr(name: LabelString, _type: RRType, rdata: Vec<u8>) -> RR {
+    RR {
+        name,
+        _type: Type::Type(_type),
+        class: Class::Class(RRClass::IN),
+        ttl: 0,
+        rdlength: rdata.len() as u16,
+        rdata: RData::Vec(rdata),
+    }
+}
+
+/// SOA of the catalog zone
+pub fn get_catalog_soa(connection: &mut PgConnection) -> Result<RR, ZNSError> {
+    let zone = catalog_zone().ok_or(ZNSError::Refused {
+        message: "Catalog zone is not enabled".to_string(),
+    })?;
+    let rdata = SoaRData {
+        mname: Config::get().authoritative_zone.clone(),
+        rname: LabelString::from("admin.zeus.ugent.be"),
+        // Increases with every version of a zone, so with every change of the members
+        serial: get_apex_serial(connection)?,
+        refresh: 3600,
+        retry: 600,
+        expire: 3600000,
+        minimum: 0,
+    };
+    Ok(catalog_rr(
+        zone.clone(),
+        RRType::SOA,
+        SoaRData::to_bytes(rdata),
+    ))
+}
+
+/// Records of the catalog zone, starting with its SOA: the NS and version records and a PTR
+/// to every zone below the authoritative zone which has records
+/// https://datatracker.ietf.org/doc/html/rfc9432#section-4
+pub fn get_catalog(connection: &mut PgConnection) -> Result<Vec<RR>, ZNSError> {
+    let soa = get_catalog_soa(connection)?;
+    let zone = soa.name.clone();
+
+    let mut rrs = vec![
+        soa,
+        // The NS record is required, but the catalog zone is not meant to be resolved
+        catalog_rr(
+            zone.clone(),
+            RRType::NS,
+            LabelString::to_bytes(LabelString::from("invalid")),
+        ),
+        catalog_rr(
+            LabelString::from(&format!("version.{}", zone)),
+            RRType::TXT,
+            [vec![CATALOG_VERSION.len() as u8], CATALOG_VERSION.into()].concat(),
+        ),
+    ];
+    for member in get_member_zones(connection)? {
+        rrs.push(catalog_rr(
+            LabelString::from(&format!("{}.zones.{}", member_id(&member), zone)),
+            RRType::PTR,
+            LabelString::to_bytes(LabelString::from(&member)),
+        ));
+    }
+    Ok(rrs)
+}
+
+#[cfg(test)]
+mod tests {
+    use zns::test_utils::get_rr;
+
+    use crate::{
+        db::{lib::tests::get_test_connection, models::insert_into_database},
+        history::store_next_version,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_member_zones() {
+        let mut connection = get_test_connection();
+        let bob = Config::get().authoritative_zone.prepend("bob".to_string());
+        let alice = Config::get()
+            .authoritative_zone
+            .prepend("Alice".to_string());
+
+        let before = get_member_zones(&mut connection).unwrap();
+        let serial = get_apex_serial(&mut connection).unwrap();
+
+        for name in [
+            bob.clone(),
+            bob.prepend("www".to_string()),
+            alice.prepend("a".to_string()).prepend("b".to_string()),
+        ] {
+            insert_into_database(&get_rr(Some(name)), &mut connection).unwrap();
+        }
+        store_next_version(&bob, &mut connection).unwrap();
+
+        let members = get_member_zones(&mut connection).unwrap();
+        assert!(members.is_superset(&before));
+        assert!(members.contains(&bob.to_string()));
+        assert!(members.contains(&alice.to_string().to_lowercase()));
+        // Names in a zone are not zones themselves
+        assert!(!members.contains(&bob.prepend("www".to_string()).to_string()));
+
+        // Every change of a zone increases the serial of the catalog
+        assert!(get_apex_serial(&mut connection).unwrap() > serial);
+    }
+
+    #[test]
+    fn test_member_id() {
+        assert_eq!(member_id("bob.users.zeus.gent").len(), 16);
+        assert_eq!(
+            member_id("bob.users.zeus.gent"),
+            member_id("bob.users.zeus.gent")
+        );
+        assert_ne!(
+            member_id("bob.users.zeus.gent"),
+            member_id("alice.users.zeus.gent")
+        );
+    }
+}
@@ -1,17 +1,54 @@
-use std::{env, net::IpAddr, sync::OnceLock};
+use std::{
+    env,
+    net::{IpAddr, SocketAddr},
+    sync::OnceLock,
+};
 
 use dotenvy::dotenv;
-use zns::labelstring::LabelString;
+use zns::{labelstring::LabelString, parser::parse_types, structs::Type};
+
+use crate::auth::{ProviderConfig, ServerKey, TransferRule};
 
 static CONFIG: OnceLock<Config> = OnceLock::new();
 
+const DEFAULT_ALLOWED_TYPES: &str = "A,AAAA,CNAME,TXT,MX,SRV,CAA,KEY,DNSKEY,CDS,CDNSKEY";
+
 pub struct Config {
     pub zauth_url: Option<String>,
+    pub zauth_timeout: u64,
+    pub zauth_cache_ttl: u64,
+    pub zauth_negative_ttl: u64,
+    pub zauth_stale_ttl: u64,
+    pub key_url_timeout: u64,
+    pub key_url_cache_ttl: u64,
+    pub key_url_negative_ttl: u64,
+    pub key_url_stale_ttl: u64,
+    pub key_providers: Vec<ProviderConfig>,
     pub db_uri: String,
     pub authoritative_zone: LabelString,
     pub port: u16,
     pub address: IpAddr,
     pub default_soa: bool,
+    pub sig_max_validity: u64,
+    pub sig_max_skew: u64,
+    pub server_key: Option<ServerKey>,
+    pub max_records: u32,
+    pub max_rdata_bytes: u32,
+    pub max_names: u32,
+    pub allowed_types: Vec<Type>,
+    pub denied_types: Vec<Type>,
+    pub delegation: bool,
+    pub apex_wildcards: bool,
+    pub zone_versions: u32,
+    pub notify: Vec<SocketAddr>,
+    pub notify_retries: u32,
+    pub notify_timeout: u64,
+    pub notify_interval: u64,
+    pub primary: Option<SocketAddr>,
+    pub primary_tsig: Option<LabelString>,
+    pub secondary_zones: Vec<LabelString>,
+    pub transfer_acl: Vec<TransferRule>,
+    pub catalog_zone: Option<LabelString>,
 }
 
 impl Config {
@@ -24,9 +61,48 @@ impl Config {
     pub fn get() -> &'static Config {
         CONFIG.get_or_init(|| {
             dotenv().ok();
-            Config {
+            let config = Config {
                 db_uri: env::var("DATABASE_URL").expect("DATABASE_URL must be set"),
                 zauth_url: env::var("ZAUTH_URL").ok(),
+                zauth_timeout: env::var("ZNS_ZAUTH_TIMEOUT")
+                    .map(|v| v.parse().expect("ZNS_ZAUTH_TIMEOUT is invalid"))
+                    .unwrap_or(5),
+                zauth_cache_ttl: env::var("ZNS_ZAUTH_CACHE_TTL")
+                    .map(|v| v.parse().expect("ZNS_ZAUTH_CACHE_TTL is invalid"))
+                    .unwrap_or(300),
+                zauth_negative_ttl: env::var("ZNS_ZAUTH_NEGATIVE_TTL")
+                    .map(|v| v.parse().expect("ZNS_ZAUTH_NEGATIVE_TTL is invalid"))
+                    .unwrap_or(60),
+                zauth_stale_ttl: env::var("ZNS_ZAUTH_STALE_TTL")
+                    .map(|v| v.parse().expect("ZNS_ZAUTH_STALE_TTL is invalid"))
+                    .unwrap_or(3600),
+                key_url_timeout: env::var("ZNS_KEY_URL_TIMEOUT")
+                    .map(|v| v.parse().expect("ZNS_KEY_URL_TIMEOUT is invalid"))
+                    .unwrap_or(5),
+                key_url_cache_ttl: env::var("ZNS_KEY_URL_CACHE_TTL")
+                    .map(|v| v.parse().expect("ZNS_KEY_URL_CACHE_TTL is invalid"))
+                    .unwrap_or(300),
+                key_url_negative_ttl: env::var("ZNS_KEY_URL_NEGATIVE_TTL")
+                    .map(|v| v.parse().expect("ZNS_KEY_URL_NEGATIVE_TTL is invalid"))
+                    .unwrap_or(60),
+                key_url_stale_ttl: env::var("ZNS_KEY_URL_STALE_TTL")
+                    .map(|v| v.parse().expect("ZNS_KEY_URL_STALE_TTL is invalid"))
+                    .unwrap_or(3600),
+                key_providers: env::var("ZNS_KEY_PROVIDERS")
+                    .ok()
+                    .or_else(|| env::var("ZAUTH_URL").ok().map(|_| String::from("zauth")))
+                    .map(|providers| {
+                        providers
+                            .split(',')
+                            .filter(|provider| !provider.trim().is_empty())
+                            .map(|provider| {
+                                provider.parse().unwrap_or_else(|e| {
+                                    panic!("ZNS_KEY_PROVIDERS is invalid: {}", e)
+                                })
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default(),
                 authoritative_zone: LabelString::from(&env::var("ZONE").expect("ZONE must be set")),
                 port: env::var("ZNS_PORT")
                     .map(|v| v.parse::<u16>().expect("ZNS_PORT is invalid"))
@@ -39,7 +115,161 @@ impl Config {
                     .unwrap_or(String::from("true"))
                     .parse()
                     .expect("ZNS_DEFAULT_SOA should have value `true` or `false`"),
+                sig_max_validity: env::var("ZNS_SIG_MAX_VALIDITY")
+                    .map(|v| v.parse().expect("ZNS_SIG_MAX_VALIDITY is invalid"))
+                    .unwrap_or(3600),
+                sig_max_skew: env::var("ZNS_SIG_MAX_SKEW")
+                    .map(|v| v.parse().expect("ZNS_SIG_MAX_SKEW is invalid"))
+                    .unwrap_or(300),
+                server_key: env::var("ZNS_SERVER_KEY").ok().map(|path| {
+                    ServerKey::from_files(&path)
+                        .unwrap_or_else(|e| panic!("ZNS_SERVER_KEY is invalid: {}", e))
+                }),
+                max_records: env::var("ZNS_MAX_RECORDS")
+                    .map(|v| v.parse().expect("ZNS_MAX_RECORDS is invalid"))
+                    .unwrap_or(1000),
+                max_rdata_bytes: env::var("ZNS_MAX_RDATA_BYTES")
+                    .map(|v| v.parse().expect("ZNS_MAX_RDATA_BYTES is invalid"))
+                    .unwrap_or(65536),
+                max_names: env::var("ZNS_MAX_NAMES")
+                    .map(|v| v.parse().expect("ZNS_MAX_NAMES is invalid"))
+                    .unwrap_or(256),
+                allowed_types: parse_types(
+                    &env::var("ZNS_ALLOWED_TYPES").unwrap_or(String::from(DEFAULT_ALLOWED_TYPES)),
+                )
+                .unwrap_or_else(|e| panic!("ZNS_ALLOWED_TYPES is invalid: {}", e)),
+                denied_types: parse_types(&env::var("ZNS_DENIED_TYPES").unwrap_or_default())
+                    .unwrap_or_else(|e| panic!("ZNS_DENIED_TYPES is invalid: {}", e)),
+                delegation: env::var("ZNS_DELEGATION")
+                    .unwrap_or(String::from("false"))
+                    .parse()
+                    .expect("ZNS_DELEGATION should have value `true` or `false`"),
+                apex_wildcards: env::var("ZNS_APEX_WILDCARDS")
+                    .unwrap_or(String::from("false"))
+                    .parse()
+                    .expect("ZNS_APEX_WILDCARDS should have value `true` or `false`"),
+                zone_versions: env::var("ZNS_ZONE_VERSIONS")
+                    .map(|v| v.parse().expect("ZNS_ZONE_VERSIONS is invalid"))
+                    .unwrap_or(30),
+                notify: env::var("ZNS_NOTIFY")
+                    .unwrap_or_default()
+                    .split(',')
+                    .filter(|address| !address.trim().is_empty())
+                    .map(|address| {
+                        parse_address(address.trim(), 53)
+                            .unwrap_or_else(|| panic!("ZNS_NOTIFY is invalid: {}", address))
+                    })
+                    .collect(),
+                notify_retries: env::var("ZNS_NOTIFY_RETRIES")
+                    .map(|v| v.parse().expect("ZNS_NOTIFY_RETRIES is invalid"))
+                    .unwrap_or(5),
+                notify_timeout: env::var("ZNS_NOTIFY_TIMEOUT")
+                    .map(|v| v.parse().expect("ZNS_NOTIFY_TIMEOUT is invalid"))
+                    .unwrap_or(2)
+                    .max(1),
+                notify_interval: env::var("ZNS_NOTIFY_INTERVAL")
+                    .map(|v| v.parse().expect("ZNS_NOTIFY_INTERVAL is invalid"))
+                    .unwrap_or(5),
+                primary: env::var("ZNS_PRIMARY").ok().map(|address| {
+                    parse_address(&address, 53)
+                        .unwrap_or_else(|| panic!("ZNS_PRIMARY is invalid: {}", address))
+                }),
+                primary_tsig: env::var("ZNS_PRIMARY_TSIG")
+                    .ok()
+                    .map(|name| LabelString::from(name.trim_end_matches('.'))),
+                secondary_zones: env::var("ZNS_SECONDARY_ZONES")
+                    .unwrap_or_default()
+                    .split(',')
+                    .filter(|zone| !zone.trim().is_empty())
+                    .map(|zone| LabelString::from(zone.trim().trim_end_matches('.')))
+                    .collect(),
+                transfer_acl: env::var("ZNS_TRANSFER_ACL")
+                    .unwrap_or_default()
+                    .split(',')
+                    .filter(|rule| !rule.trim().is_empty())
+                    .map(|rule| {
+                        rule.parse()
+                            .unwrap_or_else(|e| panic!("ZNS_TRANSFER_ACL is invalid: {}", e))
+                    })
+                    .collect(),
+                catalog_zone: env::var("ZNS_CATALOG_ZONE")
+                    .ok()
+                    .map(|zone| LabelString::from(zone.trim_end_matches('.'))),
+            };
+
+            // The latest version holds the serial of the zone
+            assert!(
+                config.zone_versions >= 1,
+                "ZNS_ZONE_VERSIONS is invalid: at least 1 version must be kept"
+            );
+            // Responses of the primary are only authenticated with TSIG
+            if config.primary.is_some() {
+                assert!(
+                    config.primary_tsig.is_some(),
+                    "ZNS_PRIMARY requires ZNS_PRIMARY_TSIG"
+                );
+            }
+            for zone in &config.secondary_zones {
+                assert!(
+                    zone.len() == config.authoritative_zone.len() + 1
+                        && zone.is_subdomain_of(&config.authoritative_zone),
+                    "ZNS_SECONDARY_ZONES is invalid: {} is not a zone directly below {}",
+                    zone,
+                    config.authoritative_zone
+                );
+            }
+            if let Some(zone) = &config.catalog_zone {
+                assert!(
+                    !config.authoritative_zone.is_subdomain_of(zone),
+                    "ZNS_CATALOG_ZONE is invalid: {} contains {}",
+                    zone,
+                    config.authoritative_zone
+                );
             }
+            config
         })
     }
 }
+
+/// Socket address of `<ip>`, `<ip>:<port>` or `[<ipv6>]:<port>`, with the default port if it is
+/// not given
+fn parse_address(address: &str, default_port: u16) -> Option<SocketAddr> {
+    address.parse().ok().or_else(|| {
+        address
+            .parse::<IpAddr>()
+            .ok()
+            .map(|ip| SocketAddr::from((ip, default_port)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(
+            parse_address("10.0.0.1", 53),
+            Some(SocketAddr::from(([10, 0, 0, 1], 53)))
+        );
+        assert_eq!(
+            parse_address("10.0.0.1:5333", 53),
+            Some(SocketAddr::from(([10, 0, 0, 1], 5333)))
+        );
+        assert_eq!(
+            parse_address("::1", 53),
+            Some(SocketAddr::from((
+                IpAddr::from([0, 0, 0, 0, 0, 0, 0, 1]),
+                53
+            )))
+        );
+        assert_eq!(
+            parse_address("[::1]:5333", 53),
+            Some(SocketAddr::from((
+                IpAddr::from([0, 0, 0, 0, 0, 0, 0, 1]),
+                5333
+            )))
+        );
+        assert_eq!(parse_address("ns.zeus.gent", 53), None);
+    }
+}
@@ -1,14 +1,27 @@
+use diesel::pg::PgRowByRowLoadingMode;
 use diesel::prelude::*;
 use diesel::sql_types::Text;
 use zns::{
     errors::ZNSError,
     labelstring::LabelString,
     structs::{Class, RData, Type, RR},
+    tsig::{TsigAlgorithm, TsigKey},
 };
 
-use self::schema::records::{self};
+use self::schema::{
+    records::{self},
+    tsig_keys::{self},
+    update_grants::{self},
+    update_log::{self},
+    zone_owners::{self},
+    zone_quotas::{self},
+    zone_serials::{self},
+    zone_type_policies::{self},
+    zone_version_records::{self},
+    zone_versions::{self},
+};
 
-mod schema {
+pub(crate) mod schema {
     diesel::table! {
         records (name, _type, class, rdlength, rdata) {
             name -> Text,
@@ -20,9 +33,95 @@ mod schema {
             rdata -> Binary,
         }
     }
+
+    diesel::table! {
+        tsig_keys (name) {
+            name -> Text,
+            algorithm -> Text,
+            secret -> Binary,
+            zone -> Text,
+        }
+    }
+
+    diesel::table! {
+        update_grants (id) {
+            id -> Integer,
+            identity -> Text,
+            rule -> Text,
+            name -> Text,
+            types -> Text,
+        }
+    }
+
+    diesel::table! {
+        update_log (id) {
+            id -> Integer,
+            zone -> Text,
+            signer -> Text,
+            key -> Text,
+            client -> Text,
+            time -> BigInt,
+            added -> Text,
+            deleted -> Text,
+        }
+    }
+
+    diesel::table! {
+        zone_owners (zone, kind, name) {
+            zone -> Text,
+            kind -> Text,
+            name -> Text,
+        }
+    }
+
+    diesel::table! {
+        zone_quotas (zone) {
+            zone -> Text,
+            max_records -> Nullable<Integer>,
+            max_rdata_bytes -> Nullable<Integer>,
+            max_names -> Nullable<Integer>,
+        }
+    }
+
+    diesel::table! {
+        zone_serials (zone) {
+            zone -> Text,
+            serial -> BigInt,
+        }
+    }
+
+    diesel::table! {
+        zone_type_policies (zone) {
+            zone -> Text,
+            allowed -> Nullable<Text>,
+            denied -> Nullable<Text>,
+        }
+    }
+
+    diesel::table! {
+        zone_versions (zone, serial) {
+            zone -> Text,
+            serial -> BigInt,
+            time -> BigInt,
+        }
+    }
+
+    diesel::table! {
+        zone_version_records (zone, serial, name, _type, class, rdlength, rdata) {
+            zone -> Text,
+            serial -> BigInt,
+            name -> Text,
+            #[sql_name = "type"]
+            _type -> Integer,
+            class -> Integer,
+            ttl -> Integer,
+            rdlength -> Integer,
+            rdata -> Binary,
+        }
+    }
 }
 
-#[derive(Insertable, Queryable, Selectable)]
+#[derive(Clone, Insertable, Queryable, Selectable)]
 #[diesel(table_name = records)]
 pub struct Record {
     pub name: String,
@@ -59,20 +158,23 @@ impl Record {
         query.get_results(db)
     }
 
-    // Returns all records with names ending with given suffix.
-    pub fn get_by_suffix(
-        db: &mut PgConnection,
-        suffix: &str,
+    /// Records of the zone and all zones below it, read from the database one row at a time
+    pub fn iter_by_zone<'a>(
+        db: &'a mut PgConnection,
+        zone: &str,
         class: i32,
-    ) -> Result<Vec<Record>, diesel::result::Error> {
-        let query = records::table
+    ) -> Result<impl Iterator<Item = QueryResult<Record>> + 'a, diesel::result::Error> {
+        let zone = zone.to_lowercase();
+        records::table
             .filter(
-                records::name
-                    .ilike(format!("%{}", suffix))
+                lower(records::name)
+                    .eq(zone.clone())
+                    .or(lower(records::name).like(names_below(&zone)).escape('\\'))
                     .and(records::class.eq(class)),
             )
-            .order(records::name.desc());
-        query.get_results(db)
+            .order(records::name.desc())
+            .select(Record::as_select())
+            .load_iter::<Record, PgRowByRowLoadingMode>(db)
     }
 
     fn create(db: &mut PgConnection, new_record: Record) -> Result<usize, diesel::result::Error> {
@@ -104,6 +206,88 @@ impl Record {
     }
 }
 
+#[derive(Insertable, Queryable, Selectable)]
+#[diesel(table_name = tsig_keys)]
+pub struct TsigKeyRecord {
+    pub name: String,
+    pub algorithm: String,
+    pub secret: Vec<u8>,
+    pub zone: String,
+}
+
+#[derive(Clone, Insertable, Queryable, Selectable)]
+#[diesel(table_name = update_grants)]
+pub struct GrantRecord {
+    pub identity: String,
+    pub rule: String,
+    pub name: String,
+    pub types: String,
+}
+
+/// Applied update, `added` and `deleted` contain a record per line
+#[derive(Debug, Insertable, Queryable, Selectable)]
+#[diesel(table_name = update_log)]
+pub struct UpdateLogRecord {
+    pub zone: String,
+    pub signer: String,
+    pub key: String,
+    pub client: String,
+    /// Unix timestamp in seconds
+    pub time: i64,
+    pub added: String,
+    pub deleted: String,
+}
+
+#[derive(Insertable, Queryable, Selectable)]
+#[diesel(table_name = zone_owners)]
+pub struct ZoneOwnerRecord {
+    pub zone: String,
+    pub kind: String,
+    pub name: String,
+}
+
+/// Limits of a zone which override the defaults, `None` if the default is used
+#[derive(Insertable, Queryable, Selectable)]
+#[diesel(table_name = zone_quotas)]
+pub struct ZoneQuotaRecord {
+    pub zone: String,
+    pub max_records: Option<i32>,
+    pub max_rdata_bytes: Option<i32>,
+    pub max_names: Option<i32>,
+}
+
+/// Types which are allowed or denied in a zone, `None` if the global policy is used
+#[derive(Insertable, Queryable, Selectable)]
+#[diesel(table_name = zone_type_policies)]
+pub struct ZoneTypePolicyRecord {
+    pub zone: String,
+    pub allowed: Option<String>,
+    pub denied: Option<String>,
+}
+
+/// Snapshot of a zone, the zone name is stored in lowercase
+#[derive(Debug, Insertable, Queryable, Selectable)]
+#[diesel(table_name = zone_versions)]
+pub struct ZoneVersionRecord {
+    pub zone: String,
+    pub serial: i64,
+    /// Unix timestamp in seconds
+    pub time: i64,
+}
+
+#[derive(Insertable, Queryable, Selectable)]
+#[diesel(table_name = zone_version_records)]
+struct VersionedRecord {
+    zone: String,
+    serial: i64,
+    name: String,
+    _type: i32,
+    class: i32,
+    ttl: i32,
+    rdlength: i32,
+    rdata: Vec<u8>,
+}
+
 const MAX_RDATA_SIZE: usize = 1000;
 
 pub fn insert_into_database(rr: &RR, connection: &mut PgConnection) -> Result<(), ZNSError> {
@@ -154,6 +338,38 @@ pub fn get_from_database(
         .collect())
 }
 
+/// LIKE pattern of the names below the zone, escaping the wildcards `%` and `_` in the zone
+fn names_below(zone: &str) -> String {
+    format!(
+        "%.{}",
+        zone.replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    )
+}
+
+/// Whether the name exists: it has records, or names below it have records
+/// https://datatracker.ietf.org/doc/html/rfc4592#section-2.2.2
+pub fn name_exists(
+    name: &LabelString,
+    class: Class,
+    connection: &mut PgConnection,
+) -> Result<bool, ZNSError> {
+    let name = name.to_string().to_lowercase();
+    diesel::select(diesel::dsl::exists(
+        records::table.filter(
+            lower(records::name)
+                .eq(name.clone())
+                .or(lower(records::name).like(names_below(&name)).escape('\\'))
+                .and(records::class.eq(i32::from(class))),
+        ),
+    ))
+    .get_result(connection)
+    .map_err(|e| ZNSError::Servfail {
+        message: e.to_string(),
+    })
+}
+
 //TODO: cleanup models
 pub fn delete_from_database(
     name: &LabelString,
@@ -171,6 +387,603 @@ pub fn delete_from_database(
     );
 }
 
+/// TSIG key with the given name and the zone it is allowed to update
+pub fn get_tsig_key(
+    name: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<Option<(TsigKey, LabelString)>, ZNSError> {
+    let record: Option<TsigKeyRecord> = tsig_keys::table
+        .filter(lower(tsig_keys::name).eq(name.to_string().to_lowercase()))
+        .first(connection)
+        .optional()
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })?;
+
+    record.map(tsig_key_from_record).transpose()
+}
+
+fn tsig_key_from_record(record: TsigKeyRecord) -> Result<(TsigKey, LabelString), ZNSError> {
+    let algorithm = TsigAlgorithm::from_name(&record.algorithm).ok_or(ZNSError::Servfail {
+        message: format!("Unsupported TSIG algorithm: {}", record.algorithm),
+    })?;
+    Ok((
+        TsigKey {
+            name: LabelString::from(record.name.trim_end_matches('.')),
+            algorithm,
+            secret: record.secret,
+        },
+        LabelString::from(record.zone.trim_end_matches('.')),
+    ))
+}
+
+/// TSIG keys of the zone and all zones below it, with the zone of each key
+pub fn get_tsig_keys(
+    zone: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<Vec<(TsigKey, LabelString)>, ZNSError> {
+    let zone = zone.to_string().to_lowercase();
+    let records: Vec<TsigKeyRecord> = tsig_keys::table
+        .filter(
+            lower(tsig_keys::zone)
+                .eq(&zone)
+                .or(lower(tsig_keys::zone).like(names_below(&zone)).escape('\\')),
+        )
+        .order(tsig_keys::name)
+        .select(TsigKeyRecord::as_select())
+        .get_results(connection)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })?;
+
+    records.into_iter().map(tsig_key_from_record).collect()
+}
+
+pub fn insert_tsig_key(
+    key: &TsigKey,
+    zone: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    diesel::insert_into(tsig_keys::table)
+        .values(TsigKeyRecord {
+            name: key.name.to_string(),
+            algorithm: key.algorithm.name().to_string(),
+            secret: key.secret.clone(),
+            zone: zone.to_string(),
+        })
+        .execute(connection)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })?;
+    Ok(())
+}
+
+/// Returns the number of deleted keys
+pub fn delete_tsig_key(
+    name: &LabelString,
+    zone: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<usize, ZNSError> {
+    diesel::delete(
+        tsig_keys::table.filter(
+            lower(tsig_keys::name)
+                .eq(name.to_string().to_lowercase())
+                .and(lower(tsig_keys::zone).eq(zone.to_string().to_lowercase())),
+        ),
+    )
+    .execute(connection)
+    .map_err(|e| ZNSError::Servfail {
+        message: e.to_string(),
+    })
+}
+
+/// Update-policy grants of the key with the given name
+pub fn get_grants(
+    identity: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<Vec<GrantRecord>, ZNSError> {
+    update_grants::table
+        .filter(lower(update_grants::identity).eq(identity.to_string().to_lowercase()))
+        .select(GrantRecord::as_select())
+        .get_results(connection)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })
+}
+
+pub fn insert_grant(grant: &GrantRecord, connection: &mut PgConnection) -> Result<(), ZNSError> {
+    diesel::insert_into(update_grants::table)
+        .values(grant)
+        .execute(connection)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })?;
+    Ok(())
+}
+
+/// Returns the number of deleted grants
+pub fn delete_grant(grant: &GrantRecord, connection: &mut PgConnection) -> Result<usize, ZNSError> {
+    diesel::delete(
+        update_grants::table.filter(
+            update_grants::identity
+                .eq(&grant.identity)
+                .and(update_grants::rule.eq(&grant.rule))
+                .and(update_grants::name.eq(&grant.name))
+                .and(update_grants::types.eq(&grant.types)),
+        ),
+    )
+    .execute(connection)
+    .map_err(|e| ZNSError::Servfail {
+        message: e.to_string(),
+    })
+}
+
+/// Owners of the given zones, as stored in the database
+pub fn get_zone_owners(
+    zones: &[LabelString],
+    connection: &mut PgConnection,
+) -> Result<Vec<ZoneOwnerRecord>, ZNSError> {
+    zone_owners::table
+        .filter(
+            lower(zone_owners::zone).eq_any(
+                zones
+                    .iter()
+                    .map(|zone| zone.to_string().to_lowercase())
+                    .collect::<Vec<String>>(),
+            ),
+        )
+        .select(ZoneOwnerRecord::as_select())
+        .get_results(connection)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })
+}
+
+pub fn insert_zone_owner(
+    owner: &ZoneOwnerRecord,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    diesel::insert_into(zone_owners::table)
+        .values(owner)
+        .execute(connection)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })?;
+    Ok(())
+}
+
+/// Returns the number of deleted owners
+pub fn delete_zone_owner(
+    owner: &ZoneOwnerRecord,
+    connection: &mut PgConnection,
+) -> Result<usize, ZNSError> {
+    diesel::delete(
+        zone_owners::table.filter(
+            lower(zone_owners::zone)
+                .eq(owner.zone.to_lowercase())
+                .and(zone_owners::kind.eq(&owner.kind))
+                .and(lower(zone_owners::name).eq(owner.name.to_lowercase())),
+        ),
+    )
+    .execute(connection)
+    .map_err(|e| ZNSError::Servfail {
+        message: e.to_string(),
+    })
+}
+
+pub fn get_zone_quota(
+    zone: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<Option<ZoneQuotaRecord>, ZNSError> {
+    zone_quotas::table
+        .filter(lower(zone_quotas::zone).eq(zone.to_string().to_lowercase()))
+        .select(ZoneQuotaRecord::as_select())
+        .first(connection)
+        .optional()
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })
+}
+
+/// Inserts or replaces the limits of the zone, the limits are removed if none are set
+pub fn set_zone_quota(
+    quota: &ZoneQuotaRecord,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    let empty =
+        quota.max_records.is_none() && quota.max_rdata_bytes.is_none() && quota.max_names.is_none();
+
+    diesel::delete(
+        zone_quotas::table.filter(lower(zone_quotas::zone).eq(quota.zone.to_lowercase())),
+    )
+    .execute(connection)
+    .and_then(|_| match empty {
+        true => Ok(0),
+        false => diesel::insert_into(zone_quotas::table)
+            .values(quota)
+            .execute(connection),
+    })
+    .map_err(|e| ZNSError::Servfail {
+        message: e.to_string(),
+    })?;
+    Ok(())
+}
+
+pub fn get_zone_type_policy(
+    zone: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<Option<ZoneTypePolicyRecord>, ZNSError> {
+    zone_type_policies::table
+        .filter(lower(zone_type_policies::zone).eq(zone.to_string().to_lowercase()))
+        .select(ZoneTypePolicyRecord::as_select())
+        .first(connection)
+        .optional()
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })
+}
+
+/// Inserts or replaces the type policy of the zone, the policy is removed if nothing is set
+pub fn set_zone_type_policy(
+    policy: &ZoneTypePolicyRecord,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    let empty = policy.allowed.is_none() && policy.denied.is_none();
+
+    diesel::delete(
+        zone_type_policies::table
+            .filter(lower(zone_type_policies::zone).eq(policy.zone.to_lowercase())),
+    )
+    .execute(connection)
+    .and_then(|_| match empty {
+        true => Ok(0),
+        false => diesel::insert_into(zone_type_policies::table)
+            .values(policy)
+            .execute(connection),
+    })
+    .map_err(|e| ZNSError::Servfail {
+        message: e.to_string(),
+    })?;
+    Ok(())
+}
+
+pub fn insert_update_log(
+    entry: &UpdateLogRecord,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    diesel::insert_into(update_log::table)
+        .values(entry)
+        .execute(connection)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })?;
+    Ok(())
+}
+
+/// Latest updates of the zone and all zones below it, newest first
+pub fn get_update_log(
+    zone: &LabelString,
+    limit: i64,
+    connection: &mut PgConnection,
+) -> Result<Vec<UpdateLogRecord>, ZNSError> {
+    let zone = zone.to_string().to_lowercase();
+    update_log::table
+        .filter(
+            lower(update_log::zone).eq(&zone).or(lower(update_log::zone)
+                .like(names_below(&zone))
+                .escape('\\')),
+        )
+        .order(update_log::id.desc())
+        .limit(limit)
+        .select(UpdateLogRecord::as_select())
+        .get_results(connection)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })
+}
+
+/// All records of the zone and all zones below it
+pub fn get_zone_records(
+    zone: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<Vec<Record>, ZNSError> {
+    let zone = zone.to_string().to_lowercase();
+    records::table
+        .filter(
+            lower(records::name)
+                .eq(&zone)
+                .or(lower(records::name).like(names_below(&zone)).escape('\\')),
+        )
+        .select(Record::as_select())
+        .get_results(connection)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })
+}
+
+/// Replaces all records of the zone and all zones below it
+pub fn replace_zone_records(
+    zone: &LabelString,
+    records: &[Record],
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    let zone = zone.to_string().to_lowercase();
+    diesel::delete(
+        records::table.filter(
+            lower(records::name)
+                .eq(&zone)
+                .or(lower(records::name).like(names_below(&zone)).escape('\\')),
+        ),
+    )
+    .execute(connection)
+    .and_then(|_| match records.is_empty() {
+        true => Ok(0),
+        false => diesel::insert_into(records::table)
+            .values(records)
+            .execute(connection),
+    })
+    .map_err(|e| ZNSError::Servfail {
+        message: e.to_string(),
+    })?;
+    Ok(())
+}
+
+/// Versions of the zone with their number of records, newest first
+pub fn get_zone_versions(
+    zone: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<Vec<(ZoneVersionRecord, i64)>, ZNSError> {
+    let zone = zone.to_string().to_lowercase();
+    let versions = zone_versions::table
+        .filter(zone_versions::zone.eq(&zone))
+        .order(zone_versions::serial.desc())
+        .select(ZoneVersionRecord::as_select())
+        .get_results(connection);
+    let counts = zone_version_records::table
+        .filter(zone_version_records::zone.eq(&zone))
+        .group_by(zone_version_records::serial)
+        .select((zone_version_records::serial, diesel::dsl::count_star()))
+        .get_results::<(i64, i64)>(connection);
+
+    versions
+        .and_then(|versions| {
+            let counts = counts?;
+            Ok(versions
+                .into_iter()
+                .map(|version| {
+                    let count = counts
+                        .iter()
+                        .find(|(serial, _)| *serial == version.serial)
+                        .map_or(0, |(_, count)| *count);
+                    (version, count)
+                })
+                .collect())
+        })
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })
+}
+
+pub fn get_latest_zone_version(
+    zone: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<Option<ZoneVersionRecord>, ZNSError> {
+    zone_versions::table
+        .filter(zone_versions::zone.eq(zone.to_string().to_lowercase()))
+        .order(zone_versions::serial.desc())
+        .select(ZoneVersionRecord::as_select())
+        .first(connection)
+        .optional()
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })
+}
+
+/// Stores a version of the zone with the given records
+pub fn insert_zone_version(
+    version: &ZoneVersionRecord,
+    records: Vec<Record>,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    let records: Vec<VersionedRecord> = records
+        .into_iter()
+        .map(|record| VersionedRecord {
+            zone: version.zone.clone(),
+            serial: version.serial,
+            name: record.name,
+            _type: record._type,
+            class: record.class,
+            ttl: record.ttl,
+            rdlength: record.rdlength,
+            rdata: record.rdata,
+        })
+        .collect();
+
+    diesel::insert_into(zone_versions::table)
+        .values(version)
+        .execute(connection)
+        .and_then(|_| match records.is_empty() {
+            true => Ok(0),
+            false => diesel::insert_into(zone_version_records::table)
+                .values(&records)
+                .execute(connection),
+        })
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })?;
+    Ok(())
+}
+
+/// Records of a version of the zone, `None` if the version does not exist
+pub fn get_zone_version_records(
+    zone: &LabelString,
+    serial: i64,
+    connection: &mut PgConnection,
+) -> Result<Option<Vec<Record>>, ZNSError> {
+    let zone = zone.to_string().to_lowercase();
+    let exists = zone_versions::table
+        .filter(
+            zone_versions::zone
+                .eq(&zone)
+                .and(zone_versions::serial.eq(serial)),
+        )
+        .count()
+        .get_result::<i64>(connection)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })?;
+    if exists == 0 {
+        return Ok(None);
+    }
+
+    let records: Vec<VersionedRecord> = zone_version_records::table
+        .filter(
+            zone_version_records::zone
+                .eq(&zone)
+                .and(zone_version_records::serial.eq(serial)),
+        )
+        .select(VersionedRecord::as_select())
+        .get_results(connection)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })?;
+
+    Ok(Some(
+        records
+            .into_iter()
+            .map(|record| Record {
+                name: record.name,
+                _type: record._type,
+                class: record.class,
+                ttl: record.ttl,
+                rdlength: record.rdlength,
+                rdata: record.rdata,
+            })
+            .collect(),
+    ))
+}
+
+/// Deletes the versions of the zone with a serial lower than or equal to the given serial
+pub fn delete_zone_versions(
+    zone: &LabelString,
+    serial: i64,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    diesel::delete(
+        zone_versions::table.filter(
+            zone_versions::zone
+                .eq(zone.to_string().to_lowercase())
+                .and(zone_versions::serial.le(serial)),
+        ),
+    )
+    .execute(connection)
+    .map_err(|e| ZNSError::Servfail {
+        message: e.to_string(),
+    })?;
+    Ok(())
+}
+
+/// Number of records, total size of the RDATA and number of distinct names
+/// of the zone and all zones below it
+pub fn get_zone_usage(
+    zone: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<(i64, i64, i64), ZNSError> {
+    let zone = zone.to_string().to_lowercase();
+    let (count, size, names): (i64, Option<i64>, i64) = records::table
+        .filter(
+            lower(records::name)
+                .eq(&zone)
+                .or(lower(records::name).like(names_below(&zone)).escape('\\')),
+        )
+        .select((
+            diesel::dsl::count_star(),
+            diesel::dsl::sum(records::rdlength),
+            diesel::dsl::count(lower(records::name)).aggregate_distinct(),
+        ))
+        .first(connection)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })?;
+    Ok((count, size.unwrap_or(0), names))
+}
+
+/// Distinct lowercase names of the records below the zone
+pub fn get_names_below(
+    zone: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<Vec<String>, ZNSError> {
+    records::table
+        .filter(
+            lower(records::name)
+                .like(names_below(&zone.to_string().to_lowercase()))
+                .escape('\\'),
+        )
+        .select(lower(records::name))
+        .distinct()
+        .load(connection)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })
+}
+
+/// Distinct lowercase names below the zone which have records of the type
+pub fn get_names_with_type_below(
+    zone: &LabelString,
+    _type: Type,
+    connection: &mut PgConnection,
+) -> Result<Vec<String>, ZNSError> {
+    records::table
+        .filter(
+            lower(records::name)
+                .like(names_below(&zone.to_string().to_lowercase()))
+                .escape('\\')
+                .and(records::_type.eq(i32::from(_type))),
+        )
+        .select(lower(records::name))
+        .distinct()
+        .load(connection)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })
+}
+
+/// Serial of a zone which does not have versions itself, e.g. the authoritative zone
+pub fn get_zone_serial(
+    zone: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<Option<i64>, ZNSError> {
+    zone_serials::table
+        .filter(zone_serials::zone.eq(zone.to_string().to_lowercase()))
+        .select(zone_serials::serial)
+        .first(connection)
+        .optional()
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })
+}
+
+/// Increments the serial of the zone, starting from `initial`, returns the new serial
+pub fn increment_zone_serial(
+    zone: &LabelString,
+    initial: i64,
+    connection: &mut PgConnection,
+) -> Result<i64, ZNSError> {
+    diesel::insert_into(zone_serials::table)
+        .values((
+            zone_serials::zone.eq(zone.to_string().to_lowercase()),
+            zone_serials::serial.eq(initial + 1),
+        ))
+        .on_conflict(zone_serials::zone)
+        .do_update()
+        .set(zone_serials::serial.eq(zone_serials::serial + 1))
+        .returning(zone_serials::serial)
+        .get_result(connection)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })
+}
+
 impl From<Record> for Option<RR> {
     fn from(record: Record) -> Self {
         RData::from_safe(&record.rdata, &Type::from(record._type as u16))
@@ -193,7 +1006,7 @@ mod tests {
 
     use super::*;
 
-    use crate::db::lib::tests::get_test_connection;
+    use crate::{config::Config, db::lib::tests::get_test_connection};
 
     #[test]
     fn test() {
@@ -232,4 +1045,30 @@ mod tests {
         assert!(insert_into_database(&rr, &mut connection).is_ok());
         assert!(insert_into_database(&rr, &mut connection).is_err());
     }
+
+    #[test]
+    fn test_name_exists() {
+        let mut connection = get_test_connection();
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let rr = get_rr(Some(
+            zone.prepend("www".to_string()).prepend("a".to_string()),
+        ));
+        insert_into_database(&rr, &mut connection).unwrap();
+
+        for name in [
+            rr.name.clone(),
+            zone.prepend("www".to_string()),
+            zone.clone(),
+        ] {
+            assert!(name_exists(&name, rr.class.clone(), &mut connection).unwrap());
+        }
+
+        // Wildcards of LIKE in the name are matched literally
+        for name in [
+            zone.prepend("ww_".to_string()),
+            zone.prepend("%".to_string()),
+        ] {
+            assert!(!name_exists(&name, rr.class.clone(), &mut connection).unwrap());
+        }
+    }
 }
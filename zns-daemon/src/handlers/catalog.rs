@@ -0,0 +1,78 @@
+use std::net::IpAddr;
+
+use diesel::PgConnection;
+use zns::{
+    errors::ZNSError,
+    structs::{Message, RRType, Type},
+};
+
+use crate::{
+    auth::transfer_allowed,
+    catalog::{get_catalog, get_catalog_soa, is_catalog_zone},
+};
+
+use super::ResponseHandler;
+
+/// Queries for the generated catalog zone, which can be transferred by the secondaries in the
+/// transfer ACL
+/// https://datatracker.ietf.org/doc/html/rfc9432
+pub struct CatalogHandler {}
+
+impl ResponseHandler for CatalogHandler {
+    async fn handle(
+        message: &Message,
+        raw: &[u8],
+        client: IpAddr,
+        connection: &mut PgConnection,
+    ) -> Result<Message, ZNSError> {
+        let mut response = message.clone();
+
+        if message.header.qdcount != 1 {
+            return Err(ZNSError::Refused {
+                message: "QDCOUNT must be one".to_string(),
+            });
+        }
+
+        let question = &message.question[0];
+        let transfer = [Type::Type(RRType::AXFR), Type::Type(RRType::IXFR)];
+        if transfer.contains(&question.qtype) {
+            if !is_catalog_zone(&question.qname) {
+                return Err(ZNSError::NotAuth {
+                    message: format!("{} is not a zone", question.qname),
+                });
+            }
+            if !transfer_allowed(message, &question.qname, raw, client, connection)? {
+                return Err(ZNSError::Refused {
+                    message: "Not Authorized".to_string(),
+                });
+            }
+            // AXFR is streamed by the resolver. The catalog has no versions, so IXFR is answered
+            // with the current SOA, which the resolver follows with the whole catalog over TCP
+            // if the client is not up to date
+            // https://datatracker.ietf.org/doc/html/rfc1995#section-4
+            if question.qtype == Type::Type(RRType::IXFR) {
+                response.authority = vec![];
+                response.header.nscount = 0;
+                response.extend_answer(vec![get_catalog_soa(connection)?]);
+            }
+            return Ok(response);
+        }
+
+        let rrs: Vec<_> = get_catalog(connection)?
+            .into_iter()
+            .filter(|rr| rr.name == question.qname)
+            .collect();
+        if rrs.is_empty() {
+            return Err(ZNSError::NXDomain {
+                domain: question.qname.to_string(),
+                qtype: question.qtype.clone(),
+            });
+        }
+        response.extend_answer(
+            rrs.into_iter()
+                .filter(|rr| [rr._type.clone(), Type::Type(RRType::ANY)].contains(&question.qtype))
+                .collect(),
+        );
+        Ok(response)
+    }
+}
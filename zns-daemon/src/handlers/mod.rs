@@ -1,3 +1,5 @@
+use std::net::IpAddr;
+
 use diesel::PgConnection;
 
 use zns::{
@@ -5,17 +7,25 @@ use zns::{
     structs::{Message, Opcode},
 };
 
-use crate::config::Config;
+use crate::{catalog::in_catalog_zone, config::Config};
 
-use self::{query::QueryHandler, update::UpdateHandler};
+use self::{
+    catalog::CatalogHandler, notify::NotifyHandler, query::QueryHandler, update::UpdateHandler,
+};
 
+mod catalog;
+mod notify;
 mod query;
 mod update;
 
+pub use query::{get_soa, is_full_transfer, split_transfer, stream_zone_transfer};
+pub use update::{add_grant, list_grants, remove_grant, Grant};
+
 pub trait ResponseHandler {
     async fn handle(
         message: &Message,
         raw: &[u8],
+        client: IpAddr,
         connection: &mut PgConnection,
     ) -> Result<Message, ZNSError>;
 }
@@ -26,8 +36,19 @@ impl ResponseHandler for Handler {
     async fn handle(
         message: &Message,
         raw: &[u8],
+        client: IpAddr,
         connection: &mut PgConnection,
     ) -> Result<Message, ZNSError> {
+        // The catalog zone is generated, and does not have to be in the authoritative zone
+        if message.question.iter().any(|q| in_catalog_zone(&q.qname)) {
+            return match message.get_opcode() {
+                Ok(Opcode::QUERY) => CatalogHandler::handle(message, raw, client, connection).await,
+                _ => Err(ZNSError::Refused {
+                    message: "The catalog zone is generated from the user zones".to_string(),
+                }),
+            };
+        }
+
         // Check for a question the server is not autoritative for
         if let Some(qname) = message.not_authoritative(&Config::get().authoritative_zone) {
             return Err(ZNSError::NotAuth { message: qname });
@@ -36,8 +57,9 @@ impl ResponseHandler for Handler {
         match message.get_opcode() {
             //TODO: implement this in Opcode
             Ok(opcode) => match opcode {
-                Opcode::QUERY => QueryHandler::handle(message, raw, connection).await,
-                Opcode::UPDATE => UpdateHandler::handle(message, raw, connection).await,
+                Opcode::QUERY => QueryHandler::handle(message, raw, client, connection).await,
+                Opcode::UPDATE => UpdateHandler::handle(message, raw, client, connection).await,
+                Opcode::NOTIFY => NotifyHandler::handle(message, raw, client, connection).await,
             },
             Err(e) => Err(ZNSError::Formerr {
                 message: e.to_string(),
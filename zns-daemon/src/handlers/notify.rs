@@ -0,0 +1,54 @@
+use std::net::IpAddr;
+
+use diesel::PgConnection;
+use zns::{
+    errors::ZNSError,
+    structs::{Message, RRType, Type},
+};
+
+use crate::{config::Config, secondary::Secondary};
+
+use super::ResponseHandler;
+
+/// NOTIFY from the primary, the zone is refreshed immediately
+/// https://datatracker.ietf.org/doc/html/rfc1996#section-3.11
+pub struct NotifyHandler {}
+
+impl ResponseHandler for NotifyHandler {
+    async fn handle(
+        message: &Message,
+        _raw: &[u8],
+        client: IpAddr,
+        _connection: &mut PgConnection,
+    ) -> Result<Message, ZNSError> {
+        let mut response = message.clone();
+
+        let primary = Config::get().primary.ok_or(ZNSError::NotImp {
+            object: String::from("Notify Handler"),
+            message: "Not a secondary".to_string(),
+        })?;
+
+        if client != primary.ip() {
+            return Err(ZNSError::Refused {
+                message: "NOTIFY is only accepted from the primary".to_string(),
+            });
+        }
+
+        if message.header.qdcount != 1 || message.question[0].qtype != Type::Type(RRType::SOA) {
+            return Err(ZNSError::Formerr {
+                message: "NOTIFY must have one SOA question".to_string(),
+            });
+        }
+
+        let zone = &message.question[0].qname;
+        if !Secondary::get().refresh(zone) {
+            return Err(ZNSError::NotAuth {
+                message: format!("{} is not a secondary zone", zone),
+            });
+        }
+
+        response.answer = vec![];
+        response.header.ancount = 0;
+        Ok(response)
+    }
+}
@@ -1,21 +1,43 @@
+use std::net::IpAddr;
+
+use diesel::PgConnection;
 use zns::{
     errors::ZNSError,
-    structs::{RRType, Type, RR},
+    labelstring::LabelString,
+    parser::ToBytes,
+    structs::{Class, Message, RRType, Type, RR},
 };
 
-use crate::{auth::verify_authorization, db::models::Record, handlers::ResponseHandler};
+use crate::{
+    auth::{transfer_allowed, verify_authorization},
+    catalog::{get_catalog, get_catalog_soa, is_catalog_zone},
+    config::Config,
+    db::models::Record,
+    handlers::ResponseHandler,
+};
 
-use super::get_default_soa;
+use super::{
+    delegation::{find_zone_cut, get_zone_cuts, is_occluded},
+    get_default_soa,
+};
 
+/// Maximum size of the records in one message of a zone transfer, which leaves room for the
+/// header, question and signature in a TCP message of at most 65535 bytes
+const MAX_TRANSFER_SIZE: usize = 16384;
+
+/// Authorizes zone transfers, the records are streamed by the resolver with
+/// [`stream_zone_transfer`] as a sequence of messages
+/// https://datatracker.ietf.org/doc/html/rfc5936
 pub struct AXFRHandler {}
 
 impl ResponseHandler for AXFRHandler {
     async fn handle(
-        message: &zns::structs::Message,
+        message: &Message,
         raw: &[u8],
-        connection: &mut diesel::PgConnection,
-    ) -> Result<zns::structs::Message, zns::errors::ZNSError> {
-        let mut response = message.clone();
+        client: IpAddr,
+        connection: &mut PgConnection,
+    ) -> Result<Message, ZNSError> {
+        let response = message.clone();
 
         if message.header.qdcount != 1 {
             return Err(ZNSError::Refused {
@@ -26,35 +48,201 @@ impl ResponseHandler for AXFRHandler {
         let question = &message.question[0];
         let zone = &question.qname;
 
-        if !verify_authorization(message, zone, raw, connection).await? {
+        // Secondaries in the transfer ACL may transfer every zone, other clients need to be
+        // authorized for the zone
+        if !transfer_allowed(message, zone, raw, client, connection)?
+            && verify_authorization(message, zone, raw, connection)
+                .await?
+                .is_none()
+        {
             return Err(ZNSError::Refused {
                 message: "Not Authorized".to_string(),
             });
         }
 
-        //TODO: TC header flag MUST be 0
+        if find_zone_cut(zone, &question.qclass, Config::get().delegation, connection)?.is_some() {
+            return Err(ZNSError::NotAuth {
+                message: format!("{} is delegated", zone),
+            });
+        }
+
+        // Fails early if the zone has no SOA
+        get_default_soa(zone, connection)?;
+
+        Ok(response)
+    }
+}
+
+/// Splits the records of an incremental zone transfer in batches of at most `MAX_TRANSFER_SIZE`
+/// bytes, which are sent as a sequence of messages like a full zone transfer
+/// https://datatracker.ietf.org/doc/html/rfc1995#section-4
+pub fn split_transfer(rrs: Vec<RR>) -> Vec<Vec<RR>> {
+    let mut batches: Vec<Vec<RR>> = vec![];
+    let mut size = 0;
+    for rr in rrs {
+        let length = RR::to_bytes(rr.clone()).len();
+        match batches.last_mut() {
+            Some(batch) if size + length <= MAX_TRANSFER_SIZE => {
+                size += length;
+                batch.push(rr);
+            }
+            _ => {
+                size = length;
+                batches.push(vec![rr]);
+            }
+        }
+    }
+    batches
+}
+
+/// Reads the records of the zone, or the generated catalog zone, one at a time and passes them to
+/// `send` in batches of at most `MAX_TRANSFER_SIZE` bytes, starting and ending with the SOA of the
+/// zone. Records hidden by a delegation are left out.
+/// Stops early if `send` returns false.
+pub fn stream_zone_transfer(
+    zone: &LabelString,
+    class: &Class,
+    connection: &mut PgConnection,
+    mut send: impl FnMut(Vec<RR>) -> bool,
+) -> Result<(), ZNSError> {
+    let catalog = is_catalog_zone(zone);
+    let soa = match catalog {
+        true => get_catalog_soa(connection)?,
+        false => get_default_soa(zone, connection)?,
+    };
+    let mut batch = vec![];
+    let mut size = 0;
+
+    let mut push = |rr: RR| {
+        let length = RR::to_bytes(rr.clone()).len();
+        let mut sent = true;
+        if !batch.is_empty() && size + length > MAX_TRANSFER_SIZE {
+            sent = send(std::mem::take(&mut batch));
+            size = 0;
+        }
+        size += length;
+        batch.push(rr);
+        sent
+    };
 
-        let results = Record::get_by_suffix(
-            connection,
-            &zone.to_string(),
-            question.qclass.clone().into(),
-        )
+    if !push(soa.clone()) {
+        return Ok(());
+    }
+    if catalog {
+        for rr in get_catalog(connection)?.into_iter().skip(1) {
+            if !push(rr) {
+                return Ok(());
+            }
+        }
+        if push(soa) {
+            send(batch);
+        }
+        return Ok(());
+    }
+    let cuts = get_zone_cuts(zone, class, Config::get().delegation, connection)?;
+    let records = Record::iter_by_zone(connection, &zone.to_string(), class.clone().into())
         .map_err(|e| ZNSError::Servfail {
             message: e.to_string(),
         })?;
+    for record in records {
+        let record = record.map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })?;
+        if let Some(rr) = Option::<RR>::from(record)
+            .filter(|rr| rr._type != Type::Type(RRType::SOA) && !is_occluded(rr, &cuts))
+        {
+            if !push(rr) {
+                return Ok(());
+            }
+        }
+    }
+    if push(soa) {
+        send(batch);
+    }
+    Ok(())
+}
 
-        let rrs: Vec<RR> = results
-            .into_iter()
-            .filter_map(|record| record.into())
-            .filter(|rr: &RR| rr._type != Type::Type(RRType::SOA))
-            .collect();
+#[cfg(test)]
+mod tests {
+    use zns::{
+        structs::{RData, RRClass},
+        test_utils::get_rr,
+    };
 
-        let soa = get_default_soa(zone)?;
+    use crate::{
+        config::Config,
+        db::{lib::tests::get_test_connection, models::insert_into_database},
+    };
 
-        response.extend_answer(vec![soa.clone()]);
-        response.extend_answer(rrs);
-        response.extend_answer(vec![soa]);
+    use super::*;
 
-        Ok(response)
+    #[test]
+    fn test_stream_zone_transfer() {
+        let mut connection = get_test_connection();
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let class = Class::Class(RRClass::IN);
+
+        // Records of another zone with the same suffix are not part of the transfer
+        let other = Config::get().authoritative_zone.prepend("xbob".to_string());
+        for (name, count) in [(&zone, 1000), (&other, 1)] {
+            for i in 0..count {
+                let rr = RR {
+                    name: name.clone(),
+                    _type: Type::Type(RRType::TXT),
+                    rdata: RData::Vec(
+                        [vec![32], vec![b'a' + (i % 26) as u8; 32], vec![i as u8; 2]].concat(),
+                    ),
+                    ..get_rr(None)
+                };
+                insert_into_database(&rr, &mut connection).unwrap();
+            }
+        }
+
+        let mut batches = vec![];
+        stream_zone_transfer(&zone, &class, &mut connection, |batch| {
+            batches.push(batch);
+            true
+        })
+        .unwrap();
+
+        assert!(batches.len() > 1);
+        for batch in &batches {
+            let size: usize = batch.iter().map(|rr| RR::to_bytes(rr.clone()).len()).sum();
+            assert!(size <= MAX_TRANSFER_SIZE);
+        }
+        let rrs: Vec<RR> = batches.into_iter().flatten().collect();
+        assert_eq!(rrs.len(), 1002);
+        assert_eq!(rrs[0]._type, Type::Type(RRType::SOA));
+        assert_eq!(rrs[1001], rrs[0]);
+        assert!(rrs.iter().all(|rr| rr.name == zone));
+
+        // The stream stops when the receiver is gone
+        let mut calls = 0;
+        stream_zone_transfer(&zone, &class, &mut connection, |_| {
+            calls += 1;
+            false
+        })
+        .unwrap();
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_split_transfer() {
+        let rr = RR {
+            _type: Type::Type(RRType::TXT),
+            rdata: RData::Vec([vec![255], vec![b'a'; 255]].concat()),
+            ..get_rr(None)
+        };
+        let batches = split_transfer(vec![rr.clone(); 1000]);
+
+        assert!(batches.len() > 1);
+        for batch in &batches {
+            let size: usize = batch.iter().map(|rr| RR::to_bytes(rr.clone()).len()).sum();
+            assert!(size <= MAX_TRANSFER_SIZE);
+        }
+        assert_eq!(batches.concat(), vec![rr.clone(); 1000]);
+
+        assert_eq!(split_transfer(vec![rr.clone()]), vec![vec![rr]]);
+        assert!(split_transfer(vec![]).is_empty());
     }
 }
@@ -0,0 +1,258 @@
+use diesel::PgConnection;
+use zns::{
+    errors::ZNSError,
+    labelstring::LabelString,
+    parser::FromBytes,
+    reader::Reader,
+    structs::{Class, Message, RRType, Type, RR},
+};
+
+use crate::{
+    auth::owners::user_zone,
+    config::Config,
+    db::models::{get_from_database, get_names_with_type_below},
+};
+
+const QR_FLAG: u16 = 0b1000_0000_0000_0000;
+const AA_FLAG: u16 = 0b0000_0100_0000_0000;
+
+/// Name below a zone where it is delegated, with the names of the name servers of the
+/// delegated zone
+#[derive(Debug, PartialEq)]
+pub(super) struct ZoneCut {
+    name: LabelString,
+    name_servers: Vec<LabelString>,
+}
+
+/// Target names of the NS records
+fn get_name_servers(ns: &[RR]) -> Result<Vec<LabelString>, ZNSError> {
+    ns.iter()
+        .map(|rr| {
+            let rdata: Vec<u8> = rr.rdata.clone().into();
+            LabelString::from_bytes(&mut Reader::new(&rdata))
+        })
+        .collect()
+}
+
+/// The topmost zone cut at or above the name, as its NS records, if `delegation` is enabled.
+/// Zones can only be delegated below the zone of a user, which is always served by ZNS.
+pub(super) fn find_zone_cut(
+    name: &LabelString,
+    class: &Class,
+    delegation: bool,
+    connection: &mut PgConnection,
+) -> Result<Option<Vec<RR>>, ZNSError> {
+    let Some(apex) = user_zone(name).filter(|_| delegation) else {
+        return Ok(None);
+    };
+    for len in apex.len() + 1..=name.len() {
+        let candidate: LabelString = name.as_slice()[name.len() - len..].into();
+        let ns = get_from_database(
+            &candidate,
+            Some(Type::Type(RRType::NS)),
+            class.clone(),
+            connection,
+        )?;
+        if !ns.is_empty() {
+            return Ok(Some(ns));
+        }
+    }
+    Ok(None)
+}
+
+/// Turns the response into a non-authoritative referral to the delegated zone: the NS records
+/// and the DS records of the zone cut in the authority section and the addresses of name
+/// servers in the authoritative zone as glue in the additional section
+/// https://datatracker.ietf.org/doc/html/rfc1034#section-4.3.2
+pub(super) fn set_referral(
+    response: &mut Message,
+    ns: Vec<RR>,
+    class: &Class,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    let cut = ns[0].name.clone();
+    let mut glue = vec![];
+    for target in get_name_servers(&ns)? {
+        if target.is_subdomain_of(&Config::get().authoritative_zone) {
+            for _type in [RRType::A, RRType::AAAA] {
+                glue.extend(get_from_database(
+                    &target,
+                    Some(Type::Type(_type)),
+                    class.clone(),
+                    connection,
+                )?);
+            }
+        }
+    }
+
+    let ds = get_from_database(
+        &cut,
+        Some(Type::Type(RRType::DS)),
+        class.clone(),
+        connection,
+    )?;
+    response.answer = vec![];
+    response.header.ancount = 0;
+    response.authority = ns.into_iter().chain(ds).collect();
+    response.header.nscount = response.authority.len() as u16;
+    response.header.arcount += glue.len() as u16;
+    response.additional.splice(0..0, glue);
+    // ZNS is not authoritative for the delegated zone, `set_response` keeps the AA flag of
+    // messages which are already a response
+    response.header.flags = (response.header.flags | QR_FLAG) & !AA_FLAG;
+    Ok(())
+}
+
+/// Zone cuts below the zone, none if delegation is disabled
+pub(super) fn get_zone_cuts(
+    zone: &LabelString,
+    class: &Class,
+    delegation: bool,
+    connection: &mut PgConnection,
+) -> Result<Vec<ZoneCut>, ZNSError> {
+    if !delegation {
+        return Ok(vec![]);
+    }
+    get_names_with_type_below(zone, Type::Type(RRType::NS), connection)?
+        .iter()
+        .map(|name| {
+            let name = LabelString::from(name);
+            let ns = get_from_database(
+                &name,
+                Some(Type::Type(RRType::NS)),
+                class.clone(),
+                connection,
+            )?;
+            Ok(ZoneCut {
+                name_servers: get_name_servers(&ns)?,
+                name,
+            })
+        })
+        .collect()
+}
+
+/// Whether the record is hidden by a zone cut, and not part of the zone.
+/// Only the NS and DS records at a zone cut and the addresses of its name servers at or below
+/// it, the glue, are kept.
+/// https://datatracker.ietf.org/doc/html/rfc5936#section-3.5
+pub(super) fn is_occluded(rr: &RR, cuts: &[ZoneCut]) -> bool {
+    let glue = [Type::Type(RRType::A), Type::Type(RRType::AAAA)];
+    cuts.iter().any(|cut| {
+        rr.name.is_subdomain_of(&cut.name)
+            && !(glue.contains(&rr._type) && cut.name_servers.contains(&rr.name))
+            && !(rr.name == cut.name
+                && [Type::Type(RRType::NS), Type::Type(RRType::DS)].contains(&rr._type))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use zns::{
+        parser::ToBytes,
+        structs::{RData, RRClass, RCODE},
+        test_utils::get_rr,
+    };
+
+    use crate::db::{lib::tests::get_test_connection, models::insert_into_database};
+
+    use super::*;
+
+    fn get_record(name: &LabelString, _type: RRType, rdata: Vec<u8>) -> RR {
+        RR {
+            name: name.clone(),
+            _type: Type::Type(_type),
+            rdlength: rdata.len() as u16,
+            rdata: RData::Vec(rdata),
+            ..get_rr(None)
+        }
+    }
+
+    #[test]
+    fn test_referral() {
+        let mut connection = get_test_connection();
+        let class = Class::Class(RRClass::IN);
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let lab = zone.prepend("lab".to_string());
+        let ns1 = lab.prepend("ns1".to_string());
+        let ns2 = LabelString::from("ns.example.org");
+
+        for target in [&ns1, &ns2] {
+            let rr = get_record(&lab, RRType::NS, LabelString::to_bytes(target.clone()));
+            insert_into_database(&rr, &mut connection).unwrap();
+        }
+        let glue = get_record(&ns1, RRType::A, vec![192, 0, 2, 53]);
+        insert_into_database(&glue, &mut connection).unwrap();
+
+        let name = lab.prepend("a".to_string()).prepend("b".to_string());
+        assert!(find_zone_cut(&name, &class, false, &mut connection)
+            .unwrap()
+            .is_none());
+        assert!(find_zone_cut(&zone, &class, true, &mut connection)
+            .unwrap()
+            .is_none());
+        let ns = find_zone_cut(&name, &class, true, &mut connection)
+            .unwrap()
+            .unwrap();
+        assert_eq!(ns.len(), 2);
+        assert!(ns.iter().all(|rr| rr.name == lab));
+
+        let mut response = zns::test_utils::get_message(Some(name));
+        response.additional = vec![];
+        response.header.arcount = 0;
+        set_referral(&mut response, ns, &class, &mut connection).unwrap();
+        assert!(response.answer.is_empty());
+        assert_eq!(response.authority.len(), 2);
+        assert_eq!(response.additional, vec![glue.clone()]);
+        assert_eq!(response.header.arcount, 1);
+        // The resolver sets the response flags again
+        response.set_response(RCODE::NOERROR);
+        assert_eq!(response.header.flags & AA_FLAG, 0);
+    }
+
+    #[test]
+    fn test_occlusion() {
+        let mut connection = get_test_connection();
+        let class = Class::Class(RRClass::IN);
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let lab = zone.prepend("lab".to_string());
+        let ns1 = lab.prepend("ns1".to_string());
+
+        let rr = get_record(&lab, RRType::NS, LabelString::to_bytes(ns1.clone()));
+        insert_into_database(&rr, &mut connection).unwrap();
+
+        // NS records below the zone are not zone cuts without delegation
+        assert!(get_zone_cuts(&zone, &class, false, &mut connection)
+            .unwrap()
+            .is_empty());
+
+        let cuts = get_zone_cuts(&zone, &class, true, &mut connection).unwrap();
+        assert_eq!(
+            cuts,
+            vec![ZoneCut {
+                name: lab.clone(),
+                name_servers: vec![ns1.clone()]
+            }]
+        );
+        assert!(get_zone_cuts(&lab, &class, true, &mut connection)
+            .unwrap()
+            .is_empty());
+
+        // Only the addresses of the name servers are glue
+        let www = lab.prepend("www".to_string());
+        assert!(!is_occluded(
+            &get_record(&ns1, RRType::A, vec![192, 0, 2, 53]),
+            &cuts
+        ));
+        assert!(is_occluded(
+            &get_record(&www, RRType::A, vec![192, 0, 2, 80]),
+            &cuts
+        ));
+        assert!(is_occluded(&get_record(&www, RRType::TXT, vec![0]), &cuts));
+        assert!(!is_occluded(&get_record(&lab, RRType::NS, vec![0]), &cuts));
+        assert!(is_occluded(&get_record(&lab, RRType::TXT, vec![0]), &cuts));
+        assert!(!is_occluded(
+            &get_record(&zone, RRType::TXT, vec![0]),
+            &cuts
+        ));
+    }
+}
@@ -0,0 +1,236 @@
+use std::net::IpAddr;
+
+use diesel::PgConnection;
+use zns::{
+    errors::ZNSError,
+    labelstring::LabelString,
+    parser::FromBytes,
+    reader::Reader,
+    structs::{Message, RRType, SoaRData, Type, RR},
+};
+
+use crate::{
+    auth::{owners::user_zone, transfer_allowed, verify_authorization},
+    config::Config,
+    handlers::ResponseHandler,
+    history::{get_changes, get_serial},
+};
+
+use super::{delegation::find_zone_cut, get_default_soa, get_soa};
+
+/// Incremental zone transfers, answered with the changes since the serial of the client
+/// https://datatracker.ietf.org/doc/html/rfc1995
+pub struct IXFRHandler {}
+
+impl ResponseHandler for IXFRHandler {
+    async fn handle(
+        message: &Message,
+        raw: &[u8],
+        client: IpAddr,
+        connection: &mut PgConnection,
+    ) -> Result<Message, ZNSError> {
+        let mut response = message.clone();
+
+        if message.header.qdcount != 1 {
+            return Err(ZNSError::Refused {
+                message: "QDCOUNT must be one".to_string(),
+            });
+        }
+
+        let question = &message.question[0];
+        let zone = &question.qname;
+
+        // Secondaries in the transfer ACL may transfer every zone, other clients need to be
+        // authorized for the zone
+        if !transfer_allowed(message, zone, raw, client, connection)?
+            && verify_authorization(message, zone, raw, connection)
+                .await?
+                .is_none()
+        {
+            return Err(ZNSError::Refused {
+                message: "Not Authorized".to_string(),
+            });
+        }
+
+        if find_zone_cut(zone, &question.qclass, Config::get().delegation, connection)?.is_some() {
+            return Err(ZNSError::NotAuth {
+                message: format!("{} is delegated", zone),
+            });
+        }
+
+        let client_serial = get_client_serial(message, zone)?.ok_or(ZNSError::Formerr {
+            message: "IXFR request must contain the SOA of the client".to_string(),
+        })?;
+
+        response.authority = vec![];
+        response.header.nscount = 0;
+        response.extend_answer(get_ixfr_answer(zone, client_serial, connection)?);
+
+        Ok(response)
+    }
+}
+
+/// Serial of the SOA of the version of the client, in the authority section of the request
+fn get_client_serial(message: &Message, zone: &LabelString) -> Result<Option<u32>, ZNSError> {
+    message
+        .authority
+        .iter()
+        .find(|rr| rr._type == Type::Type(RRType::SOA) && &rr.name == zone)
+        .map(|rr| {
+            let rdata: Vec<u8> = rr.rdata.clone().into();
+            SoaRData::from_bytes(&mut Reader::new(&rdata)).map(|soa| soa.serial)
+        })
+        .transpose()
+}
+
+/// Serial number arithmetic: the serial is older than the other serial
+/// https://datatracker.ietf.org/doc/html/rfc1982#section-3.2
+fn is_older(serial: u32, other: u32) -> bool {
+    (serial.wrapping_sub(other) as i32) < 0
+}
+
+/// Whether the IXFR response is only the SOA of a newer version than the version of the client,
+/// which means that the changes are not kept and the whole zone has to be transferred.
+/// Over TCP, the resolver then streams the zone as with AXFR.
+/// https://datatracker.ietf.org/doc/html/rfc1995#section-4
+pub fn is_full_transfer(request: &Message, response: &Message) -> bool {
+    let Some(question) = request.question.first() else {
+        return false;
+    };
+    let serial = match response.answer.as_slice() {
+        [rr] if rr._type == Type::Type(RRType::SOA) => {
+            let rdata: Vec<u8> = rr.rdata.clone().into();
+            SoaRData::from_bytes(&mut Reader::new(&rdata)).map(|soa| soa.serial)
+        }
+        _ => return false,
+    };
+    question.qtype == Type::Type(RRType::IXFR)
+        && matches!(
+            (get_client_serial(request, &question.qname), serial),
+            (Ok(Some(client_serial)), Ok(serial)) if is_older(client_serial, serial)
+        )
+}
+
+/// The changes since the version of the client, or only the SOA of the current version if the
+/// client is up to date or its version is not kept. A single SOA fits in a UDP response, over
+/// TCP it is followed by a full zone transfer, see [`is_full_transfer`].
+fn get_ixfr_answer(
+    zone: &LabelString,
+    client_serial: u32,
+    connection: &mut PgConnection,
+) -> Result<Vec<RR>, ZNSError> {
+    let current = get_default_soa(zone, connection)?;
+
+    // Only the zones directly below the authoritative zone have versions
+    if user_zone(zone).as_ref() != Some(zone) {
+        return Ok(vec![current]);
+    }
+
+    let serial = get_serial(zone, connection)?;
+    if !is_older(client_serial, serial) {
+        return Ok(vec![current]);
+    }
+
+    match get_changes(zone, client_serial, connection)? {
+        Some(changes) => {
+            let mut answer = vec![current.clone()];
+            for change in changes {
+                answer.push(get_soa(zone, change.from)?);
+                answer.extend(change.deleted);
+                answer.push(get_soa(zone, change.to)?);
+                answer.extend(change.added);
+            }
+            answer.push(current);
+            Ok(answer)
+        }
+        None => Ok(vec![current]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zns::{
+        structs::RData,
+        test_utils::{get_message, get_rr},
+    };
+
+    use crate::{
+        config::Config,
+        db::{lib::tests::get_test_connection, models::insert_into_database},
+        history::{ensure_version, store_next_version},
+    };
+
+    use super::*;
+
+    fn get_serials(answer: &[RR]) -> Vec<Option<u32>> {
+        answer
+            .iter()
+            .map(|rr| {
+                (rr._type == Type::Type(RRType::SOA)).then(|| {
+                    let rdata: Vec<u8> = rr.rdata.clone().into();
+                    SoaRData::from_bytes(&mut Reader::new(&rdata))
+                        .unwrap()
+                        .serial
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ixfr_answer() {
+        let mut connection = get_test_connection();
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let home = zone.prepend("home".to_string());
+        let record = |name: &LabelString| RR {
+            name: name.clone(),
+            rdata: RData::Vec(vec![1, 2, 3, 4]),
+            ..get_rr(None)
+        };
+
+        ensure_version(&zone, &mut connection).unwrap();
+        insert_into_database(&record(&zone), &mut connection).unwrap();
+        store_next_version(&zone, &mut connection).unwrap();
+        insert_into_database(&record(&home), &mut connection).unwrap();
+        store_next_version(&zone, &mut connection).unwrap();
+
+        let mut answer = |serial: u32| get_ixfr_answer(&zone, serial, &mut connection).unwrap();
+
+        assert_eq!(get_serials(&answer(3)), vec![Some(3)]);
+        assert_eq!(get_serials(&answer(4)), vec![Some(3)]);
+        assert_eq!(
+            get_serials(&answer(1)),
+            vec![
+                Some(3),
+                Some(1),
+                Some(2),
+                None,
+                Some(2),
+                Some(3),
+                None,
+                Some(3)
+            ]
+        );
+        assert_eq!(
+            get_serials(&answer(2)),
+            vec![Some(3), Some(2), Some(3), None, Some(3)]
+        );
+        // Versions which are not kept fall back to a full zone transfer
+        assert_eq!(get_serials(&answer(u32::MAX)), vec![Some(3)]);
+
+        let request = |serial: u32, answer: Vec<RR>| {
+            let mut request = get_message(Some(zone.clone()));
+            request.question.truncate(1);
+            request.question[0].qtype = Type::Type(RRType::IXFR);
+            request.authority = vec![get_soa(&zone, serial).unwrap()];
+            let mut response = request.clone();
+            response.answer = answer;
+            (request, response)
+        };
+        let (full, response) = request(u32::MAX, answer(u32::MAX));
+        assert!(is_full_transfer(&full, &response));
+        let (current, response) = request(3, answer(3));
+        assert!(!is_full_transfer(&current, &response));
+        let (incremental, response) = request(1, answer(1));
+        assert!(!is_full_transfer(&incremental, &response));
+    }
+}
@@ -0,0 +1,75 @@
+use std::net::IpAddr;
+
+use diesel::PgConnection;
+use zns::{
+    errors::ZNSError,
+    parser::ToBytes,
+    structs::{Class, Message, RData, RRClass, RRType, Type, RR},
+};
+
+use crate::{audit::get_log, auth::verify_authorization, handlers::ResponseHandler};
+
+/// Label of the name at which the update log of a zone can be queried
+pub const LOG_LABEL: &str = "_log";
+
+/// Number of updates which are read for a response
+const LOG_LIMIT: u32 = 100;
+
+/// Size of the answer, the latest updates are included as long as they fit
+const LOG_SIZE: usize = 16384;
+
+/// Answers signed TXT queries for `_log.<zone>` with the latest updates of the zone,
+/// a TXT record per update
+pub struct LogHandler {}
+
+impl ResponseHandler for LogHandler {
+    async fn handle(
+        message: &Message,
+        raw: &[u8],
+        _client: IpAddr,
+        connection: &mut PgConnection,
+    ) -> Result<Message, ZNSError> {
+        let mut response = message.clone();
+        if message.header.qdcount != 1 {
+            return Err(ZNSError::Formerr {
+                message: "QDCOUNT must be one".to_string(),
+            });
+        }
+
+        let question = &message.question[0];
+        let zone = question.qname.as_slice()[1..].into();
+
+        if verify_authorization(message, &zone, raw, connection)
+            .await?
+            .is_none()
+        {
+            return Err(ZNSError::Refused {
+                message: "Not Authorized".to_string(),
+            });
+        }
+
+        let mut size = 0;
+        response.extend_answer(
+            get_log(&zone, LOG_LIMIT, connection)?
+                .iter()
+                .map(|entry| {
+                    let rdata = entry.to_txt();
+                    RR {
+                        name: question.qname.clone(),
+                        _type: Type::Type(RRType::TXT),
+                        class: Class::Class(RRClass::IN),
+                        ttl: 0,
+                        rdlength: rdata.len() as u16,
+                        rdata: RData::Vec(rdata),
+                    }
+                })
+                .take_while(|rr| {
+                    size += RR::to_bytes(rr.clone()).len();
+                    size <= LOG_SIZE
+                })
+                .collect(),
+        );
+
+        Ok(response)
+    }
+}
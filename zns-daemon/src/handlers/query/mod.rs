@@ -1,5 +1,10 @@
+use std::net::IpAddr;
+
 use axfr::AXFRHandler;
 use diesel::PgConnection;
+use ixfr::IXFRHandler;
+use log::{LogHandler, LOG_LABEL};
+use versions::{VersionsHandler, VERSIONS_LABEL};
 
 use normal_query::NormalQueryHandler;
 use zns::{
@@ -9,12 +14,23 @@ use zns::{
     structs::{Class, Message, RData, RRClass, RRType, SoaRData, Type, RR},
 };
 
-use crate::config::Config;
+use crate::{
+    config::Config,
+    history::{get_apex_serial, get_serial},
+    secondary::Secondary,
+};
 
 use super::ResponseHandler;
 
 mod axfr;
+mod delegation;
+mod ixfr;
+mod log;
 mod normal_query;
+mod versions;
+
+pub use axfr::{split_transfer, stream_zone_transfer};
+pub use ixfr::is_full_transfer;
 
 pub struct QueryHandler {}
 
@@ -22,27 +38,71 @@ impl ResponseHandler for QueryHandler {
     async fn handle(
         message: &Message,
         raw: &[u8],
+        client: IpAddr,
         connection: &mut PgConnection,
     ) -> Result<Message, ZNSError> {
-        match message
+        let signed = message.additional.last().is_some_and(|rr| {
+            [Type::Type(RRType::SIG), Type::Type(RRType::TSIG)].contains(&rr._type)
+        });
+
+        // Replicated zones which could not be refreshed are no longer served
+        // https://datatracker.ietf.org/doc/html/rfc1034#section-4.3.5
+        if let Some(q) = message
             .question
-            .first()
-            .filter(|q| q.qtype == Type::Type(RRType::AXFR))
+            .iter()
+            .find(|q| Secondary::get().is_expired(&q.qname))
         {
-            Some(_) => AXFRHandler::handle(message, raw, connection).await,
-            None => NormalQueryHandler::handle(message, raw, connection).await,
+            return Err(ZNSError::Servfail {
+                message: format!("Zone of {} is expired", q.qname),
+            });
+        }
+
+        match message.question.first() {
+            Some(q) if q.qtype == Type::Type(RRType::AXFR) => {
+                AXFRHandler::handle(message, raw, client, connection).await
+            }
+            Some(q) if q.qtype == Type::Type(RRType::IXFR) => {
+                IXFRHandler::handle(message, raw, client, connection).await
+            }
+            // Unsigned queries for these names are answered from the records of the zone
+            Some(q) if signed && q.qtype == Type::Type(RRType::TXT) => {
+                match q.qname.as_slice().first().map(String::as_str) {
+                    Some(LOG_LABEL) => LogHandler::handle(message, raw, client, connection).await,
+                    Some(VERSIONS_LABEL) => {
+                        VersionsHandler::handle(message, raw, client, connection).await
+                    }
+                    _ => NormalQueryHandler::handle(message, raw, client, connection).await,
+                }
+            }
+            _ => NormalQueryHandler::handle(message, raw, client, connection).await,
         }
     }
 }
 
-fn get_default_soa(name: &LabelString) -> Result<RR, ZNSError> {
+/// SOA of the zone with the serial of its latest version
+pub(super) fn get_default_soa(
+    name: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<RR, ZNSError> {
+    let serial = if name == &Config::get().authoritative_zone {
+        get_apex_serial(connection)?
+    } else if name.len() == 1 + Config::get().authoritative_zone.len() {
+        get_serial(name, connection)?
+    } else {
+        1
+    };
+    get_soa(name, serial)
+}
+
+/// SOA of the zone with the given serial
+pub fn get_soa(name: &LabelString, serial: u32) -> Result<RR, ZNSError> {
     let auth_zone = Config::get().authoritative_zone.clone();
     let rdata = if &auth_zone == name {
         // Recommended values taken from wikipedia: https://en.wikipedia.org/wiki/SOA_record
         Ok(SoaRData {
             mname: auth_zone,
             rname: LabelString::from("admin.zeus.ugent.be"),
-            serial: 1,
+            serial,
             refresh: 86400,
             retry: 7200,
             expire: 3600000,
@@ -53,7 +113,7 @@ fn get_default_soa(name: &LabelString) -> Result<RR, ZNSError> {
         Ok(SoaRData {
             mname: auth_zone,
             rname: LabelString::from(&format!("{}.zeus.ugent.be", zone.as_slice()[0])),
-            serial: 1,
+            serial,
             refresh: 86400,
             retry: 7200,
             expire: 3600000,
@@ -1,13 +1,22 @@
+use std::net::IpAddr;
+
 use diesel::PgConnection;
 
 use zns::{
     errors::ZNSError,
-    structs::{Message, Question, RRType, Type, RR},
+    labelstring::LabelString,
+    structs::{Class, Message, Question, RRType, Type, RR},
 };
 
-use crate::{config::Config, db::models::get_from_database};
+use crate::{
+    config::Config,
+    db::models::{get_from_database, name_exists},
+};
 
-use super::{get_default_soa, ResponseHandler};
+use super::{
+    delegation::{find_zone_cut, set_referral},
+    get_default_soa, ResponseHandler,
+};
 
 pub struct NormalQueryHandler {}
 
@@ -16,11 +25,25 @@ impl ResponseHandler for NormalQueryHandler {
     async fn handle(
         message: &Message,
         _raw: &[u8],
+        _client: IpAddr,
         connection: &mut PgConnection,
     ) -> Result<Message, ZNSError> {
         let mut response = message.clone();
 
         for question in &message.question {
+            if let Some(ns) = find_zone_cut(
+                &question.qname,
+                &question.qclass,
+                Config::get().delegation,
+                connection,
+            )? {
+                // The DS records at a zone cut belong to the parent zone
+                if question.qtype != Type::Type(RRType::DS) || ns[0].name != question.qname {
+                    set_referral(&mut response, ns, &question.qclass, connection)?;
+                    continue;
+                }
+            }
+
             let answers = get_from_database(
                 &question.qname,
                 Some(question.qtype.clone()),
@@ -40,18 +63,22 @@ impl ResponseHandler for NormalQueryHandler {
 
                         rrs.extend(try_cname(&domain_records));
 
-                        if domain_records.is_empty() && !question.qname.is_empty() {
-                            rrs.extend(try_wildcard(question, connection)?);
+                        let mut exists = !domain_records.is_empty();
+                        if !exists && !question.qname.is_empty() {
+                            if let Some(wildcard) = try_wildcard(question, connection)? {
+                                exists = true;
+                                rrs.extend(wildcard);
+                            }
                         }
 
                         if rrs.is_empty()
                             && question.qtype == Type::Type(RRType::SOA)
                             && Config::get().default_soa
                         {
-                            rrs.extend([get_default_soa(&question.qname)?])
+                            rrs.extend([get_default_soa(&question.qname, connection)?])
                         }
 
-                        if rrs.is_empty() && domain_records.is_empty() {
+                        if rrs.is_empty() && !exists {
                             return Err(ZNSError::NXDomain {
                                 domain: question.qname.to_string(),
                                 qtype: question.qtype.clone(),
@@ -81,39 +108,69 @@ fn try_cname(records: &[RR]) -> Vec<RR> {
         .collect()
 }
 
-fn try_wildcard(question: &Question, connection: &mut PgConnection) -> Result<Vec<RR>, ZNSError> {
-    let mut qname = question.qname.clone().to_vec();
-    qname[0] = String::from("*");
-    let matches: Vec<RR> = get_from_database(
-        &qname.clone().into(),
-        Some(question.qtype.clone()),
-        question.qclass.clone(),
-        connection,
-    )?
-    .into_iter()
-    .map(|mut rr| {
-        rr.name.clone_from(&question.qname);
-        rr
-    })
-    .collect();
+/// Answers for a name without records, `None` if the name does not exist.
+/// A name which only has names below it exists without records, otherwise the answers are
+/// synthesized from the wildcard below the closest encloser, the closest existing ancestor.
+/// https://datatracker.ietf.org/doc/html/rfc4592#section-3.3
+fn try_wildcard(
+    question: &Question,
+    connection: &mut PgConnection,
+) -> Result<Option<Vec<RR>>, ZNSError> {
+    if name_exists(&question.qname, question.qclass.clone(), connection)? {
+        return Ok(Some(vec![]));
+    }
+
+    let Some(encloser) = closest_encloser(&question.qname, &question.qclass, connection)? else {
+        return Ok(None);
+    };
+    let mut source = encloser.to_vec();
+    source.insert(0, String::from("*"));
+    let records = get_from_database(&source.into(), None, question.qclass.clone(), connection)?;
+    if records.is_empty() {
+        return Ok(None);
+    }
 
+    let mut matches: Vec<RR> = records
+        .iter()
+        .filter(|rr| rr._type == question.qtype)
+        .cloned()
+        .collect();
     // Maybe wildcard cname exists
     if matches.is_empty() {
-        Ok(get_from_database(
-            &qname.into(),
-            Some(Type::Type(RRType::CNAME)),
-            question.qclass.clone(),
-            connection,
-        )?
-        .into_iter()
-        .map(|mut rr| {
-            rr.name.clone_from(&question.qname);
-            rr
-        })
-        .collect())
-    } else {
-        Ok(matches)
+        matches = try_cname(&records);
+    }
+
+    // The source of synthesis exists, without matches the answer is empty
+    Ok(Some(
+        matches
+            .into_iter()
+            .map(|mut rr| {
+                rr.name.clone_from(&question.qname);
+                rr
+            })
+            .collect(),
+    ))
+}
+
+/// The closest ancestor of the name which exists, the authoritative zone always exists.
+/// Names outside the authoritative zone have no closest encloser.
+/// https://datatracker.ietf.org/doc/html/rfc4592#section-3.3.1
+fn closest_encloser(
+    name: &LabelString,
+    class: &Class,
+    connection: &mut PgConnection,
+) -> Result<Option<LabelString>, ZNSError> {
+    let apex = &Config::get().authoritative_zone;
+    if !name.is_subdomain_of(apex) {
+        return Ok(None);
+    }
+    for len in (apex.len()..name.len()).rev() {
+        let ancestor: LabelString = name.as_slice()[name.len() - len..].into();
+        if len == apex.len() || name_exists(&ancestor, class.clone(), connection)? {
+            return Ok(Some(ancestor));
+        }
     }
+    Ok(None)
 }
 
 #[cfg(test)]
@@ -139,6 +196,7 @@ mod tests {
         let result = NormalQueryHandler::handle(
             &message,
             &Message::to_bytes(message.clone()),
+            IpAddr::from([127, 0, 0, 1]),
             &mut connection,
         )
         .await
@@ -171,6 +229,7 @@ mod tests {
         let result = NormalQueryHandler::handle(
             &message,
             &Message::to_bytes(message.clone()),
+            IpAddr::from([127, 0, 0, 1]),
             &mut connection,
         )
         .await
@@ -194,6 +253,7 @@ mod tests {
         let result = NormalQueryHandler::handle(
             &message,
             &Message::to_bytes(message.clone()),
+            IpAddr::from([127, 0, 0, 1]),
             &mut connection,
         )
         .await
@@ -226,6 +286,7 @@ mod tests {
         let result = NormalQueryHandler::handle(
             &message,
             &Message::to_bytes(message.clone()),
+            IpAddr::from([127, 0, 0, 1]),
             &mut connection,
         )
         .await
@@ -234,4 +295,62 @@ mod tests {
         assert_eq!(result.answer.len(), 2);
         assert_eq!(result.answer[0], rr);
     }
+
+    #[test]
+    fn test_closest_encloser() {
+        let mut connection = get_test_connection();
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let question = |name: &LabelString, qtype: RRType| Question {
+            qname: name.clone(),
+            qtype: Type::Type(qtype),
+            qclass: Class::Class(zns::structs::RRClass::IN),
+        };
+
+        let rr = get_rr(Some(zone.prepend("*".to_string())));
+        let existing = zone.prepend("c".to_string());
+        for rr in [
+            rr.clone(),
+            get_rr(Some(existing.prepend("www".to_string()))),
+        ] {
+            insert_into_database(&rr, &mut connection).unwrap();
+        }
+
+        // The wildcard matches names of multiple labels
+        let name = zone.prepend("b".to_string()).prepend("a".to_string());
+        let answers = try_wildcard(&question(&name, RRType::A), &mut connection)
+            .unwrap()
+            .unwrap();
+        assert_eq!(answers, vec![RR { name, ..rr.clone() }]);
+
+        // Not below an existing name, of which the closest encloser has no wildcard
+        let name = existing.prepend("x".to_string());
+        assert_eq!(
+            closest_encloser(&name, &question(&name, RRType::A).qclass, &mut connection).unwrap(),
+            Some(existing.clone())
+        );
+        assert_eq!(
+            try_wildcard(&question(&name, RRType::A), &mut connection).unwrap(),
+            None
+        );
+
+        // Outside the authoritative zone
+        let name = LabelString::from("a.b.example.org");
+        assert_eq!(
+            closest_encloser(&name, &question(&name, RRType::A).qclass, &mut connection).unwrap(),
+            None
+        );
+
+        // An empty non-terminal exists without records
+        assert_eq!(
+            try_wildcard(&question(&existing, RRType::A), &mut connection).unwrap(),
+            Some(vec![])
+        );
+
+        // The wildcard has no records of the type
+        let name = zone.prepend("b".to_string());
+        assert_eq!(
+            try_wildcard(&question(&name, RRType::TXT), &mut connection).unwrap(),
+            Some(vec![])
+        );
+    }
 }
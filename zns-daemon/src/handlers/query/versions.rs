@@ -0,0 +1,84 @@
+use std::net::IpAddr;
+
+use diesel::PgConnection;
+use zns::{
+    errors::ZNSError,
+    labelstring::LabelString,
+    structs::{Class, Message, RData, RRClass, RRType, Type, RR},
+};
+
+use crate::{
+    audit::format_time,
+    auth::{owners::user_zone, verify_authorization},
+    handlers::ResponseHandler,
+    history::get_versions,
+};
+
+/// Label of the name at which the versions of a zone can be queried
+pub const VERSIONS_LABEL: &str = "_versions";
+
+/// Answers signed TXT queries for `_versions.<zone>` with the kept versions of the zone,
+/// a TXT record per version
+pub struct VersionsHandler {}
+
+impl ResponseHandler for VersionsHandler {
+    async fn handle(
+        message: &Message,
+        raw: &[u8],
+        _client: IpAddr,
+        connection: &mut PgConnection,
+    ) -> Result<Message, ZNSError> {
+        let mut response = message.clone();
+        if message.header.qdcount != 1 {
+            return Err(ZNSError::Formerr {
+                message: "QDCOUNT must be one".to_string(),
+            });
+        }
+
+        let question = &message.question[0];
+        let zone: LabelString = question.qname.as_slice()[1..].into();
+
+        // Versions are only kept for the zones directly below the authoritative zone
+        if user_zone(&zone).as_ref() != Some(&zone) {
+            return Err(ZNSError::Refused {
+                message: format!("No versions are kept for {}", zone),
+            });
+        }
+
+        if verify_authorization(message, &zone, raw, connection)
+            .await?
+            .is_none()
+        {
+            return Err(ZNSError::Refused {
+                message: "Not Authorized".to_string(),
+            });
+        }
+
+        response.extend_answer(
+            get_versions(&zone, connection)?
+                .iter()
+                .map(|version| {
+                    let mut rdata = vec![];
+                    for string in [
+                        format!("serial={}", version.serial),
+                        format!("time={}", format_time(version.time)),
+                        format!("records={}", version.records),
+                    ] {
+                        rdata.push(string.len() as u8);
+                        rdata.extend(string.as_bytes());
+                    }
+                    RR {
+                        name: question.qname.clone(),
+                        _type: Type::Type(RRType::TXT),
+                        class: Class::Class(RRClass::IN),
+                        ttl: 0,
+                        rdlength: rdata.len() as u16,
+                        rdata: RData::Vec(rdata),
+                    }
+                })
+                .collect(),
+        );
+
+        Ok(response)
+    }
+}
@@ -1,15 +1,36 @@
-use diesel::PgConnection;
+use std::net::IpAddr;
 
-use crate::auth::verify_authorization;
+use diesel::{Connection, PgConnection};
+
+use crate::audit::{log_changes, log_update};
+use crate::auth::{owners::user_zone, verify_authorization};
+use crate::catalog::get_catalog_soa;
+use crate::config::Config;
 use crate::db::models::{delete_from_database, get_from_database, insert_into_database};
+use crate::history::{ensure_version, rollback, store_next_version, Change};
+use crate::notify::Notifier;
+use crate::quota::check_quota;
+use crate::secondary::Secondary;
+use crate::type_policy::TypePolicy;
 
+use zns::labelstring::LabelString;
 use zns::structs::{Class, Message, RRClass, RRType, Type};
 use zns::{errors::ZNSError, structs::RR};
 
-use super::ResponseHandler;
+use self::ds::{synchronize_ds, validate_child_record};
+use self::policy::{check_grants, check_unrestricted};
+use self::rdata::validate_rdata;
+
+use super::{query::get_default_soa, ResponseHandler};
+
+mod ds;
+mod policy;
+mod rdata;
 
-// Types which are not allowed to add. Array should be small.
-static ILLEGAL_TYPES: [RRType; 2] = [RRType::SOA, RRType::NS];
+pub use policy::{add_grant, list_grants, remove_grant, Grant};
+
+/// Label of the TXT record which rolls a zone back to the version with the serial in its RDATA
+const ROLLBACK_LABEL: &str = "_rollback";
 
 pub struct UpdateHandler {}
 
@@ -17,6 +38,7 @@ impl ResponseHandler for UpdateHandler {
     async fn handle(
         message: &Message,
         raw: &[u8],
+        client: IpAddr,
         connection: &mut PgConnection,
     ) -> Result<Message, ZNSError> {
         let response = message.clone();
@@ -32,14 +54,21 @@ impl ResponseHandler for UpdateHandler {
         // Check Prerequisite    TODO: implement this
 
         let zone = &message.question[0];
-        let zlen = zone.qname.as_slice().len();
 
-        if !verify_authorization(message, &zone.qname, raw, connection).await? {
+        if Secondary::get().is_replicated(&zone.qname) {
             return Err(ZNSError::Refused {
-                message: "Not Authorized".to_string(),
+                message: "Zone is replicated from the primary, which should be updated".to_string(),
             });
         }
 
+        let zlen = zone.qname.as_slice().len();
+
+        let signer = verify_authorization(message, &zone.qname, raw, connection)
+            .await?
+            .ok_or(ZNSError::Refused {
+                message: "Not Authorized".to_string(),
+            })?;
+
         // Update Section Prescan
         for rr in &message.authority {
             let rlen = rr.name.as_slice().len();
@@ -51,6 +80,7 @@ impl ResponseHandler for UpdateHandler {
                 });
             }
 
+            #[allow(clippy::single_match)]
             match (rr.class == Class::Class(RRClass::ANY) && (rr.ttl != 0 || rr.rdlength != 0))
                 || (rr.class == Class::Class(RRClass::NONE) && rr.ttl != 0)
                 || ![
@@ -69,59 +99,210 @@ impl ResponseHandler for UpdateHandler {
             }
         }
 
-        for rr in &message.authority {
-            if rr.class == zone.qclass {
-                if let Some(message) = validate_record(rr, connection)? {
-                    return Err(ZNSError::Refused { message });
+        // The entire update is rejected if the key is not allowed to change one of the records
+        check_grants(&signer.name, &message.authority, connection)?;
+
+        // A rollback may change every record of the zone, which is only allowed without grants
+        let rollback_serial = get_rollback_serial(message, &zone.qname, &zone.qclass)?;
+        if rollback_serial.is_some() {
+            check_unrestricted(&signer.name, connection)?;
+        }
+
+        // The update is applied atomically and rolled back if it fails or exceeds the quota,
+        // applied updates are stored as a new version and recorded in the log of the zone
+        let mut result = Ok(());
+        let transaction = connection.transaction(|connection| {
+            result = ensure_version(&zone.qname, connection).and_then(|_| match rollback_serial {
+                Some(serial) => {
+                    apply_rollback(&zone.qname, serial, connection).and_then(|change| {
+                        log_changes(
+                            &zone.qname,
+                            &change.added.iter().collect::<Vec<_>>(),
+                            &change.deleted.iter().collect::<Vec<_>>(),
+                            &signer,
+                            client,
+                            connection,
+                        )
+                    })
                 }
-                insert_into_database(rr, connection)?;
-            } else if rr.class == Class::Class(RRClass::ANY) {
-                if rr._type == Type::Type(RRType::ANY) {
-                    if rr.name == zone.qname {
-                        return Err(ZNSError::NotImp {
-                            object: String::from("Update Handler"),
-                            message: "rr.name == zone.qname".to_string(),
-                        });
-                    } else {
-                        delete_from_database(
-                            &rr.name,
-                            None,
-                            Class::Class(RRClass::IN),
-                            None,
+                None => apply_update(message, &zone.qname, &zone.qclass, connection)
+                    .and_then(|_| store_next_version(&zone.qname, connection))
+                    .and_then(|_| {
+                        log_update(
+                            message,
+                            &zone.qname,
+                            &zone.qclass,
+                            &signer,
+                            client,
                             connection,
                         )
-                    }
+                    }),
+            });
+            match result {
+                Ok(()) => Ok(()),
+                Err(_) => Err(diesel::result::Error::RollbackTransaction),
+            }
+        });
+        result?;
+        transaction.map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })?;
+
+        // Secondaries transfer the zone which has the SOA, or the authoritative zone with the
+        // records of all zones
+        let notify_zone = user_zone(&zone.qname).unwrap_or(zone.qname.clone());
+        Notifier::get().notify(&notify_zone, get_default_soa(&notify_zone, connection)?);
+        let apex = &Config::get().authoritative_zone;
+        if &notify_zone != apex {
+            Notifier::get().notify(apex, get_default_soa(apex, connection)?);
+        }
+        // Zones may have been added to or removed from the catalog
+        if let Some(catalog) = &Config::get().catalog_zone {
+            Notifier::get().notify(catalog, get_catalog_soa(connection)?);
+        }
+
+        Ok(response)
+    }
+}
+
+fn apply_update(
+    message: &Message,
+    zone: &LabelString,
+    zone_class: &Class,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    let policy = TypePolicy::get(zone, connection)?;
+
+    for rr in &message.authority {
+        if rr.class == *zone_class {
+            policy.check(rr, zone)?;
+            validate_rdata(rr)?;
+            if let Some(message) = validate_record(rr, zone, connection)? {
+                return Err(ZNSError::Refused { message });
+            }
+            insert_into_database(rr, connection)?;
+        } else if rr.class == Class::Class(RRClass::ANY) {
+            if rr._type == Type::Type(RRType::ANY) {
+                if rr.name == *zone {
+                    return Err(ZNSError::NotImp {
+                        object: String::from("Update Handler"),
+                        message: "rr.name == zone.qname".to_string(),
+                    });
                 } else {
                     delete_from_database(
                         &rr.name,
-                        Some(rr._type.clone()),
+                        None,
                         Class::Class(RRClass::IN),
                         None,
                         connection,
                     )
                 }
-            } else if rr.class == Class::Class(RRClass::NONE) {
-                if rr._type == Type::Type(RRType::SOA) {
-                    continue;
-                }
+            } else {
                 delete_from_database(
                     &rr.name,
                     Some(rr._type.clone()),
                     Class::Class(RRClass::IN),
-                    Some(rr.rdata.clone().into()),
+                    None,
                     connection,
                 )
             }
+        } else if rr.class == Class::Class(RRClass::NONE) {
+            if rr._type == Type::Type(RRType::SOA) {
+                continue;
+            }
+            delete_from_database(
+                &rr.name,
+                Some(rr._type.clone()),
+                Class::Class(RRClass::IN),
+                Some(rr.rdata.clone().into()),
+                connection,
+            )
         }
+    }
 
-        Ok(response)
+    // Child zone published new CDS/CDNSKEY records: update DS RRset in parent
+    if message
+        .authority
+        .iter()
+        .any(|rr| [Type::Type(RRType::CDS), Type::Type(RRType::CDNSKEY)].contains(&rr._type))
+    {
+        synchronize_ds(zone, connection)?;
+    }
+
+    if message.authority.iter().any(|rr| &rr.class == zone_class) {
+        check_quota(zone, connection)?;
     }
+
+    Ok(())
+}
+
+/// Rolls the zone back to a previous version. The records which the rollback adds again must be
+/// allowed by the current type policy and the zone must stay within its quota.
+fn apply_rollback(
+    zone: &LabelString,
+    serial: u32,
+    connection: &mut PgConnection,
+) -> Result<Change, ZNSError> {
+    let change = rollback(zone, serial, connection)?;
+
+    let policy = TypePolicy::get(zone, connection)?;
+    for rr in &change.added {
+        policy.check(rr, zone)?;
+    }
+    if !change.added.is_empty() {
+        check_quota(zone, connection)?;
+    }
+
+    Ok(change)
+}
+
+/// An update which only adds `_rollback.<zone> TXT "<serial>"` rolls the zone back to a
+/// previous version, instead of adding the record
+fn get_rollback_serial(
+    message: &Message,
+    zone: &LabelString,
+    zone_class: &Class,
+) -> Result<Option<u32>, ZNSError> {
+    let rr = match message.authority.as_slice() {
+        [rr] if rr.name.as_slice().first().map(String::as_str) == Some(ROLLBACK_LABEL)
+            && rr._type == Type::Type(RRType::TXT)
+            && &rr.class == zone_class =>
+        {
+            rr
+        }
+        _ => return Ok(None),
+    };
+
+    if rr.name.as_slice()[1..] != *zone.as_slice() || user_zone(zone).as_ref() != Some(zone) {
+        return Err(ZNSError::Refused {
+            message: format!(
+                "A rollback must be requested with {}.<zone> in the zone itself",
+                ROLLBACK_LABEL
+            ),
+        });
+    }
+
+    let rdata: Vec<u8> = rr.rdata.clone().into();
+    rdata
+        .split_first()
+        .filter(|(length, serial)| **length as usize == serial.len())
+        .and_then(|(_, serial)| std::str::from_utf8(serial).ok()?.parse().ok())
+        .map(Some)
+        .ok_or(ZNSError::Formerr {
+            message: format!("{} must contain the serial of a version", rr.name),
+        })
 }
 
-fn validate_record(record: &RR, connection: &mut PgConnection) -> Result<Option<String>, ZNSError> {
+fn validate_record(
+    record: &RR,
+    zone: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<Option<String>, ZNSError> {
     if let Type::Type(rr_type) = &record._type {
-        if ILLEGAL_TYPES.contains(rr_type) {
-            return Ok(Some(format!("Illegal type in add: {:#?} ", rr_type)));
+        if [RRType::CDS, RRType::CDNSKEY].contains(rr_type) {
+            if let Some(message) = validate_child_record(record, zone) {
+                return Ok(Some(message));
+            }
         }
     }
 
@@ -139,3 +320,113 @@ fn validate_record(record: &RR, connection: &mut PgConnection) -> Result<Option<
         Ok(None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use zns::{
+        structs::RData,
+        test_utils::{get_message, get_rr},
+    };
+
+    use crate::{
+        config::Config, db::lib::tests::get_test_connection, quota::set_quota,
+        type_policy::set_type_policy,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_rollback_serial() {
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let class = Class::Class(RRClass::IN);
+        let message = |name: &LabelString, rdata: &[u8]| {
+            let mut message = get_message(Some(zone.clone()));
+            message.authority = vec![RR {
+                name: name.clone(),
+                _type: Type::Type(RRType::TXT),
+                rdata: RData::Vec(rdata.to_vec()),
+                ..get_rr(None)
+            }];
+            message
+        };
+        let rollback = zone.prepend(ROLLBACK_LABEL.to_string());
+
+        assert_eq!(
+            get_rollback_serial(&message(&rollback, b"\x0242"), &zone, &class).unwrap(),
+            Some(42)
+        );
+        assert_eq!(
+            get_rollback_serial(&message(&zone, b"\x0242"), &zone, &class).unwrap(),
+            None
+        );
+        assert!(matches!(
+            get_rollback_serial(&message(&rollback, b"\x02ab"), &zone, &class),
+            Err(ZNSError::Formerr { .. })
+        ));
+
+        let home = zone.prepend("home".to_string());
+        assert!(matches!(
+            get_rollback_serial(
+                &message(&home.prepend(ROLLBACK_LABEL.to_string()), b"\x0242"),
+                &home,
+                &class
+            ),
+            Err(ZNSError::Refused { .. })
+        ));
+    }
+
+    #[test]
+    fn test_apply_rollback() {
+        let mut connection = get_test_connection();
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let txt = RR {
+            name: zone.prepend("home".to_string()),
+            _type: Type::Type(RRType::TXT),
+            rdlength: 3,
+            rdata: RData::Vec(b"\x02hi".to_vec()),
+            ..get_rr(None)
+        };
+
+        ensure_version(&zone, &mut connection).unwrap();
+        insert_into_database(&txt, &mut connection).unwrap();
+        let serial = store_next_version(&zone, &mut connection).unwrap();
+        delete_from_database(&txt.name, None, txt.class.clone(), None, &mut connection);
+        store_next_version(&zone, &mut connection).unwrap();
+
+        // Failed rollbacks are undone, as in the transaction of an update
+        let apply = |connection: &mut PgConnection| {
+            let mut result = Err(ZNSError::Servfail {
+                message: String::new(),
+            });
+            let _ = connection.transaction(|connection| {
+                result = apply_rollback(&zone, serial, connection);
+                match result {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(diesel::result::Error::RollbackTransaction),
+                }
+            });
+            result
+        };
+
+        // The restored records must be allowed by the current type policy
+        let denied = Some(vec![Type::Type(RRType::TXT)]);
+        set_type_policy(&zone, None, denied, &mut connection).unwrap();
+        assert!(matches!(
+            apply(&mut connection),
+            Err(ZNSError::Refused { .. })
+        ));
+        set_type_policy(&zone, None, None, &mut connection).unwrap();
+
+        // The restored records must fit in the current quota
+        set_quota(&zone, Some(0), None, None, &mut connection).unwrap();
+        assert!(matches!(
+            apply(&mut connection),
+            Err(ZNSError::Refused { .. })
+        ));
+        set_quota(&zone, None, None, None, &mut connection).unwrap();
+
+        let change = apply(&mut connection).unwrap();
+        assert_eq!(change.added, vec![txt]);
+        assert!(change.deleted.is_empty());
+    }
+}
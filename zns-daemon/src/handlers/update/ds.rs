@@ -0,0 +1,304 @@
+use diesel::PgConnection;
+use ring::digest;
+
+use zns::{
+    dnssec::key_tag,
+    errors::ZNSError,
+    labelstring::LabelString,
+    parser::{FromBytes, ToBytes},
+    reader::Reader,
+    structs::{Class, DsRData, RData, RRClass, RRType, Type, RR},
+};
+
+use crate::db::models::{delete_from_database, get_from_database, insert_into_database};
+
+// Delete sentinels: https://datatracker.ietf.org/doc/html/rfc8078#section-4
+const CDS_DELETE: [u8; 5] = [0, 0, 0, 0, 0];
+const CDNSKEY_DELETE: [u8; 5] = [0, 0, 3, 0, 0];
+
+const ZONE_KEY_FLAG: u16 = 0x0100;
+const DIGEST_SHA256: u8 = 2;
+
+/// Validates a CDS or CDNSKEY record which is added to a zone.
+/// https://datatracker.ietf.org/doc/html/rfc7344#section-4
+pub fn validate_child_record(rr: &RR, zone: &LabelString) -> Option<String> {
+    if &rr.name != zone {
+        return Some(format!(
+            "{:?} record is only allowed at the zone apex",
+            rr._type
+        ));
+    }
+
+    let rdata: Vec<u8> = rr.rdata.clone().into();
+
+    match rr._type {
+        Type::Type(RRType::CDS) => validate_cds(&rdata),
+        Type::Type(RRType::CDNSKEY) => validate_cdnskey(&rdata),
+        _ => None,
+    }
+}
+
+fn validate_cds(rdata: &[u8]) -> Option<String> {
+    if rdata == CDS_DELETE {
+        return None;
+    }
+
+    let ds = match DsRData::from_bytes(&mut Reader::new(rdata)) {
+        Ok(ds) => ds,
+        Err(e) => return Some(e.to_string()),
+    };
+
+    let digest_len = match ds.digest_type {
+        1 => 20,
+        2 => 32,
+        4 => 48,
+        other => return Some(format!("Unsupported CDS digest type: {}", other)),
+    };
+
+    if ds.algorithm == 0 {
+        Some(String::from("CDS algorithm can not be zero"))
+    } else if ds.digest.len() != digest_len {
+        Some(String::from("CDS digest length does not match digest type"))
+    } else {
+        None
+    }
+}
+
+fn validate_cdnskey(rdata: &[u8]) -> Option<String> {
+    if rdata == CDNSKEY_DELETE {
+        return None;
+    }
+
+    if rdata.len() < 5 {
+        return Some(String::from("CDNSKEY rdata is too short"));
+    }
+
+    let flags = u16::from_be_bytes([rdata[0], rdata[1]]);
+
+    if flags & ZONE_KEY_FLAG == 0 {
+        Some(String::from("CDNSKEY must have the zone key flag set"))
+    } else if rdata[2] != 3 {
+        Some(String::from("CDNSKEY protocol must be 3"))
+    } else if rdata[3] == 0 {
+        Some(String::from("CDNSKEY algorithm can not be zero"))
+    } else {
+        None
+    }
+}
+
+/// Creates a DS record (SHA-256 digest) from the CDNSKEY RDATA.
+/// https://datatracker.ietf.org/doc/html/rfc4034#section-5.1.4
+fn cdnskey_to_ds(owner: &LabelString, rdata: &[u8]) -> DsRData {
+    let mut data = LabelString::to_bytes(
+        owner
+            .as_slice()
+            .iter()
+            .map(|label| label.to_lowercase())
+            .collect::<Vec<String>>()
+            .into(),
+    );
+    data.extend(rdata);
+
+    DsRData {
+        key_tag: key_tag(rdata),
+        algorithm: rdata[3],
+        digest_type: DIGEST_SHA256,
+        digest: digest::digest(&digest::SHA256, &data).as_ref().to_vec(),
+    }
+}
+
+/// Replaces the DS RRset of the zone in the parent zone,
+/// based on the CDS and CDNSKEY records published in the zone.
+pub fn synchronize_ds(zone: &LabelString, connection: &mut PgConnection) -> Result<(), ZNSError> {
+    let mut child_records = get_from_database(
+        zone,
+        Some(Type::Type(RRType::CDS)),
+        Class::Class(RRClass::IN),
+        connection,
+    )?;
+    child_records.extend(get_from_database(
+        zone,
+        Some(Type::Type(RRType::CDNSKEY)),
+        Class::Class(RRClass::IN),
+        connection,
+    )?);
+
+    if child_records.is_empty() {
+        return Ok(());
+    }
+
+    let mut ds_records: Vec<RR> = vec![];
+    let mut delete = false;
+
+    for rr in child_records {
+        let rdata: Vec<u8> = rr.rdata.into();
+        let ds = match rr._type {
+            Type::Type(RRType::CDS) if rdata == CDS_DELETE => {
+                delete = true;
+                continue;
+            }
+            Type::Type(RRType::CDNSKEY) if rdata == CDNSKEY_DELETE => {
+                delete = true;
+                continue;
+            }
+            Type::Type(RRType::CDNSKEY) => DsRData::to_bytes(cdnskey_to_ds(zone, &rdata)),
+            _ => rdata,
+        };
+
+        if ds_records
+            .iter()
+            .all(|rr| rr.rdata != RData::Vec(ds.clone()))
+        {
+            ds_records.push(RR {
+                name: zone.clone(),
+                _type: Type::Type(RRType::DS),
+                class: Class::Class(RRClass::IN),
+                ttl: rr.ttl,
+                rdlength: ds.len() as u16,
+                rdata: RData::Vec(ds),
+            });
+        }
+    }
+
+    delete_from_database(
+        zone,
+        Some(Type::Type(RRType::DS)),
+        Class::Class(RRClass::IN),
+        None,
+        connection,
+    );
+
+    if !delete {
+        for rr in &ds_records {
+            insert_into_database(rr, connection)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::prelude::*;
+
+    use crate::db::lib::tests::get_test_connection;
+
+    use super::*;
+
+    fn get_cdnskey() -> Vec<u8> {
+        // Example taken from https://datatracker.ietf.org/doc/html/rfc4509#section-2.3
+        let mut rdata = vec![1, 0, 3, 5];
+        rdata.extend(
+            BASE64_STANDARD
+                .decode(
+                    "AQOeiiR0GOMYkDshWoSKz9XzfwJr1AYtsmx3TGkJaNXVbfi/\
+                     2pHm822aJ5iI9BMzNXxeYCmZDRD99WYwYqUSdjMmmAphXdvx\
+                     egXd/M5+X7OrzKBaMbCVdFLUUh6DhweJBjEVv5f2wwjM9Xzc\
+                     nOf+EPbtG9DMBmADjFDc2w/rljwvFw==",
+                )
+                .unwrap(),
+        );
+        rdata
+    }
+
+    fn get_child_rr(name: &LabelString, _type: RRType, rdata: Vec<u8>) -> RR {
+        RR {
+            name: name.clone(),
+            _type: Type::Type(_type),
+            class: Class::Class(RRClass::IN),
+            ttl: 300,
+            rdlength: rdata.len() as u16,
+            rdata: RData::Vec(rdata),
+        }
+    }
+
+    #[test]
+    fn test_cdnskey_to_ds() {
+        let ds = cdnskey_to_ds(&LabelString::from("dskey.example.com"), &get_cdnskey());
+
+        assert_eq!(ds.key_tag, 60485);
+        assert_eq!(ds.algorithm, 5);
+        assert_eq!(ds.digest_type, DIGEST_SHA256);
+        assert_eq!(
+            ds.digest,
+            [
+                0xD4, 0xB7, 0xD5, 0x20, 0xE7, 0xBB, 0x5F, 0x0F, 0x67, 0x67, 0x4A, 0x0C, 0xCE, 0xB1,
+                0xE3, 0xE0, 0x61, 0x4B, 0x93, 0xC4, 0xF9, 0xE9, 0x9B, 0x83, 0x83, 0xF6, 0xA1, 0xE4,
+                0x46, 0x9D, 0xA5, 0x0A
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let zone = LabelString::from("dskey.example.com");
+
+        let cdnskey = get_child_rr(&zone, RRType::CDNSKEY, get_cdnskey());
+        assert!(validate_child_record(&cdnskey, &zone).is_none());
+
+        let not_apex = get_child_rr(
+            &zone.prepend("sub".to_string()),
+            RRType::CDNSKEY,
+            get_cdnskey(),
+        );
+        assert!(validate_child_record(&not_apex, &zone).is_some());
+
+        let mut no_zone_key = get_cdnskey();
+        no_zone_key[0] = 0;
+        let cdnskey = get_child_rr(&zone, RRType::CDNSKEY, no_zone_key);
+        assert!(validate_child_record(&cdnskey, &zone).is_some());
+
+        let cdnskey = get_child_rr(&zone, RRType::CDNSKEY, CDNSKEY_DELETE.to_vec());
+        assert!(validate_child_record(&cdnskey, &zone).is_none());
+
+        let cds = get_child_rr(&zone, RRType::CDS, vec![0xEC, 0x45, 5, 2, 1, 2, 3]);
+        assert!(validate_child_record(&cds, &zone).is_some());
+
+        let cds = get_child_rr(
+            &zone,
+            RRType::CDS,
+            DsRData::to_bytes(cdnskey_to_ds(&zone, &get_cdnskey())),
+        );
+        assert!(validate_child_record(&cds, &zone).is_none());
+
+        let cds = get_child_rr(&zone, RRType::CDS, CDS_DELETE.to_vec());
+        assert!(validate_child_record(&cds, &zone).is_none());
+    }
+
+    #[test]
+    fn test_synchronize_ds() {
+        let mut connection = get_test_connection();
+        let zone = LabelString::from("dskey.example.com");
+
+        let get_ds = |connection: &mut PgConnection| {
+            get_from_database(
+                &zone,
+                Some(Type::Type(RRType::DS)),
+                Class::Class(RRClass::IN),
+                connection,
+            )
+            .unwrap()
+        };
+
+        let cdnskey = get_child_rr(&zone, RRType::CDNSKEY, get_cdnskey());
+        let cds = get_child_rr(
+            &zone,
+            RRType::CDS,
+            DsRData::to_bytes(cdnskey_to_ds(&zone, &get_cdnskey())),
+        );
+
+        assert!(insert_into_database(&cdnskey, &mut connection).is_ok());
+        assert!(insert_into_database(&cds, &mut connection).is_ok());
+        assert!(synchronize_ds(&zone, &mut connection).is_ok());
+
+        let ds = get_ds(&mut connection);
+        assert_eq!(ds.len(), 1);
+        assert_eq!(ds[0].rdata, cds.rdata);
+
+        let delete = get_child_rr(&zone, RRType::CDS, CDS_DELETE.to_vec());
+        assert!(insert_into_database(&delete, &mut connection).is_ok());
+        assert!(synchronize_ds(&zone, &mut connection).is_ok());
+
+        assert!(get_ds(&mut connection).is_empty());
+    }
+}
@@ -0,0 +1,422 @@
+use std::{fmt::Display, str::FromStr};
+
+use diesel::PgConnection;
+use zns::{
+    errors::ZNSError,
+    labelstring::LabelString,
+    parser::parse_types,
+    structs::{Type, RR},
+};
+
+use crate::db::models::{delete_grant, get_grants, insert_grant, GrantRecord};
+
+/// How the name of an updated record is matched by a grant, as in the `update-policy` of BIND
+/// https://bind9.readthedocs.io/en/latest/reference.html#namedconf-statement-update-policy
+#[derive(Debug, PartialEq)]
+enum Rule {
+    /// The name of the record equals the name of the grant
+    Name,
+    /// The name of the record is the name of the grant or below it
+    Subdomain,
+    /// The name of the record matches the wildcard name of the grant, e.g. `*.home.bob.user.zeus.gent`
+    Wildcard,
+    /// The name of the record equals the name of the key
+    SelfName,
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "name" => Ok(Rule::Name),
+            "subdomain" => Ok(Rule::Subdomain),
+            "wildcard" => Ok(Rule::Wildcard),
+            "self" => Ok(Rule::SelfName),
+            _ => Err(format!("Unknown grant rule: {}", s)),
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rule::Name => write!(f, "name"),
+            Rule::Subdomain => write!(f, "subdomain"),
+            Rule::Wildcard => write!(f, "wildcard"),
+            Rule::SelfName => write!(f, "self"),
+        }
+    }
+}
+
+/// Grant which allows a key to update records matching the rule and types
+#[derive(Debug, PartialEq)]
+pub struct Grant {
+    rule: Rule,
+    name: LabelString,
+    /// Empty if all types are allowed
+    types: Vec<Type>,
+}
+
+impl TryFrom<GrantRecord> for Grant {
+    type Error = ZNSError;
+
+    fn try_from(record: GrantRecord) -> Result<Self, Self::Error> {
+        let invalid = |message: String| ZNSError::Servfail {
+            message: format!("Invalid grant for {}: {}", record.identity, message),
+        };
+
+        let rule = record.rule.parse().map_err(invalid)?;
+        let types = parse_types(&record.types).map_err(invalid)?;
+
+        Ok(Grant {
+            rule,
+            name: LabelString::from(record.name.trim_end_matches('.')),
+            types,
+        })
+    }
+}
+
+/// The rule, the name of the grant (except for `self`) and the types, if not all types are allowed
+impl Display for Grant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.rule)?;
+        if self.rule != Rule::SelfName {
+            write!(f, " {}", self.name)?;
+        }
+        for _type in &self.types {
+            write!(f, " {}", _type)?;
+        }
+        Ok(())
+    }
+}
+
+impl Grant {
+    /// Parses a grant given by an admin
+    pub fn new(rule: &str, name: &LabelString, types: &str) -> Result<Self, ZNSError> {
+        let invalid = |message: String| ZNSError::Refused { message };
+
+        let rule = rule.parse().map_err(invalid)?;
+        let types = parse_types(types).map_err(invalid)?;
+        let name = match rule {
+            Rule::SelfName => LabelString::from(""),
+            Rule::Wildcard if name.as_slice().first().is_none_or(|label| label != "*") => {
+                return Err(ZNSError::Refused {
+                    message: format!("Name of a wildcard grant must start with `*`: {}", name),
+                })
+            }
+            _ => name.clone(),
+        };
+
+        Ok(Grant { rule, name, types })
+    }
+
+    fn to_record(&self, identity: &LabelString) -> GrantRecord {
+        GrantRecord {
+            identity: identity.to_string(),
+            rule: self.rule.to_string(),
+            name: self.name.to_string(),
+            types: self
+                .types
+                .iter()
+                .map(|_type| _type.to_string())
+                .collect::<Vec<String>>()
+                .join(" "),
+        }
+    }
+
+    fn matches(&self, identity: &LabelString, rr: &RR) -> bool {
+        let name_matches = match self.rule {
+            Rule::Name => rr.name == self.name,
+            Rule::Subdomain => rr.name.is_subdomain_of(&self.name),
+            Rule::Wildcard => match self.name.as_slice().split_first() {
+                Some((label, parent)) if label == "*" => {
+                    let parent: LabelString = parent.into();
+                    rr.name.len() > parent.len() && rr.name.is_subdomain_of(&parent)
+                }
+                _ => false,
+            },
+            Rule::SelfName => &rr.name == identity,
+        };
+
+        // Deleting all RRsets of a name (type ANY) requires a grant for all types or for ANY
+        name_matches && (self.types.is_empty() || self.types.contains(&rr._type))
+    }
+}
+
+/// Grants of the key, in the order in which they were added
+pub fn list_grants(
+    identity: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<Vec<Grant>, ZNSError> {
+    get_grants(identity, connection)?
+        .into_iter()
+        .map(Grant::try_from)
+        .collect()
+}
+
+/// Restricts the key to the records matching the grant and its other grants
+pub fn add_grant(
+    identity: &LabelString,
+    grant: &Grant,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    if list_grants(identity, connection)?.contains(grant) {
+        return Err(ZNSError::Refused {
+            message: format!("Key {} already has grant {}", identity, grant),
+        });
+    }
+    insert_grant(&grant.to_record(identity), connection)
+}
+
+/// Removes a grant of the key, the key may update the entire zone once it has no grants left
+pub fn remove_grant(
+    identity: &LabelString,
+    grant: &Grant,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    // Grants inserted in the database directly may be written differently, e.g. `A,AAAA`
+    let mut deleted = 0;
+    for record in get_grants(identity, connection)? {
+        if Grant::try_from(record.clone()).is_ok_and(|stored| &stored == grant) {
+            deleted += delete_grant(&record, connection)?;
+        }
+    }
+
+    match deleted {
+        0 => Err(ZNSError::Refused {
+            message: format!("Key {} does not have grant {}", identity, grant),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that every record in the update section is covered by a grant of the key.
+/// Keys without any grants may update the entire zone.
+pub fn check_grants(
+    identity: &LabelString,
+    records: &[RR],
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    let grants = get_grants(identity, connection)?
+        .into_iter()
+        .map(Grant::try_from)
+        .collect::<Result<Vec<Grant>, ZNSError>>()?;
+
+    if grants.is_empty() {
+        return Ok(());
+    }
+
+    match records
+        .iter()
+        .find(|rr| !grants.iter().any(|grant| grant.matches(identity, rr)))
+    {
+        Some(rr) => Err(ZNSError::Refused {
+            message: format!(
+                "Key {} is not allowed to update {} {:?}",
+                identity, rr.name, rr._type
+            ),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Checks that the key has no grants, for updates of which the changed records are not known
+/// in advance, such as a rollback of the zone
+pub fn check_unrestricted(
+    identity: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    if get_grants(identity, connection)?.is_empty() {
+        Ok(())
+    } else {
+        Err(ZNSError::Refused {
+            message: format!("Key {} is restricted by grants", identity),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::RunQueryDsl;
+    use zns::{structs::RRType, test_utils::get_rr};
+
+    use crate::{
+        config::Config,
+        db::{lib::tests::get_test_connection, models::schema::update_grants},
+    };
+
+    use super::*;
+
+    fn insert_grant(
+        identity: &LabelString,
+        rule: &str,
+        name: &str,
+        types: &str,
+        connection: &mut PgConnection,
+    ) {
+        diesel::insert_into(update_grants::table)
+            .values(GrantRecord {
+                identity: identity.to_string(),
+                rule: rule.to_string(),
+                name: name.to_string(),
+                types: types.to_string(),
+            })
+            .execute(connection)
+            .unwrap();
+    }
+
+    fn get_record(name: &LabelString, _type: RRType) -> RR {
+        RR {
+            name: name.clone(),
+            _type: Type::Type(_type),
+            ..get_rr(None)
+        }
+    }
+
+    #[test]
+    fn test_rules() {
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let key = zone.prepend("router".to_string());
+        let home = zone.prepend("home".to_string());
+        let host = home.prepend("host".to_string());
+
+        let grant = |rule: Rule, name: &LabelString, types: Vec<Type>| Grant {
+            rule,
+            name: name.clone(),
+            types,
+        };
+
+        let name = grant(Rule::Name, &home, vec![]);
+        assert!(name.matches(&key, &get_record(&home, RRType::TXT)));
+        assert!(!name.matches(&key, &get_record(&host, RRType::TXT)));
+
+        let subdomain = grant(Rule::Subdomain, &home, vec![]);
+        assert!(subdomain.matches(&key, &get_record(&home, RRType::TXT)));
+        assert!(subdomain.matches(&key, &get_record(&host, RRType::TXT)));
+        assert!(!subdomain.matches(&key, &get_record(&zone, RRType::TXT)));
+
+        let wildcard = grant(Rule::Wildcard, &home.prepend("*".to_string()), vec![]);
+        assert!(!wildcard.matches(&key, &get_record(&home, RRType::TXT)));
+        assert!(wildcard.matches(&key, &get_record(&host, RRType::TXT)));
+
+        let own = grant(Rule::SelfName, &LabelString::from(""), vec![]);
+        assert!(own.matches(&key, &get_record(&key, RRType::TXT)));
+        assert!(!own.matches(&key, &get_record(&home, RRType::TXT)));
+
+        let types = grant(
+            Rule::Name,
+            &home,
+            vec![Type::Type(RRType::A), Type::Type(RRType::AAAA)],
+        );
+        assert!(types.matches(&key, &get_record(&home, RRType::AAAA)));
+        assert!(!types.matches(&key, &get_record(&home, RRType::TXT)));
+        assert!(!types.matches(&key, &get_record(&home, RRType::ANY)));
+        assert!(name.matches(&key, &get_record(&home, RRType::ANY)));
+    }
+
+    #[test]
+    fn test_check_grants() {
+        let mut connection = get_test_connection();
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let key = zone.prepend("router".to_string());
+        let home = zone.prepend("home".to_string());
+
+        // No grants: the whole zone may be updated
+        assert!(check_grants(&key, &[get_record(&zone, RRType::TXT)], &mut connection).is_ok());
+        assert!(check_unrestricted(&key, &mut connection).is_ok());
+
+        insert_grant(&key, "name", &home.to_string(), "A AAAA", &mut connection);
+        insert_grant(&key, "self", "", "TXT", &mut connection);
+
+        assert!(check_grants(
+            &key,
+            &[
+                get_record(&home, RRType::A),
+                get_record(&home, RRType::AAAA),
+                get_record(&key, RRType::TXT)
+            ],
+            &mut connection
+        )
+        .is_ok());
+
+        // A single record outside of the grants rejects the entire update
+        assert!(matches!(
+            check_grants(
+                &key,
+                &[get_record(&home, RRType::A), get_record(&home, RRType::TXT)],
+                &mut connection
+            ),
+            Err(ZNSError::Refused { .. })
+        ));
+        assert!(matches!(
+            check_unrestricted(&key, &mut connection),
+            Err(ZNSError::Refused { .. })
+        ));
+
+        insert_grant(&zone, "nothing", "", "", &mut connection);
+        assert!(matches!(
+            check_grants(&zone, &[get_record(&zone, RRType::A)], &mut connection),
+            Err(ZNSError::Servfail { .. })
+        ));
+    }
+
+    #[test]
+    fn test_manage_grants() {
+        let mut connection = get_test_connection();
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let key = zone.prepend("router".to_string());
+        let home = zone.prepend("home".to_string());
+
+        assert!(Grant::new("nothing", &home, "").is_err());
+        assert!(Grant::new("name", &home, "A NOTATYPE").is_err());
+        assert!(Grant::new("wildcard", &home, "").is_err());
+
+        let name = Grant::new("name", &home, "A,AAAA").unwrap();
+        let own = Grant::new("self", &home, "TXT").unwrap();
+        assert_eq!(name.to_string(), format!("name {} A AAAA", home));
+        assert_eq!(own.to_string(), "self TXT");
+
+        add_grant(&key, &name, &mut connection).unwrap();
+        add_grant(&key, &own, &mut connection).unwrap();
+        assert!(add_grant(&key, &name, &mut connection).is_err());
+        assert_eq!(list_grants(&key, &mut connection).unwrap(), vec![name, own]);
+        assert!(check_grants(&key, &[get_record(&zone, RRType::A)], &mut connection).is_err());
+
+        // Grants written differently in the database are removed as well
+        insert_grant(
+            &key,
+            "Subdomain",
+            &format!("{}.", home),
+            "aaaa,a",
+            &mut connection,
+        );
+        remove_grant(
+            &key,
+            &Grant::new("subdomain", &home, "AAAA A").unwrap(),
+            &mut connection,
+        )
+        .unwrap();
+        assert_eq!(list_grants(&key, &mut connection).unwrap().len(), 2);
+
+        remove_grant(
+            &key,
+            &Grant::new("name", &home, "A AAAA").unwrap(),
+            &mut connection,
+        )
+        .unwrap();
+        remove_grant(
+            &key,
+            &Grant::new("self", &zone, "TXT").unwrap(),
+            &mut connection,
+        )
+        .unwrap();
+        assert!(remove_grant(
+            &key,
+            &Grant::new("self", &zone, "TXT").unwrap(),
+            &mut connection
+        )
+        .is_err());
+        assert!(check_unrestricted(&key, &mut connection).is_ok());
+    }
+}
@@ -0,0 +1,252 @@
+use zns::{
+    errors::ZNSError,
+    structs::{RRType, Type, RR},
+};
+
+const MAX_LABEL_LENGTH: usize = 63;
+const MAX_NAME_LENGTH: usize = 255;
+
+/// Validates the RDATA of a record which is added, based on its type
+pub fn validate_rdata(rr: &RR) -> Result<(), ZNSError> {
+    let rdata: Vec<u8> = rr.rdata.clone().into();
+
+    let result = match &rr._type {
+        Type::Type(RRType::A) => validate_length(&rdata, 4),
+        Type::Type(RRType::AAAA) => validate_length(&rdata, 16),
+        Type::Type(RRType::TXT) => validate_txt(&rdata),
+        Type::Type(RRType::CNAME) | Type::Type(RRType::PTR) => validate_target(&rdata),
+        Type::Type(RRType::NS) => validate_host_name(&rdata, "name server", false),
+        Type::Type(RRType::MX) => validate_mx(&rdata),
+        Type::Type(RRType::SRV) => validate_srv(&rdata),
+        Type::Type(RRType::CAA) => validate_caa(&rdata),
+        _ => Ok(()),
+    };
+
+    result.map_err(|message| ZNSError::Formerr {
+        message: format!("Invalid {} record for {}: {}", rr._type, rr.name, message),
+    })
+}
+
+fn validate_length(rdata: &[u8], length: usize) -> Result<(), String> {
+    if rdata.len() == length {
+        Ok(())
+    } else {
+        Err(format!(
+            "RDATA must be {} octets, not {}",
+            length,
+            rdata.len()
+        ))
+    }
+}
+
+/// https://datatracker.ietf.org/doc/html/rfc1035#section-3.3.14
+fn validate_txt(rdata: &[u8]) -> Result<(), String> {
+    if rdata.is_empty() {
+        return Err(String::from("at least one character-string is required"));
+    }
+
+    let mut position = 0;
+    while position < rdata.len() {
+        let length = rdata[position] as usize;
+        position += 1 + length;
+        if position > rdata.len() {
+            return Err(format!(
+                "character-string of {} octets exceeds the RDATA",
+                length
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Parses an uncompressed domain name, returns its labels and the length of the name
+fn read_name(rdata: &[u8]) -> Result<(Vec<&[u8]>, usize), String> {
+    let mut labels = vec![];
+    let mut position = 0;
+
+    loop {
+        let length = *rdata.get(position).ok_or("name is not terminated")? as usize;
+        if length == 0 {
+            position += 1;
+            break;
+        }
+        if length > MAX_LABEL_LENGTH {
+            return Err(format!("label of {} octets is too long", length));
+        }
+        let label = rdata
+            .get(position + 1..position + 1 + length)
+            .ok_or("label exceeds the RDATA")?;
+        labels.push(label);
+        position += 1 + length;
+    }
+
+    if position > MAX_NAME_LENGTH {
+        return Err(format!("name of {} octets is too long", position));
+    }
+    Ok((labels, position))
+}
+
+/// The target must be a host name (rfc1123 section 2.1), or the root if `allow_root` is set
+fn validate_host_name(rdata: &[u8], field: &str, allow_root: bool) -> Result<(), String> {
+    let (labels, length) = read_name(rdata)?;
+    if length != rdata.len() {
+        return Err(format!("trailing data after {}", field));
+    }
+    if labels.is_empty() {
+        return match allow_root {
+            true => Ok(()),
+            false => Err(format!("{} can't be the root", field)),
+        };
+    }
+
+    let valid_label = |label: &&[u8]| {
+        label
+            .iter()
+            .all(|c| c.is_ascii_alphanumeric() || *c == b'-')
+            && !label.starts_with(b"-")
+            && !label.ends_with(b"-")
+    };
+    if labels.iter().all(valid_label) {
+        Ok(())
+    } else {
+        Err(format!("{} is not a valid host name", field))
+    }
+}
+
+/// CNAME targets can be any domain name, e.g. `_acme-challenge.example.org`
+fn validate_target(rdata: &[u8]) -> Result<(), String> {
+    let (labels, length) = read_name(rdata)?;
+    if length != rdata.len() {
+        Err(String::from("trailing data after target"))
+    } else if labels.is_empty() {
+        Err(String::from("target can't be the root"))
+    } else {
+        Ok(())
+    }
+}
+
+/// A target of `.` is a null MX (rfc7505)
+/// https://datatracker.ietf.org/doc/html/rfc1035#section-3.3.9
+fn validate_mx(rdata: &[u8]) -> Result<(), String> {
+    if rdata.len() < 3 {
+        return Err(String::from("RDATA is too short"));
+    }
+    validate_host_name(&rdata[2..], "exchange", true)
+}
+
+/// A target of `.` means the service is not available
+/// https://datatracker.ietf.org/doc/html/rfc2782
+fn validate_srv(rdata: &[u8]) -> Result<(), String> {
+    if rdata.len() < 7 {
+        return Err(String::from("RDATA is too short"));
+    }
+    validate_host_name(&rdata[6..], "target", true)
+}
+
+/// https://datatracker.ietf.org/doc/html/rfc8659#section-4.1
+fn validate_caa(rdata: &[u8]) -> Result<(), String> {
+    if rdata.len() < 2 {
+        return Err(String::from("RDATA is too short"));
+    }
+
+    let length = rdata[1] as usize;
+    let tag = rdata.get(2..2 + length).ok_or("tag exceeds the RDATA")?;
+    if tag.is_empty() || tag.len() > 15 {
+        Err(format!(
+            "tag length must be between 1 and 15, not {}",
+            length
+        ))
+    } else if !tag.iter().all(u8::is_ascii_alphanumeric) {
+        Err(String::from("tag must be alphanumeric"))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zns::{
+        labelstring::LabelString,
+        parser::ToBytes,
+        structs::{Class, RData, RRClass},
+    };
+
+    use super::*;
+
+    fn get_record(_type: RRType, rdata: Vec<u8>) -> RR {
+        RR {
+            name: LabelString::from("bob.user.zeus.gent"),
+            _type: Type::Type(_type),
+            class: Class::Class(RRClass::IN),
+            ttl: 300,
+            rdlength: rdata.len() as u16,
+            rdata: RData::Vec(rdata),
+        }
+    }
+
+    fn name(name: &str) -> Vec<u8> {
+        LabelString::to_bytes(LabelString::from(name))
+    }
+
+    fn is_valid(_type: RRType, rdata: Vec<u8>) -> bool {
+        validate_rdata(&get_record(_type, rdata)).is_ok()
+    }
+
+    #[test]
+    fn test_address() {
+        assert!(is_valid(RRType::A, vec![10, 0, 0, 1]));
+        assert!(!is_valid(RRType::A, vec![10, 0, 0, 1, 2, 3, 4]));
+        assert!(is_valid(RRType::AAAA, vec![0; 16]));
+        assert!(!is_valid(RRType::AAAA, vec![0; 4]));
+        assert!(matches!(
+            validate_rdata(&get_record(RRType::A, vec![])),
+            Err(ZNSError::Formerr { .. })
+        ));
+    }
+
+    #[test]
+    fn test_txt() {
+        assert!(is_valid(RRType::TXT, b"\x05hello\x00\x02hi".to_vec()));
+        assert!(!is_valid(RRType::TXT, b"\x06hello".to_vec()));
+        assert!(!is_valid(RRType::TXT, vec![]));
+    }
+
+    #[test]
+    fn test_targets() {
+        assert!(is_valid(RRType::CNAME, name("_acme.example.org")));
+        assert!(!is_valid(RRType::CNAME, vec![0]));
+        assert!(!is_valid(RRType::CNAME, vec![3, b'o', b'r']));
+        assert!(!is_valid(
+            RRType::CNAME,
+            [name("example.org"), vec![1]].concat()
+        ));
+        assert!(!is_valid(
+            RRType::CNAME,
+            [vec![64], vec![b'a'; 64], vec![0]].concat()
+        ));
+
+        let mx = |target: &str| [vec![0, 10], name(target)].concat();
+        assert!(is_valid(RRType::MX, mx("mail.example.org")));
+        assert!(is_valid(RRType::MX, vec![0, 0, 0]));
+        assert!(!is_valid(RRType::MX, mx("_mail.example.org")));
+        assert!(!is_valid(RRType::MX, mx("-mail.example.org")));
+        assert!(!is_valid(RRType::MX, vec![0, 10]));
+
+        let srv = |target: &str| [vec![0, 10, 0, 5, 1, 187], name(target)].concat();
+        assert!(is_valid(RRType::SRV, srv("server.example.org")));
+        assert!(!is_valid(RRType::SRV, srv("server_1.example.org")));
+        assert!(!is_valid(RRType::SRV, vec![0, 10, 0, 5]));
+    }
+
+    #[test]
+    fn test_caa() {
+        assert!(is_valid(
+            RRType::CAA,
+            b"\x00\x05issueletsencrypt.org".to_vec()
+        ));
+        assert!(is_valid(RRType::CAA, b"\x80\x05iodef".to_vec()));
+        assert!(!is_valid(RRType::CAA, b"\x00\x00".to_vec()));
+        assert!(!is_valid(RRType::CAA, b"\x00\x06issue".to_vec()));
+        assert!(!is_valid(RRType::CAA, b"\x00\x05is-ue".to_vec()));
+    }
+}
@@ -0,0 +1,345 @@
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use diesel::PgConnection;
+use zns::{errors::ZNSError, labelstring::LabelString, structs::RR};
+
+use crate::{
+    audit::format_time,
+    auth::owners::user_zone,
+    config::Config,
+    db::models::{
+        delete_zone_versions, get_latest_zone_version, get_zone_records, get_zone_serial,
+        get_zone_version_records, get_zone_versions, increment_zone_serial, insert_zone_version,
+        replace_zone_records, Record, ZoneVersionRecord,
+    },
+};
+
+/// Serial of a zone without versions
+const INITIAL_SERIAL: i64 = 1;
+
+/// Snapshot of the records of a zone, including all zones below it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Version {
+    pub serial: u32,
+    /// Unix timestamp in seconds
+    pub time: u64,
+    pub records: u64,
+}
+
+/// Records which were deleted and added between two consecutive versions of a zone
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub from: u32,
+    pub to: u32,
+    pub deleted: Vec<RR>,
+    pub added: Vec<RR>,
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} ({} records)",
+            self.serial,
+            format_time(self.time),
+            self.records
+        )
+    }
+}
+
+/// Zone below the authoritative zone of which the versions are kept
+fn history_zone(zone: &LabelString) -> Result<LabelString, ZNSError> {
+    user_zone(zone).ok_or(ZNSError::Refused {
+        message: format!(
+            "Zone {} is not below {}",
+            zone,
+            Config::get().authoritative_zone
+        ),
+    })
+}
+
+fn now() -> Result<i64, ZNSError> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })
+}
+
+/// Stores the current records of the zone as a version, older versions beyond the
+/// configured retention are removed. The authoritative zone contains the records of all zones,
+/// so its serial is incremented as well.
+fn store_version(
+    zone: &LabelString,
+    serial: i64,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    let records = get_zone_records(zone, connection)?;
+    insert_zone_version(
+        &ZoneVersionRecord {
+            zone: zone.to_string().to_lowercase(),
+            serial,
+            time: now()?,
+        },
+        records,
+        connection,
+    )?;
+    delete_zone_versions(
+        zone,
+        serial - Config::get().zone_versions as i64,
+        connection,
+    )?;
+    increment_zone_serial(
+        &Config::get().authoritative_zone,
+        INITIAL_SERIAL,
+        connection,
+    )
+    .map(|_| ())
+}
+
+/// SOA serial of the zone: the serial of its latest version
+pub fn get_serial(zone: &LabelString, connection: &mut PgConnection) -> Result<u32, ZNSError> {
+    Ok(get_latest_zone_version(&history_zone(zone)?, connection)?
+        .map_or(INITIAL_SERIAL, |version| version.serial) as u32)
+}
+
+/// SOA serial of the authoritative zone, which increases with every version of a zone in it
+pub fn get_apex_serial(connection: &mut PgConnection) -> Result<u32, ZNSError> {
+    Ok(
+        get_zone_serial(&Config::get().authoritative_zone, connection)?.unwrap_or(INITIAL_SERIAL)
+            as u32,
+    )
+}
+
+/// Serial of the latest version of the zone, if it has versions
+pub fn get_latest_serial(
+    zone: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<Option<u32>, ZNSError> {
+    Ok(get_latest_zone_version(&history_zone(zone)?, connection)?
+        .map(|version| version.serial as u32))
+}
+
+/// Stores the current records as the first version if the zone has no versions yet,
+/// so the first change of a zone can be rolled back as well
+pub fn ensure_version(zone: &LabelString, connection: &mut PgConnection) -> Result<(), ZNSError> {
+    let zone = history_zone(zone)?;
+    match get_latest_zone_version(&zone, connection)? {
+        Some(_) => Ok(()),
+        None => store_version(&zone, INITIAL_SERIAL, connection),
+    }
+}
+
+/// Stores the records of the zone after a change as a new version, returns its serial
+pub fn store_next_version(
+    zone: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<u32, ZNSError> {
+    let zone = history_zone(zone)?;
+    let serial = get_latest_zone_version(&zone, connection)?
+        .map_or(INITIAL_SERIAL, |version| version.serial)
+        + 1;
+    store_version(&zone, serial, connection)?;
+    Ok(serial as u32)
+}
+
+/// Stores the records of a zone transferred from the primary as a version with the serial of
+/// the primary. Versions which are not older are removed first, e.g. when the primary was reset.
+pub fn store_primary_version(
+    zone: &LabelString,
+    serial: u32,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    let zone = history_zone(zone)?;
+    if get_latest_zone_version(&zone, connection)?
+        .is_some_and(|version| version.serial >= serial as i64)
+    {
+        delete_zone_versions(&zone, i64::MAX, connection)?;
+    }
+    store_version(&zone, serial as i64, connection)
+}
+
+/// Kept versions of the zone, newest first
+pub fn get_versions(
+    zone: &LabelString,
+    connection: &mut PgConnection,
+) -> Result<Vec<Version>, ZNSError> {
+    Ok(get_zone_versions(&history_zone(zone)?, connection)?
+        .into_iter()
+        .map(|(version, records)| Version {
+            serial: version.serial as u32,
+            time: version.time.max(0) as u64,
+            records: records as u64,
+        })
+        .collect())
+}
+
+/// Replaces the records of the zone with the records of a previous version.
+/// The result is stored as a new version, the change to this version is returned.
+pub fn rollback(
+    zone: &LabelString,
+    serial: u32,
+    connection: &mut PgConnection,
+) -> Result<Change, ZNSError> {
+    let zone = history_zone(zone)?;
+    let records =
+        get_zone_version_records(&zone, serial as i64, connection)?.ok_or(ZNSError::Refused {
+            message: format!("Version {} of {} does not exist", serial, zone),
+        })?;
+    let current = get_zone_records(&zone, connection)?;
+    let from = get_serial(&zone, connection)?;
+
+    replace_zone_records(&zone, &records, connection)?;
+    Ok(Change {
+        from,
+        to: store_next_version(&zone, connection)?,
+        deleted: difference(&current, &records),
+        added: difference(&records, &current),
+    })
+}
+
+/// Records of `records` which are not in `other`
+fn difference(records: &[Record], other: &[Record]) -> Vec<RR> {
+    let key = |r: &Record| {
+        (
+            r.name.to_lowercase(),
+            r._type,
+            r.class,
+            r.ttl,
+            r.rdata.clone(),
+        )
+    };
+    let other: HashSet<_> = other.iter().map(key).collect();
+    records
+        .iter()
+        .filter(|record| !other.contains(&key(record)))
+        .filter_map(|record| record.clone().into())
+        .collect()
+}
+
+/// Changes of the zone since the version with the given serial, oldest first.
+/// Returns `None` if that version is not kept.
+pub fn get_changes(
+    zone: &LabelString,
+    serial: u32,
+    connection: &mut PgConnection,
+) -> Result<Option<Vec<Change>>, ZNSError> {
+    let zone = history_zone(zone)?;
+    let latest = get_serial(&zone, connection)? as i64;
+    let Some(mut previous) = get_zone_version_records(&zone, serial as i64, connection)? else {
+        return Ok(None);
+    };
+
+    let mut changes = vec![];
+    for next in serial as i64 + 1..=latest {
+        let Some(records) = get_zone_version_records(&zone, next, connection)? else {
+            return Ok(None);
+        };
+        changes.push(Change {
+            from: (next - 1) as u32,
+            to: next as u32,
+            deleted: difference(&previous, &records),
+            added: difference(&records, &previous),
+        });
+        previous = records;
+    }
+    Ok(Some(changes))
+}
+
+#[cfg(test)]
+mod tests {
+    use zns::{
+        structs::{Class, RData, RRClass},
+        test_utils::get_rr,
+    };
+
+    use crate::db::{
+        lib::tests::get_test_connection,
+        models::{get_from_database, insert_into_database},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_rollback() {
+        let mut connection = get_test_connection();
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let home = zone.prepend("home".to_string());
+        let record = |name: &LabelString, rdata: Vec<u8>| RR {
+            name: name.clone(),
+            rdata: RData::Vec(rdata),
+            ..get_rr(None)
+        };
+        let records = |name: &LabelString, connection: &mut PgConnection| {
+            get_from_database(name, None, Class::Class(RRClass::IN), connection)
+                .unwrap()
+                .len()
+        };
+
+        insert_into_database(&record(&zone, vec![1, 2, 3, 4]), &mut connection).unwrap();
+        assert_eq!(get_serial(&home, &mut connection).unwrap(), 1);
+        ensure_version(&home, &mut connection).unwrap();
+        ensure_version(&home, &mut connection).unwrap();
+
+        let apex_serial = get_apex_serial(&mut connection).unwrap();
+        insert_into_database(&record(&home, vec![5, 6, 7, 8]), &mut connection).unwrap();
+        assert_eq!(store_next_version(&home, &mut connection).unwrap(), 2);
+        assert_eq!(get_serial(&zone, &mut connection).unwrap(), 2);
+        // Every version of a zone is a change of the authoritative zone
+        assert_eq!(get_apex_serial(&mut connection).unwrap(), apex_serial + 1);
+
+        let versions = get_versions(&zone, &mut connection).unwrap();
+        assert_eq!(
+            versions
+                .iter()
+                .map(|v| (v.serial, v.records))
+                .collect::<Vec<_>>(),
+            vec![(2, 2), (1, 1)]
+        );
+
+        let changes = get_changes(&zone, 1, &mut connection).unwrap().unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].from, changes[0].to), (1, 2));
+        assert!(changes[0].deleted.is_empty());
+        assert_eq!(changes[0].added, vec![record(&home, vec![5, 6, 7, 8])]);
+        assert_eq!(
+            get_changes(&zone, 2, &mut connection).unwrap(),
+            Some(vec![])
+        );
+        assert_eq!(get_changes(&zone, 42, &mut connection).unwrap(), None);
+
+        let change = rollback(&zone, 1, &mut connection).unwrap();
+        assert_eq!((change.from, change.to), (2, 3));
+        assert_eq!(change.deleted, vec![record(&home, vec![5, 6, 7, 8])]);
+        assert!(change.added.is_empty());
+        let changes = get_changes(&zone, 2, &mut connection).unwrap().unwrap();
+        assert_eq!(changes[0].deleted, vec![record(&home, vec![5, 6, 7, 8])]);
+        assert!(changes[0].added.is_empty());
+
+        assert_eq!(records(&zone, &mut connection), 1);
+        assert_eq!(records(&home, &mut connection), 0);
+
+        let change = rollback(&zone, 2, &mut connection).unwrap();
+        assert_eq!(change.to, 4);
+        assert_eq!(change.added, vec![record(&home, vec![5, 6, 7, 8])]);
+        assert_eq!(records(&home, &mut connection), 1);
+
+        assert!(matches!(
+            rollback(&zone, 42, &mut connection),
+            Err(ZNSError::Refused { .. })
+        ));
+
+        // Only the configured number of versions is kept
+        for _ in 0..Config::get().zone_versions {
+            store_next_version(&zone, &mut connection).unwrap();
+        }
+        let versions = get_versions(&zone, &mut connection).unwrap();
+        assert_eq!(versions.len(), Config::get().zone_versions as usize);
+        assert!(rollback(&zone, 1, &mut connection).is_err());
+    }
+}
@@ -0,0 +1,15 @@
+pub mod audit;
+pub mod auth;
+pub mod catalog;
+pub mod config;
+mod db;
+mod handlers;
+pub mod history;
+pub mod notify;
+pub mod quota;
+pub mod resolver;
+pub mod secondary;
+pub mod type_policy;
+
+pub use db::lib::get_connection;
+pub use handlers::{add_grant, list_grants, remove_grant, Grant};
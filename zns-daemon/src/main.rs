@@ -1,18 +1,291 @@
 use std::{error::Error, net::SocketAddr};
 
-mod auth;
-mod config;
-mod db;
-mod handlers;
-mod resolver;
+use base64::prelude::*;
+use clap::{Parser, Subcommand};
+use diesel::Connection;
+use zns::{labelstring::LabelString, structs::Type};
+use zns_daemon::{
+    add_grant,
+    audit::get_log,
+    auth::{
+        add_tsig_key, list_tsig_keys,
+        owners::{add_zone_owner, create_shared_zone, remove_zone_owner, zone_owners, Owner},
+        remove_tsig_key,
+    },
+    config::Config,
+    get_connection,
+    history::{get_serial, get_versions, rollback},
+    list_grants,
+    quota::{get_quota, get_usage, set_quota},
+    remove_grant,
+    resolver::{tcp_listener_loop, udp_listener_loop},
+    secondary::Secondary,
+    type_policy::{set_type_policy, TypePolicy},
+    Grant,
+};
 
-use config::Config;
+/// DNS server for the zones of users, without a command the server is started
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-use crate::resolver::{tcp_listener_loop, udp_listener_loop};
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage zones: owners, TSIG keys, update grants, quotas, record types, the update log and versions
+    Zone {
+        #[command(subcommand)]
+        command: ZoneCommand,
+    },
+}
+
+/// Owners are `user:<username>` or `key:<key name>`
+#[derive(Subcommand, Debug)]
+enum ZoneCommand {
+    /// Create a shared zone, which is not owned by the user with the same name
+    Create {
+        zone: String,
+        #[arg(required = true)]
+        owners: Vec<Owner>,
+    },
+    /// Allow an owner to update the zone and all zones below it
+    Grant { zone: String, owner: Owner },
+    /// Remove an owner of the zone
+    Revoke { zone: String, owner: Owner },
+    /// List the owners of the zone, including the owners of parent zones
+    List { zone: String },
+    /// Manage the TSIG keys which can update the zone and all zones below it
+    Tsig {
+        #[command(subcommand)]
+        command: TsigCommand,
+    },
+    /// Manage the grants which restrict a key to the matching records
+    Grants {
+        #[command(subcommand)]
+        command: GrantsCommand,
+    },
+    /// Show the quota and usage of the zone, or override its limits.
+    /// Limits which are not given use the configured defaults.
+    Quota {
+        zone: String,
+        /// Maximum number of records
+        #[arg(long)]
+        records: Option<u32>,
+        /// Maximum total size of the RDATA of all records in bytes
+        #[arg(long)]
+        rdata_bytes: Option<u32>,
+        /// Maximum number of distinct names
+        #[arg(long)]
+        names: Option<u32>,
+        /// Remove the overrides of the zone
+        #[arg(long, conflicts_with_all = ["records", "rdata_bytes", "names"])]
+        reset: bool,
+    },
+    /// Show the record types which can be added to the zone, or override its type policy
+    Types {
+        zone: String,
+        /// Types which are allowed instead of the global allowed types, e.g. `A,AAAA,TXT`
+        #[arg(long, value_delimiter = ',')]
+        allow: Option<Vec<Type>>,
+        /// Types which are denied in addition to the global denied types
+        #[arg(long, value_delimiter = ',')]
+        deny: Option<Vec<Type>>,
+        /// Remove the policy of the zone
+        #[arg(long, conflicts_with_all = ["allow", "deny"])]
+        reset: bool,
+    },
+    /// Show the latest updates of the zone and all zones below it
+    Log {
+        zone: String,
+        /// Number of updates
+        #[arg(long, default_value_t = 20)]
+        limit: u32,
+    },
+    /// List the kept versions of the zone, newest first
+    Versions { zone: String },
+    /// Restore the records of the zone and all zones below it to a previous version
+    Rollback { zone: String, serial: u32 },
+}
+
+#[derive(Subcommand, Debug)]
+enum TsigCommand {
+    /// Add a TSIG key and print it in the format of BIND (e.g. for `nsupdate -k`)
+    Add {
+        zone: String,
+        name: String,
+        /// `hmac-sha256`, `hmac-sha384` or `hmac-sha512`
+        #[arg(long, default_value = "hmac-sha256")]
+        algorithm: String,
+        /// Base64 encoded secret, a random secret is generated if not given
+        #[arg(long)]
+        secret: Option<String>,
+    },
+    /// Remove a TSIG key of the zone
+    Remove { zone: String, name: String },
+    /// List the TSIG keys of the zone and all zones below it
+    List { zone: String },
+}
+
+/// Keys are identified by their TSIG key name or SIG(0) signer name
+#[derive(Subcommand, Debug)]
+enum GrantsCommand {
+    /// Allow the key to update the records matching the grant, a key without grants may update
+    /// the entire zone
+    Add {
+        key: String,
+        #[command(flatten)]
+        grant: GrantArgs,
+    },
+    /// Remove a grant of the key
+    Remove {
+        key: String,
+        #[command(flatten)]
+        grant: GrantArgs,
+    },
+    /// List the grants of the key
+    List { key: String },
+}
+
+#[derive(clap::Args, Debug)]
+struct GrantArgs {
+    /// `name`, `subdomain`, `wildcard` or `self`
+    rule: String,
+    /// Name of the grant, not used by `self`
+    #[arg(default_value = "")]
+    name: String,
+    /// Types which may be updated, e.g. `A,AAAA`, all types if not given
+    #[arg(long, default_value = "")]
+    types: String,
+}
+
+fn zone_command(command: ZoneCommand) -> Result<(), Box<dyn Error>> {
+    let connection = &mut get_connection();
+    let zone = |name: &str| LabelString::from(name.trim_end_matches('.'));
+
+    match command {
+        ZoneCommand::Create { zone: name, owners } => {
+            create_shared_zone(&zone(&name), &owners, connection)?
+        }
+        ZoneCommand::Grant { zone: name, owner } => {
+            add_zone_owner(&zone(&name), &owner, connection)?
+        }
+        ZoneCommand::Revoke { zone: name, owner } => {
+            remove_zone_owner(&zone(&name), &owner, connection)?
+        }
+        ZoneCommand::List { zone: name } => {
+            for owner in zone_owners(&zone(&name), connection)? {
+                println!("{}", owner);
+            }
+        }
+        ZoneCommand::Tsig { command } => match command {
+            TsigCommand::Add {
+                zone: name,
+                name: key_name,
+                algorithm,
+                secret,
+            } => {
+                let secret = secret
+                    .map(|secret| BASE64_STANDARD.decode(secret))
+                    .transpose()?;
+                let key = add_tsig_key(
+                    &zone(&key_name),
+                    &zone(&name),
+                    &algorithm,
+                    secret,
+                    connection,
+                )?;
+                println!("key \"{}\" {{", key.name);
+                println!("\talgorithm {};", key.algorithm.name());
+                println!("\tsecret \"{}\";", BASE64_STANDARD.encode(&key.secret));
+                println!("}};");
+            }
+            TsigCommand::Remove {
+                zone: name,
+                name: key_name,
+            } => remove_tsig_key(&zone(&key_name), &zone(&name), connection)?,
+            TsigCommand::List { zone: name } => {
+                for (key, key_zone) in list_tsig_keys(&zone(&name), connection)? {
+                    println!("{} {} {}", key.name, key.algorithm.name(), key_zone);
+                }
+            }
+        },
+        ZoneCommand::Grants { command } => match command {
+            GrantsCommand::Add { key, grant } => add_grant(
+                &zone(&key),
+                &Grant::new(&grant.rule, &zone(&grant.name), &grant.types)?,
+                connection,
+            )?,
+            GrantsCommand::Remove { key, grant } => remove_grant(
+                &zone(&key),
+                &Grant::new(&grant.rule, &zone(&grant.name), &grant.types)?,
+                connection,
+            )?,
+            GrantsCommand::List { key } => {
+                for grant in list_grants(&zone(&key), connection)? {
+                    println!("{}", grant);
+                }
+            }
+        },
+        ZoneCommand::Quota {
+            zone: name,
+            records,
+            rdata_bytes,
+            names,
+            reset,
+        } => {
+            let zone = zone(&name);
+            if reset || records.is_some() || rdata_bytes.is_some() || names.is_some() {
+                set_quota(&zone, records, rdata_bytes, names, connection)?;
+            }
+            println!("quota: {}", get_quota(&zone, connection)?);
+            println!("usage: {}", get_usage(&zone, connection)?);
+        }
+        ZoneCommand::Types {
+            zone: name,
+            allow,
+            deny,
+            reset,
+        } => {
+            let zone = zone(&name);
+            if reset || allow.is_some() || deny.is_some() {
+                set_type_policy(&zone, allow, deny, connection)?;
+            }
+            println!("{}", TypePolicy::get(&zone, connection)?);
+        }
+        ZoneCommand::Log { zone: name, limit } => {
+            for entry in get_log(&zone(&name), limit, connection)? {
+                println!("{}", entry);
+            }
+        }
+        ZoneCommand::Versions { zone: name } => {
+            for version in get_versions(&zone(&name), connection)? {
+                println!("{}", version);
+            }
+        }
+        ZoneCommand::Rollback { zone: name, serial } => {
+            let zone = zone(&name);
+            connection.transaction::<_, Box<dyn Error>, _>(|connection| {
+                Ok(rollback(&zone, serial, connection).map(|_| ())?)
+            })?;
+            println!("serial: {}", get_serial(&zone, connection)?);
+        }
+    }
+    Ok(())
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
     Config::initialize();
+
+    if let Some(Command::Zone { command }) = args.command {
+        return zone_command(command);
+    }
+
+    Secondary::get().start();
+
     let resolver_add = SocketAddr::from((Config::get().address, Config::get().port));
     let _ = tokio::join!(
         udp_listener_loop(resolver_add),
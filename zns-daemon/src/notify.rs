@@ -0,0 +1,316 @@
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Mutex, MutexGuard, OnceLock},
+    time::{Duration, Instant},
+};
+
+use ring::rand::{SecureRandom, SystemRandom};
+use tokio::net::UdpSocket;
+use zns::{
+    errors::ZNSError,
+    labelstring::LabelString,
+    parser::{FromBytes, ToBytes},
+    reader::Reader,
+    structs::{Class, Header, Message, Opcode, Question, RRClass, RRType, Type, RCODE, RR},
+};
+
+use crate::config::Config;
+
+static NOTIFIER: OnceLock<Notifier> = OnceLock::new();
+
+/// Sends NOTIFY messages to the secondaries in `ZNS_NOTIFY` when a zone changes
+/// https://datatracker.ietf.org/doc/html/rfc1996
+pub struct Notifier {
+    /// Notifications by lowercase zone name
+    zones: Mutex<HashMap<String, ZoneNotify>>,
+}
+
+#[derive(Default)]
+struct ZoneNotify {
+    /// SOA of the change which is not sent yet
+    pending: Option<RR>,
+    last_sent: Option<Instant>,
+}
+
+impl Notifier {
+    pub fn get() -> &'static Notifier {
+        NOTIFIER.get_or_init(|| Notifier {
+            zones: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, HashMap<String, ZoneNotify>>, ZNSError> {
+        self.zones.lock().map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })
+    }
+
+    /// Notifies the secondaries of a change of the zone in the background.
+    /// Changes within `ZNS_NOTIFY_INTERVAL` seconds of the previous NOTIFY of the zone are
+    /// sent together, with the latest SOA.
+    pub fn notify(&'static self, zone: &LabelString, soa: RR) {
+        if Config::get().notify.is_empty() {
+            return;
+        }
+
+        let interval = Duration::from_secs(Config::get().notify_interval);
+        match self.schedule(zone, soa, Instant::now(), interval) {
+            Ok(Some(delay)) => {
+                let zone = zone.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    self.send(&zone);
+                });
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    /// Stores the SOA of the change, returns the delay after which it should be sent if no
+    /// NOTIFY of the zone is pending yet
+    fn schedule(
+        &self,
+        zone: &LabelString,
+        soa: RR,
+        now: Instant,
+        interval: Duration,
+    ) -> Result<Option<Duration>, ZNSError> {
+        let mut zones = self.lock()?;
+        let state = zones.entry(zone.to_string().to_lowercase()).or_default();
+        if state.pending.replace(soa).is_some() {
+            return Ok(None);
+        }
+        Ok(Some(state.last_sent.map_or(Duration::ZERO, |last| {
+            (last + interval).saturating_duration_since(now)
+        })))
+    }
+
+    /// Takes the SOA of the pending change of the zone
+    fn take(&self, zone: &LabelString, now: Instant) -> Result<Option<RR>, ZNSError> {
+        let mut zones = self.lock()?;
+        Ok(zones
+            .get_mut(&zone.to_string().to_lowercase())
+            .and_then(|state| {
+                state.last_sent = Some(now);
+                state.pending.take()
+            }))
+    }
+
+    fn send(&self, zone: &LabelString) {
+        let soa = match self.take(zone, Instant::now()) {
+            Ok(Some(soa)) => soa,
+            Ok(None) => return,
+            Err(e) => return eprintln!("{}", e),
+        };
+        for address in &Config::get().notify {
+            let (address, zone, soa) = (*address, zone.clone(), soa.clone());
+            tokio::spawn(async move {
+                let config = Config::get();
+                let timeout = Duration::from_secs(config.notify_timeout);
+                if let Err(e) =
+                    send_notify(address, &zone, soa, config.notify_retries, timeout).await
+                {
+                    eprintln!("{}", e);
+                }
+            });
+        }
+    }
+}
+
+/// NOTIFY request with the SOA of the zone in the answer section
+/// https://datatracker.ietf.org/doc/html/rfc1996#section-3.7
+fn notify_message(id: u16, zone: &LabelString, soa: RR) -> Message {
+    Message {
+        header: Header {
+            id,
+            // Opcode NOTIFY and AA
+            flags: (Opcode::NOTIFY as u16) << 11 | 0b0000_0100_0000_0000,
+            qdcount: 1,
+            ancount: 1,
+            nscount: 0,
+            arcount: 0,
+        },
+        question: vec![Question {
+            qname: zone.clone(),
+            qtype: Type::Type(RRType::SOA),
+            qclass: Class::Class(RRClass::IN),
+        }],
+        answer: vec![soa],
+        authority: vec![],
+        additional: vec![],
+    }
+}
+
+/// Whether the message is the response of a secondary to the NOTIFY request
+/// https://datatracker.ietf.org/doc/html/rfc1996#section-4.7
+fn is_response(request: &Message, response: &Message) -> bool {
+    response.header.id == request.header.id
+        && response.header.flags & 0b1000_0000_0000_0000 != 0
+        && matches!(response.get_opcode(), Ok(Opcode::NOTIFY))
+        && response.question == request.question
+}
+
+pub(crate) fn random_id() -> Result<u16, ZNSError> {
+    let mut id = [0; 2];
+    SystemRandom::new()
+        .fill(&mut id)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })?;
+    Ok(u16::from_be_bytes(id))
+}
+
+/// Sends a NOTIFY of the zone to the secondary until it responds.
+/// The request is retried `retries` times, the timeout doubles after every attempt.
+async fn send_notify(
+    address: SocketAddr,
+    zone: &LabelString,
+    soa: RR,
+    retries: u32,
+    mut timeout: Duration,
+) -> Result<(), ZNSError> {
+    let error = |e: std::io::Error| ZNSError::Servfail {
+        message: format!("NOTIFY of {} to {} failed: {}", zone, address, e),
+    };
+    let local = match address {
+        SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+        SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+    };
+    let socket = UdpSocket::bind(local).await.map_err(error)?;
+    let request = notify_message(random_id()?, zone, soa);
+    let bytes = Message::to_bytes(request.clone());
+
+    for _ in 0..=retries {
+        socket.send_to(&bytes, address).await.map_err(error)?;
+        let response = async {
+            let mut buf = vec![0; 512];
+            loop {
+                let (length, from) = socket.recv_from(&mut buf).await?;
+                if let Ok(response) = Message::from_bytes(&mut Reader::new(&buf[..length])) {
+                    if from == address && is_response(&request, &response) {
+                        return Ok(response);
+                    }
+                }
+            }
+        };
+        match tokio::time::timeout(timeout, response).await {
+            Ok(response) => {
+                let rcode = response.map_err(error)?.header.flags & 0b1111;
+                return match rcode == RCODE::NOERROR as u16 {
+                    true => Ok(()),
+                    false => Err(ZNSError::Servfail {
+                        message: format!(
+                            "NOTIFY of {} to {} failed with RCODE {}",
+                            zone, address, rcode
+                        ),
+                    }),
+                };
+            }
+            Err(_) => timeout *= 2,
+        }
+    }
+
+    Err(ZNSError::Servfail {
+        message: format!("NOTIFY of {} to {} timed out", zone, address),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use zns::test_utils::get_rr;
+
+    use super::*;
+
+    #[test]
+    fn test_schedule() {
+        let notifier = Notifier {
+            zones: Mutex::new(HashMap::new()),
+        };
+        let zone = LabelString::from("bob.user.zeus.gent");
+        let interval = Duration::from_secs(5);
+        let now = Instant::now();
+        let soa = |serial: u8| RR {
+            rdata: zns::structs::RData::Vec(vec![serial]),
+            ..get_rr(Some(zone.clone()))
+        };
+
+        assert_eq!(
+            notifier.schedule(&zone, soa(1), now, interval).unwrap(),
+            Some(Duration::ZERO)
+        );
+        // Changes are combined while a NOTIFY is pending
+        assert_eq!(
+            notifier.schedule(&zone, soa(2), now, interval).unwrap(),
+            None
+        );
+        assert_eq!(notifier.take(&zone, now).unwrap(), Some(soa(2)));
+        assert_eq!(notifier.take(&zone, now).unwrap(), None);
+
+        // The next NOTIFY waits for the interval
+        let later = now + Duration::from_secs(2);
+        assert_eq!(
+            notifier.schedule(&zone, soa(3), later, interval).unwrap(),
+            Some(Duration::from_secs(3))
+        );
+
+        let other = LabelString::from("alice.user.zeus.gent");
+        assert_eq!(
+            notifier.schedule(&other, soa(4), later, interval).unwrap(),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_notify() {
+        let zone = LabelString::from("bob.user.zeus.gent");
+        let secondary = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = secondary.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let mut buf = vec![0; 512];
+            // The first request is not answered, which has to be retried
+            secondary.recv_from(&mut buf).await.unwrap();
+            let (length, from) = secondary.recv_from(&mut buf).await.unwrap();
+            let request = Message::from_bytes(&mut Reader::new(&buf[..length])).unwrap();
+            assert!(matches!(request.get_opcode(), Ok(Opcode::NOTIFY)));
+            assert_eq!(request.answer.len(), 1);
+
+            // Unrelated messages are ignored
+            let mut other = request.clone();
+            other.header.id = request.header.id.wrapping_add(1);
+            other.set_response(RCODE::NOERROR);
+            let mut response = request.clone();
+            response.set_response(RCODE::NOERROR);
+            for message in [other, response] {
+                secondary
+                    .send_to(&Message::to_bytes(message), from)
+                    .await
+                    .unwrap();
+            }
+
+            // Refused
+            let (length, from) = secondary.recv_from(&mut buf).await.unwrap();
+            let mut response = Message::from_bytes(&mut Reader::new(&buf[..length])).unwrap();
+            response.set_response(RCODE::REFUSED);
+            secondary
+                .send_to(&Message::to_bytes(response), from)
+                .await
+                .unwrap();
+        });
+
+        let soa = get_rr(Some(zone.clone()));
+        let timeout = Duration::from_millis(100);
+        assert!(send_notify(address, &zone, soa.clone(), 2, timeout)
+            .await
+            .is_ok());
+        assert!(send_notify(address, &zone, soa.clone(), 2, timeout)
+            .await
+            .is_err());
+        server.await.unwrap();
+
+        // No response at all
+        assert!(send_notify(address, &zone, soa, 1, timeout).await.is_err());
+    }
+}
@@ -0,0 +1,201 @@
+use std::fmt::Display;
+
+use diesel::PgConnection;
+use zns::{errors::ZNSError, labelstring::LabelString};
+
+use crate::{
+    auth::owners::user_zone,
+    config::Config,
+    db::models::{get_zone_quota, get_zone_usage, set_zone_quota, ZoneQuotaRecord},
+};
+
+/// Limits of a zone below the authoritative zone, including all zones below it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quota {
+    pub records: u32,
+    pub rdata_bytes: u32,
+    pub names: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Usage {
+    pub records: u64,
+    pub rdata_bytes: u64,
+    pub names: u64,
+}
+
+impl Display for Quota {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} records, {} bytes of RDATA, {} names",
+            self.records, self.rdata_bytes, self.names
+        )
+    }
+}
+
+impl Display for Usage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} records, {} bytes of RDATA, {} names",
+            self.records, self.rdata_bytes, self.names
+        )
+    }
+}
+
+/// Zone below the authoritative zone to which the quota applies
+fn quota_zone(zone: &LabelString) -> Result<LabelString, ZNSError> {
+    user_zone(zone).ok_or(ZNSError::Refused {
+        message: format!(
+            "Zone {} is not below {}",
+            zone,
+            Config::get().authoritative_zone
+        ),
+    })
+}
+
+/// Limits of the zone: the override of the zone in the database or the configured defaults
+pub fn get_quota(zone: &LabelString, connection: &mut PgConnection) -> Result<Quota, ZNSError> {
+    let record = get_zone_quota(&quota_zone(zone)?, connection)?;
+    let limit = |value: Option<i32>, default: u32| value.map_or(default, |v| v.max(0) as u32);
+
+    Ok(Quota {
+        records: limit(
+            record.as_ref().and_then(|r| r.max_records),
+            Config::get().max_records,
+        ),
+        rdata_bytes: limit(
+            record.as_ref().and_then(|r| r.max_rdata_bytes),
+            Config::get().max_rdata_bytes,
+        ),
+        names: limit(
+            record.as_ref().and_then(|r| r.max_names),
+            Config::get().max_names,
+        ),
+    })
+}
+
+pub fn get_usage(zone: &LabelString, connection: &mut PgConnection) -> Result<Usage, ZNSError> {
+    let (records, rdata_bytes, names) = get_zone_usage(&quota_zone(zone)?, connection)?;
+    Ok(Usage {
+        records: records as u64,
+        rdata_bytes: rdata_bytes as u64,
+        names: names as u64,
+    })
+}
+
+/// Refuses if the records of the zone exceed one of its limits
+pub fn check_quota(zone: &LabelString, connection: &mut PgConnection) -> Result<(), ZNSError> {
+    let quota = get_quota(zone, connection)?;
+    let usage = get_usage(zone, connection)?;
+
+    let exceeded = [
+        (usage.records, quota.records, "records"),
+        (usage.rdata_bytes, quota.rdata_bytes, "bytes of RDATA"),
+        (usage.names, quota.names, "names"),
+    ]
+    .into_iter()
+    .find(|(used, limit, _)| used > &(*limit as u64));
+
+    match exceeded {
+        Some((used, limit, unit)) => Err(ZNSError::Refused {
+            message: format!(
+                "Quota of {} exceeded: {} {} (maximum {})",
+                quota_zone(zone)?,
+                used,
+                unit,
+                limit
+            ),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Overrides the limits of the zone, `None` uses the configured default
+pub fn set_quota(
+    zone: &LabelString,
+    records: Option<u32>,
+    rdata_bytes: Option<u32>,
+    names: Option<u32>,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    let zone = quota_zone(zone)?;
+    let value = |v: Option<u32>| v.map(|v| v.min(i32::MAX as u32) as i32);
+
+    set_zone_quota(
+        &ZoneQuotaRecord {
+            zone: zone.to_string(),
+            max_records: value(records),
+            max_rdata_bytes: value(rdata_bytes),
+            max_names: value(names),
+        },
+        connection,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use zns::{
+        structs::{RData, RR},
+        test_utils::get_rr,
+    };
+
+    use crate::db::{lib::tests::get_test_connection, models::insert_into_database};
+
+    use super::*;
+
+    #[test]
+    fn test_quota() {
+        let mut connection = get_test_connection();
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let home = zone.prepend("home".to_string());
+
+        assert_eq!(
+            get_quota(&home, &mut connection).unwrap(),
+            Quota {
+                records: Config::get().max_records,
+                rdata_bytes: Config::get().max_rdata_bytes,
+                names: Config::get().max_names,
+            }
+        );
+        assert!(get_quota(&Config::get().authoritative_zone, &mut connection).is_err());
+
+        for (name, rdata) in [
+            (&zone, vec![1, 2, 3, 4]),
+            (&home, vec![1, 2, 3, 4]),
+            (&home, vec![5, 6, 7, 8]),
+        ] {
+            let rr = RR {
+                name: name.clone(),
+                rdata: RData::Vec(rdata),
+                ..get_rr(None)
+            };
+            insert_into_database(&rr, &mut connection).unwrap();
+        }
+
+        assert_eq!(
+            get_usage(&home, &mut connection).unwrap(),
+            Usage {
+                records: 3,
+                rdata_bytes: 12,
+                names: 2
+            }
+        );
+        assert!(check_quota(&home, &mut connection).is_ok());
+
+        // Wildcards of LIKE in the zone are matched literally
+        let other = Config::get().authoritative_zone.prepend("bo_".to_string());
+        assert_eq!(get_usage(&other, &mut connection).unwrap().records, 0);
+
+        set_quota(&home, Some(3), None, Some(1), &mut connection).unwrap();
+        assert_eq!(get_quota(&zone, &mut connection).unwrap().records, 3);
+        assert!(matches!(
+            check_quota(&zone, &mut connection),
+            Err(ZNSError::Refused { .. })
+        ));
+
+        set_quota(&zone, None, None, None, &mut connection).unwrap();
+        assert!(check_quota(&zone, &mut connection).is_ok());
+    }
+}
@@ -1,18 +1,29 @@
 use std::error::Error;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpSocket, UdpSocket};
+use tokio::net::{TcpSocket, TcpStream, UdpSocket};
+use tokio::sync::mpsc;
+use zns::edns::{has_opt, set_extended_error, ExtendedError, UDP_PAYLOAD_SIZE};
 use zns::errors::ZNSError;
 use zns::parser::{FromBytes, ToBytes};
 use zns::reader::Reader;
-use zns::structs::{Header, Message, RCODE};
+use zns::structs::{Header, Message, Opcode, RRType, Type, RCODE, RR};
+use zns::tsig::TsigError;
 
+use crate::auth::RequestTsig;
+use crate::config::Config;
 use crate::db::lib::get_connection;
-use crate::handlers::{Handler, ResponseHandler};
+use crate::handlers::{
+    is_full_transfer, split_transfer, stream_zone_transfer, Handler, ResponseHandler,
+};
 
 const MAX_DATAGRAM_SIZE: usize = 512;
+/// Space left for the SIG(0) or TSIG record of a response
+const SIGNATURE_SIZE: usize = 256;
+/// Number of messages of a zone transfer which are read ahead of the client
+const TRANSFER_BUFFER: usize = 4;
 
 fn handle_parse_error(bytes: &[u8], err: ZNSError) -> Message {
     eprintln!("{}", err);
@@ -42,22 +53,223 @@ fn handle_parse_error(bytes: &[u8], err: ZNSError) -> Message {
     message
 }
 
-async fn get_response(bytes: &[u8]) -> Vec<u8> {
+// Responses to UPDATE, AXFR and IXFR are signed with the server key
+fn needs_signature(message: &Message) -> bool {
+    matches!(message.get_opcode(), Ok(Opcode::UPDATE))
+        || message.question.first().is_some_and(|question| {
+            [Type::Type(RRType::AXFR), Type::Type(RRType::IXFR)].contains(&question.qtype)
+        })
+}
+
+fn is_axfr(message: &Message) -> bool {
+    message
+        .question
+        .first()
+        .is_some_and(|question| question.qtype == Type::Type(RRType::AXFR))
+}
+
+fn is_ixfr(message: &Message) -> bool {
+    message
+        .question
+        .first()
+        .is_some_and(|question| question.qtype == Type::Type(RRType::IXFR))
+}
+
+/// Message of a zone transfer with a batch of the records, only the first message contains
+/// the question
+fn transfer_message(response: &Message, batch: Vec<RR>, first: bool) -> Message {
+    let mut message = response.clone();
+    if !first {
+        message.question = vec![];
+        message.header.qdcount = 0;
+    }
+    message.answer = vec![];
+    message.header.ancount = 0;
+    message.extend_answer(batch);
+    message
+}
+
+/// Response to a request
+enum Response {
+    Message(Vec<u8>),
+    /// Incremental zone transfer over TCP which is split in multiple messages
+    Messages(Vec<Vec<u8>>),
+    /// Authorized AXFR or IXFR of the whole zone, the records are streamed after the response
+    Transfer {
+        response: Box<Message>,
+        tsig: Option<Box<RequestTsig>>,
+    },
+}
+
+/// Removes the signatures of the request and signs the response, with TSIG if the request
+/// was signed with TSIG or with the server key if needed
+fn sign_response(
+    request: &[u8],
+    mut response: Message,
+    tsig: &mut Option<RequestTsig>,
+    sign: bool,
+) -> Vec<u8> {
+    // Signatures of the request are not part of the response
+    response
+        .additional
+        .retain(|rr| ![Type::Type(RRType::SIG), Type::Type(RRType::TSIG)].contains(&rr._type));
+    response.header.arcount = response.additional.len() as u16;
+
+    let mut response = Message::to_bytes(response);
+    let signed = match (tsig, Config::get().server_key.as_ref()) {
+        (Some(tsig), _) => tsig.sign_response(&mut response),
+        (None, Some(key)) if sign => {
+            key.sign_response(request, &mut response, Config::get().sig_max_skew as u32)
+        }
+        _ => Ok(()),
+    };
+    if let Err(e) = signed {
+        eprintln!("{}", e);
+    }
+    response
+}
+
+async fn get_response(bytes: &[u8], client: IpAddr, tcp: bool) -> Response {
     let mut reader = Reader::new(bytes);
-    Message::to_bytes(match Message::from_bytes(&mut reader) {
-        Ok(mut message) => match Handler::handle(&message, bytes, &mut get_connection()).await {
-            Ok(mut response) => {
-                response.set_response(RCODE::NOERROR);
-                response
-            }
-            Err(e) => {
-                eprintln!("{}", e);
-                message.set_response(e.rcode());
-                message
-            }
-        },
-        Err(err) => handle_parse_error(bytes, err),
-    })
+    match Message::from_bytes(&mut reader) {
+        Ok(mut message) => {
+            let mut connection = get_connection();
+            let sign = needs_signature(&message);
+            let transfer = is_axfr(&message);
+
+            let (mut tsig, result) =
+                match RequestTsig::from_request(&message, bytes, &mut connection) {
+                    Ok(Some(tsig)) if tsig.error() != TsigError::NOERROR => {
+                        let error = ZNSError::NotAuth {
+                            message: format!("TSIG error: {:?}", tsig.error()),
+                        };
+                        (Some(tsig), Err(error))
+                    }
+                    // A zone transfer does not fit in a datagram
+                    // https://datatracker.ietf.org/doc/html/rfc5936#section-4.2
+                    Ok(tsig) if transfer && !tcp => (
+                        tsig,
+                        Err(ZNSError::Refused {
+                            message: "AXFR is only supported over TCP".to_string(),
+                        }),
+                    ),
+                    Ok(tsig) => (
+                        tsig,
+                        Handler::handle(&message, bytes, client, &mut connection).await,
+                    ),
+                    Err(e) => (None, Err(e)),
+                };
+
+            let response = match result {
+                Ok(mut response) => {
+                    response.set_response(RCODE::NOERROR);
+                    // An IXFR which falls back to a full zone transfer is streamed over TCP,
+                    // over UDP only the current SOA is sent
+                    if transfer || (tcp && is_full_transfer(&message, &response)) {
+                        response.answer = vec![];
+                        response.header.ancount = 0;
+                        return Response::Transfer {
+                            response: Box::new(response),
+                            tsig: tsig.map(Box::new),
+                        };
+                    }
+                    if is_ixfr(&message) && tcp {
+                        let batches = split_transfer(std::mem::take(&mut response.answer));
+                        return Response::Messages(
+                            batches
+                                .into_iter()
+                                .enumerate()
+                                .map(|(i, batch)| {
+                                    let message = transfer_message(&response, batch, i == 0);
+                                    sign_response(bytes, message, &mut tsig, sign)
+                                })
+                                .collect(),
+                        );
+                    }
+                    // Changes which do not fit in a datagram are replaced by the current SOA,
+                    // the client then retries over TCP
+                    // https://datatracker.ietf.org/doc/html/rfc1995#section-2
+                    let limit = match has_opt(&message) {
+                        true => UDP_PAYLOAD_SIZE as usize,
+                        false => MAX_DATAGRAM_SIZE,
+                    };
+                    if is_ixfr(&message)
+                        && Message::to_bytes(response.clone()).len() + SIGNATURE_SIZE > limit
+                    {
+                        response.answer.truncate(1);
+                        response.header.ancount = response.answer.len() as u16;
+                    }
+                    response
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    message.set_response(e.rcode());
+                    // Explain why the request is refused to clients which support EDNS
+                    if let ZNSError::Refused { message: text } = &e {
+                        set_extended_error(&mut message, ExtendedError::Other, text);
+                    }
+                    message
+                }
+            };
+
+            Response::Message(sign_response(bytes, response, &mut tsig, sign))
+        }
+        Err(err) => Response::Message(Message::to_bytes(handle_parse_error(bytes, err))),
+    }
+}
+
+async fn write_message(stream: &mut TcpStream, message: &[u8]) -> std::io::Result<()> {
+    stream.write_u16(message.len() as u16).await?;
+    stream.write_all(message).await
+}
+
+/// Sends the records of the zone as a sequence of messages, each with its own signature.
+/// The records are read while the previous messages are sent.
+/// https://datatracker.ietf.org/doc/html/rfc5936#section-2.2
+async fn stream_transfer(
+    stream: &mut TcpStream,
+    request: &[u8],
+    response: Message,
+    mut tsig: Option<RequestTsig>,
+) {
+    let question = response.question[0].clone();
+    let (sender, mut receiver) = mpsc::channel(TRANSFER_BUFFER);
+    let producer = tokio::task::spawn_blocking(move || {
+        let mut connection = get_connection();
+        stream_zone_transfer(
+            &question.qname,
+            &question.qclass,
+            &mut connection,
+            |batch| sender.blocking_send(batch).is_ok(),
+        )
+    });
+
+    let mut sent = false;
+    while let Some(batch) = receiver.recv().await {
+        let message = transfer_message(&response, batch, !sent);
+        let message = sign_response(request, message, &mut tsig, true);
+        if write_message(stream, &message).await.is_err() {
+            break;
+        }
+        sent = true;
+    }
+    // Stops the producer if the client is gone
+    drop(receiver);
+
+    let result = producer.await.unwrap_or_else(|e| {
+        Err(ZNSError::Servfail {
+            message: e.to_string(),
+        })
+    });
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        if !sent {
+            let mut message = response;
+            message.set_response(e.rcode());
+            let message = sign_response(request, message, &mut tsig, true);
+            let _ = write_message(stream, &message).await;
+        }
+    }
 }
 
 pub async fn udp_listener_loop(addr: SocketAddr) -> Result<(), Box<dyn Error>> {
@@ -67,9 +279,11 @@ pub async fn udp_listener_loop(addr: SocketAddr) -> Result<(), Box<dyn Error>> {
         let (len, addr) = socket_shared.recv_from(&mut data).await?;
         let socket = socket_shared.clone();
         tokio::spawn(async move {
-            let response = get_response(&data[..len]).await;
             // TODO: if length is larger then 512 bytes, message should be truncated
-            let _ = socket.send_to(&response, addr).await;
+            if let Response::Message(response) = get_response(&data[..len], addr.ip(), false).await
+            {
+                let _ = socket.send_to(&response, addr).await;
+            }
         });
     }
 }
@@ -79,7 +293,7 @@ pub async fn tcp_listener_loop(addr: SocketAddr) -> Result<(), Box<dyn Error>> {
     socket.bind(addr)?;
     let listener = socket.listen(1024)?;
     loop {
-        let (mut stream, _) = listener.accept().await?;
+        let (mut stream, peer) = listener.accept().await?;
         tokio::spawn(async move {
             if stream.readable().await.is_ok() {
                 if let Ok(length) = stream.read_u16().await {
@@ -88,10 +302,26 @@ pub async fn tcp_listener_loop(addr: SocketAddr) -> Result<(), Box<dyn Error>> {
                         .try_read_buf(&mut buf)
                         .is_ok_and(|v| v == length as usize)
                     {
-                        let response = get_response(&buf).await;
-                        if stream.writable().await.is_ok() {
-                            let _ = stream.write_u16(response.len() as u16).await;
-                            let _ = stream.try_write(&response);
+                        match get_response(&buf, peer.ip(), true).await {
+                            Response::Message(response) => {
+                                let _ = write_message(&mut stream, &response).await;
+                            }
+                            Response::Messages(responses) => {
+                                for response in responses {
+                                    if write_message(&mut stream, &response).await.is_err() {
+                                        break;
+                                    }
+                                }
+                            }
+                            Response::Transfer { response, tsig } => {
+                                stream_transfer(
+                                    &mut stream,
+                                    &buf,
+                                    *response,
+                                    tsig.map(|tsig| *tsig),
+                                )
+                                .await;
+                            }
                         }
                     }
                 }
@@ -102,15 +332,12 @@ pub async fn tcp_listener_loop(addr: SocketAddr) -> Result<(), Box<dyn Error>> {
 
 #[cfg(test)]
 mod tests {
-    use zns::structs::{Class, Question, RRClass, RRType, Type};
-
-    use crate::config::Config;
+    use zns::structs::{Class, Question, RRClass};
 
     use super::*;
 
-    #[tokio::test]
-    async fn test_get_response() {
-        let message = Message {
+    fn get_request(qtype: RRType) -> Message {
+        Message {
             header: Header {
                 id: 1,
                 flags: 288,
@@ -121,20 +348,39 @@ mod tests {
             },
             question: vec![Question {
                 qname: Config::get().authoritative_zone.clone(),
-                qtype: Type::Type(RRType::A),
+                qtype: Type::Type(qtype),
                 qclass: Class::Class(RRClass::IN),
             }],
             answer: vec![],
             authority: vec![],
             additional: vec![],
-        };
+        }
+    }
 
-        let response = get_response(&Message::to_bytes(message)).await;
-        let mut reader = Reader::new(&response);
+    async fn get_rcode(message: Message, tcp: bool) -> Result<RCODE, u16> {
+        let client = IpAddr::from([127, 0, 0, 1]);
+        match get_response(&Message::to_bytes(message), client, tcp).await {
+            Response::Message(response) => Message::from_bytes(&mut Reader::new(&response))
+                .unwrap()
+                .get_rcode(),
+            Response::Transfer { .. } | Response::Messages(_) => panic!("Unexpected zone transfer"),
+        }
+    }
 
+    #[tokio::test]
+    async fn test_get_response() {
         assert_eq!(
-            Message::from_bytes(&mut reader).unwrap().get_rcode(),
+            get_rcode(get_request(RRType::A), false).await,
             Ok(RCODE::NXDOMAIN)
         );
+        assert_eq!(
+            get_rcode(get_request(RRType::AXFR), false).await,
+            Ok(RCODE::REFUSED)
+        );
+        // Refused over TCP as well, as the request is not signed
+        assert_eq!(
+            get_rcode(get_request(RRType::AXFR), true).await,
+            Ok(RCODE::REFUSED)
+        );
     }
 }
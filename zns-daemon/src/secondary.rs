@@ -0,0 +1,658 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use diesel::{Connection, PgConnection};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    sync::Notify,
+};
+use zns::{
+    errors::ZNSError,
+    labelstring::LabelString,
+    parser::{FromBytes, ToBytes},
+    reader::Reader,
+    structs::{Class, Header, Message, Question, RRClass, RRType, SoaRData, Type, RCODE, RR},
+    tsig::{sign_request, TsigKey, TsigStream},
+};
+
+use crate::{
+    auth::owners::user_zone,
+    config::Config,
+    db::{
+        lib::get_connection,
+        models::{delete_from_database, get_tsig_key, insert_into_database, replace_zone_records},
+    },
+    handlers::get_soa,
+    history::{get_latest_serial, store_primary_version, Change},
+    notify::random_id,
+};
+
+static SECONDARY: OnceLock<Secondary> = OnceLock::new();
+
+/// Time to wait for the connection to and each message from the primary
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Retry interval in seconds until the SOA of the primary is known
+const INITIAL_RETRY: u64 = 60;
+
+/// Zones in `ZNS_SECONDARY_ZONES`, which are replicated from the primary in `ZNS_PRIMARY`
+/// with zone transfers and served read-only
+pub struct Secondary {
+    /// Zones by lowercase name
+    zones: HashMap<String, SecondaryZone>,
+}
+
+struct SecondaryZone {
+    zone: LabelString,
+    /// Wakes up the zone to refresh it immediately, e.g. after a NOTIFY
+    refresh: Notify,
+    /// The zone is no longer served after this time without a successful refresh
+    expires: Mutex<Option<Instant>>,
+}
+
+/// Contents of a zone transfer
+#[derive(Debug, Clone, PartialEq)]
+enum Transfer {
+    /// The zone is up to date
+    Current(SoaRData),
+    /// All records of the zone
+    Full { soa: SoaRData, records: Vec<RR> },
+    /// Changes since the version of the secondary, oldest first
+    Incremental { soa: SoaRData, changes: Vec<Change> },
+}
+
+impl Secondary {
+    pub fn get() -> &'static Secondary {
+        SECONDARY.get_or_init(|| Secondary {
+            zones: match Config::get().primary {
+                Some(_) => Config::get()
+                    .secondary_zones
+                    .iter()
+                    .map(|zone| {
+                        (
+                            zone.to_string().to_lowercase(),
+                            SecondaryZone {
+                                zone: zone.clone(),
+                                refresh: Notify::new(),
+                                expires: Mutex::new(None),
+                            },
+                        )
+                    })
+                    .collect(),
+                None => HashMap::new(),
+            },
+        })
+    }
+
+    /// Replicated zone which contains the name
+    fn zone(&self, name: &LabelString) -> Option<&SecondaryZone> {
+        user_zone(name).and_then(|zone| self.zones.get(&zone.to_string().to_lowercase()))
+    }
+
+    /// Whether the name is in a zone which is replicated from the primary
+    pub fn is_replicated(&self, name: &LabelString) -> bool {
+        self.zone(name).is_some()
+    }
+
+    /// Whether the name is in a replicated zone which could not be refreshed before it expired
+    pub fn is_expired(&self, name: &LabelString) -> bool {
+        self.zone(name).is_some_and(|zone| {
+            zone.expires
+                .lock()
+                .is_ok_and(|expires| expires.is_some_and(|expires| expires < Instant::now()))
+        })
+    }
+
+    /// Refreshes the zone as soon as possible, returns false if it is not replicated
+    pub fn refresh(&self, zone: &LabelString) -> bool {
+        match self.zones.get(&zone.to_string().to_lowercase()) {
+            Some(zone) => {
+                zone.refresh.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Keeps all zones up to date in the background
+    pub fn start(&'static self) {
+        for zone in self.zones.values() {
+            tokio::spawn(zone.replicate());
+        }
+    }
+}
+
+impl SecondaryZone {
+    /// Refreshes the zone after the refresh interval of its SOA, or the retry interval after
+    /// a failed refresh
+    /// https://datatracker.ietf.org/doc/html/rfc1034#section-4.3.5
+    async fn replicate(&self) {
+        let mut retry = INITIAL_RETRY;
+        loop {
+            let wait = match refresh_zone(&self.zone).await {
+                Ok(soa) => {
+                    if let Ok(mut expires) = self.expires.lock() {
+                        *expires =
+                            Some(Instant::now() + Duration::from_secs(soa.expire.max(0) as u64));
+                    }
+                    retry = soa.retry.max(1) as u64;
+                    soa.refresh.max(1) as u64
+                }
+                Err(e) => {
+                    eprintln!("Refresh of {} failed: {}", self.zone, e);
+                    retry
+                }
+            };
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(wait)) => {}
+                _ = self.refresh.notified() => {}
+            }
+        }
+    }
+}
+
+fn now() -> Result<u64, ZNSError> {
+    Ok(SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| ZNSError::Servfail {
+            message: e.to_string(),
+        })?
+        .as_secs())
+}
+
+fn get_soa_rdata(rr: &RR) -> Option<SoaRData> {
+    let rdata: Vec<u8> = rr.rdata.clone().into();
+    (rr._type == Type::Type(RRType::SOA))
+        .then(|| SoaRData::from_bytes(&mut Reader::new(&rdata)).ok())
+        .flatten()
+}
+
+/// Transfers the changes of the zone from the primary and stores them, returns the SOA of the
+/// primary
+async fn refresh_zone(zone: &LabelString) -> Result<SoaRData, ZNSError> {
+    let primary = Config::get().primary.ok_or(ZNSError::Servfail {
+        message: String::from("No primary configured"),
+    })?;
+
+    let mut connection = get_connection();
+    let serial = get_latest_serial(zone, &mut connection)?;
+    let name = Config::get()
+        .primary_tsig
+        .as_ref()
+        .ok_or(ZNSError::Servfail {
+            message: String::from("No TSIG key for the primary configured"),
+        })?;
+    let key = get_tsig_key(name, &mut connection)?
+        .ok_or(ZNSError::Servfail {
+            message: format!("TSIG key {} does not exist", name),
+        })?
+        .0;
+
+    let transfer = request_transfer(primary, zone, serial, key).await?;
+    apply_transfer(zone, transfer, &mut connection)
+}
+
+/// Requests an IXFR of the changes since the serial of the secondary, or an AXFR if the zone
+/// has no versions yet. The request and every message of the response are signed with the
+/// TSIG key.
+async fn request_transfer(
+    primary: SocketAddr,
+    zone: &LabelString,
+    serial: Option<u32>,
+    key: TsigKey,
+) -> Result<Transfer, ZNSError> {
+    let request = Message {
+        header: Header {
+            id: random_id()?,
+            flags: 0,
+            qdcount: 1,
+            ancount: 0,
+            nscount: serial.is_some() as u16,
+            arcount: 0,
+        },
+        question: vec![Question {
+            qname: zone.clone(),
+            qtype: Type::Type(match serial {
+                Some(_) => RRType::IXFR,
+                None => RRType::AXFR,
+            }),
+            qclass: Class::Class(RRClass::IN),
+        }],
+        answer: vec![],
+        // The SOA of the version of the secondary
+        // https://datatracker.ietf.org/doc/html/rfc1995#section-3
+        authority: serial
+            .map(|serial| get_soa(zone, serial))
+            .transpose()?
+            .into_iter()
+            .collect(),
+        additional: vec![],
+    };
+    let id = request.header.id;
+
+    let mut bytes = Message::to_bytes(request);
+    let mac = sign_request(&mut bytes, &key, now()?)?;
+    let mut tsig = TsigStream::new(key, &mac);
+
+    let error = |e: std::io::Error| ZNSError::Servfail {
+        message: format!("Transfer of {} from {} failed: {}", zone, primary, e),
+    };
+    let timeout = |_| ZNSError::Servfail {
+        message: format!("Transfer of {} from {} timed out", zone, primary),
+    };
+
+    let mut stream = tokio::time::timeout(TRANSFER_TIMEOUT, TcpStream::connect(primary))
+        .await
+        .map_err(timeout)?
+        .map_err(error)?;
+    let bytes = [&u16::to_be_bytes(bytes.len() as u16), &bytes[..]].concat();
+    stream.write_all(&bytes).await.map_err(error)?;
+
+    let mut rrs = vec![];
+    loop {
+        let raw = tokio::time::timeout(TRANSFER_TIMEOUT, async {
+            let mut raw = vec![0; stream.read_u16().await? as usize];
+            stream.read_exact(&mut raw).await.map(|_| raw)
+        })
+        .await
+        .map_err(timeout)?
+        .map_err(error)?;
+
+        tsig.verify(&raw, now()?).map_err(|e| ZNSError::NotAuth {
+            message: format!("TSIG error in transfer of {}: {:?}", zone, e),
+        })?;
+
+        let message = Message::from_bytes(&mut Reader::new(&raw))?;
+        let rcode = message.header.flags & 0b1111;
+        if message.header.id != id {
+            return Err(ZNSError::Formerr {
+                message: format!("Transfer of {} has an unexpected ID", zone),
+            });
+        } else if rcode != RCODE::NOERROR as u16 {
+            return Err(ZNSError::Servfail {
+                message: format!(
+                    "Transfer of {} from {} failed with RCODE {}",
+                    zone, primary, rcode
+                ),
+            });
+        }
+
+        rrs.extend(message.answer);
+        if let Some(transfer) = parse_transfer(&rrs, serial)? {
+            return Ok(transfer);
+        }
+    }
+}
+
+/// Parses the records of an AXFR or IXFR response, returns `None` if more messages are needed
+/// https://datatracker.ietf.org/doc/html/rfc1995#section-4
+fn parse_transfer(rrs: &[RR], serial: Option<u32>) -> Result<Option<Transfer>, ZNSError> {
+    let Some(first) = rrs.first() else {
+        return Ok(None);
+    };
+    let soa = get_soa_rdata(first).ok_or(ZNSError::Formerr {
+        message: String::from("Zone transfer does not start with a SOA"),
+    })?;
+    let is_final = |rr: &RR| get_soa_rdata(rr).is_some_and(|rdata| rdata.serial == soa.serial);
+
+    // Serial number arithmetic: the secondary has the same or a newer version
+    // https://datatracker.ietf.org/doc/html/rfc1982#section-3.2
+    if rrs.len() == 1 {
+        return Ok(serial
+            .filter(|serial| serial.wrapping_sub(soa.serial) as i32 >= 0)
+            .map(|_| Transfer::Current(soa)));
+    }
+
+    // An IXFR response continues with the SOA of the version of the secondary, otherwise the
+    // primary fell back to an AXFR, which might be of a zone without records
+    let is_incremental = serial
+        .is_some_and(|serial| get_soa_rdata(&rrs[1]).is_some_and(|rdata| rdata.serial == serial));
+    if !is_incremental {
+        return Ok(is_final(&rrs[rrs.len() - 1]).then(|| Transfer::Full {
+            records: rrs[1..rrs.len() - 1]
+                .iter()
+                .filter(|rr| rr._type != Type::Type(RRType::SOA))
+                .cloned()
+                .collect(),
+            soa,
+        }));
+    }
+
+    // Sequences of the SOA of the old version, the deleted records,
+    // the SOA of the new version and the added records
+    let mut changes = vec![];
+    let mut rest = &rrs[1..];
+    loop {
+        if is_final(&rest[0]) {
+            return match rest.len() {
+                1 => Ok(Some(Transfer::Incremental { soa, changes })),
+                _ => Err(ZNSError::Formerr {
+                    message: String::from("Records after the end of the zone transfer"),
+                }),
+            };
+        }
+
+        let mut versions = [0; 2];
+        let mut records: [Vec<RR>; 2] = [vec![], vec![]];
+        for (version, records) in versions.iter_mut().zip(records.iter_mut()) {
+            let Some((first, others)) = rest.split_first() else {
+                return Ok(None);
+            };
+            *version = get_soa_rdata(first)
+                .ok_or(ZNSError::Formerr {
+                    message: String::from("Expected a SOA in the zone transfer"),
+                })?
+                .serial;
+            let end = others
+                .iter()
+                .position(|rr| rr._type == Type::Type(RRType::SOA))
+                .unwrap_or(others.len());
+            records.extend_from_slice(&others[..end]);
+            rest = &others[end..];
+        }
+        if rest.is_empty() {
+            return Ok(None);
+        }
+
+        let [deleted, added] = records;
+        changes.push(Change {
+            from: versions[0],
+            to: versions[1],
+            deleted,
+            added,
+        });
+    }
+}
+
+/// Stores the records of the transfer as the records of the zone, returns the SOA of the primary
+fn apply_transfer(
+    zone: &LabelString,
+    transfer: Transfer,
+    connection: &mut PgConnection,
+) -> Result<SoaRData, ZNSError> {
+    let in_zone = |rr: &RR| rr.name.is_subdomain_of(zone);
+    let class = Class::Class(RRClass::IN);
+
+    let mut result = Ok(());
+    let transaction = connection.transaction(|connection| {
+        result = match &transfer {
+            Transfer::Current(_) => Ok(()),
+            Transfer::Full { soa, records } => replace_zone_records(zone, &[], connection)
+                .and_then(|_| {
+                    records
+                        .iter()
+                        .filter(|rr| in_zone(rr) && rr.class == class)
+                        .try_for_each(|rr| insert_into_database(rr, connection))
+                })
+                .and_then(|_| store_primary_version(zone, soa.serial, connection)),
+            Transfer::Incremental { changes, .. } => changes.iter().try_for_each(|change| {
+                for rr in change.deleted.iter().filter(|rr| in_zone(rr)) {
+                    delete_from_database(
+                        &rr.name,
+                        Some(rr._type.clone()),
+                        rr.class.clone(),
+                        Some(rr.rdata.clone().into()),
+                        connection,
+                    );
+                }
+                change
+                    .added
+                    .iter()
+                    .filter(|rr| in_zone(rr) && rr.class == class)
+                    .try_for_each(|rr| insert_into_database(rr, connection))?;
+                store_primary_version(zone, change.to, connection)
+            }),
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(_) => Err(diesel::result::Error::RollbackTransaction),
+        }
+    });
+    result?;
+    transaction.map_err(|e| ZNSError::Servfail {
+        message: e.to_string(),
+    })?;
+
+    Ok(match transfer {
+        Transfer::Current(soa) | Transfer::Full { soa, .. } | Transfer::Incremental { soa, .. } => {
+            soa
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+    use zns::{
+        structs::RData,
+        test_utils::{get_message, get_rr},
+        tsig::{Tsig, TsigAlgorithm, TsigError},
+    };
+
+    use crate::{
+        db::{lib::tests::get_test_connection, models::get_from_database},
+        history::get_serial,
+    };
+
+    use super::*;
+
+    fn soa(zone: &LabelString, serial: u32) -> RR {
+        get_soa(zone, serial).unwrap()
+    }
+
+    fn record(name: &LabelString, octet: u8) -> RR {
+        RR {
+            name: name.clone(),
+            rdata: RData::Vec(vec![10, 0, 0, octet]),
+            ..get_rr(None)
+        }
+    }
+
+    #[test]
+    fn test_parse_transfer() {
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let www = zone.prepend("www".to_string());
+
+        assert_eq!(parse_transfer(&[], None).unwrap(), None);
+        assert!(parse_transfer(&[record(&www, 1)], None).is_err());
+
+        // AXFR
+        let axfr = [
+            soa(&zone, 3),
+            record(&www, 1),
+            record(&www, 2),
+            soa(&zone, 3),
+        ];
+        assert_eq!(parse_transfer(&axfr[..1], None).unwrap(), None);
+        assert_eq!(parse_transfer(&axfr[..3], None).unwrap(), None);
+        assert_eq!(
+            parse_transfer(&axfr, Some(1)).unwrap(),
+            Some(Transfer::Full {
+                soa: get_soa_rdata(&axfr[0]).unwrap(),
+                records: axfr[1..3].to_vec()
+            })
+        );
+
+        // Up to date
+        assert_eq!(
+            parse_transfer(&axfr[..1], Some(3)).unwrap(),
+            Some(Transfer::Current(get_soa_rdata(&axfr[0]).unwrap()))
+        );
+
+        // IXFR
+        let ixfr = [
+            soa(&zone, 3),
+            soa(&zone, 1),
+            record(&www, 1),
+            soa(&zone, 2),
+            soa(&zone, 2),
+            soa(&zone, 3),
+            record(&www, 2),
+            soa(&zone, 3),
+        ];
+        for end in 1..ixfr.len() {
+            assert_eq!(parse_transfer(&ixfr[..end], Some(1)).unwrap(), None);
+        }
+        assert_eq!(
+            parse_transfer(&ixfr, Some(1)).unwrap(),
+            Some(Transfer::Incremental {
+                soa: get_soa_rdata(&ixfr[0]).unwrap(),
+                changes: vec![
+                    Change {
+                        from: 1,
+                        to: 2,
+                        deleted: vec![record(&www, 1)],
+                        added: vec![]
+                    },
+                    Change {
+                        from: 2,
+                        to: 3,
+                        deleted: vec![],
+                        added: vec![record(&www, 2)]
+                    }
+                ]
+            })
+        );
+        assert!(parse_transfer(&[&ixfr[..], &[record(&www, 3)]].concat(), Some(1)).is_err());
+
+        // AXFR of a zone without records in response to an IXFR
+        assert_eq!(
+            parse_transfer(&[soa(&zone, 3), soa(&zone, 3)], Some(1)).unwrap(),
+            Some(Transfer::Full {
+                soa: get_soa_rdata(&axfr[0]).unwrap(),
+                records: vec![]
+            })
+        );
+    }
+
+    #[test]
+    fn test_apply_transfer() {
+        let mut connection = get_test_connection();
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let www = zone.prepend("www".to_string());
+        let other = Config::get()
+            .authoritative_zone
+            .prepend("alice".to_string());
+        let records = |connection: &mut PgConnection| {
+            get_from_database(&www, None, Class::Class(RRClass::IN), connection).unwrap()
+        };
+
+        insert_into_database(&record(&www, 9), &mut connection).unwrap();
+        let full = Transfer::Full {
+            soa: get_soa_rdata(&soa(&zone, 5)).unwrap(),
+            records: vec![record(&www, 1), record(&www, 2), record(&other, 3)],
+        };
+        assert_eq!(
+            apply_transfer(&zone, full, &mut connection).unwrap().serial,
+            5
+        );
+        assert_eq!(
+            records(&mut connection),
+            vec![record(&www, 1), record(&www, 2)]
+        );
+        assert_eq!(get_serial(&zone, &mut connection).unwrap(), 5);
+        assert!(
+            get_from_database(&other, None, Class::Class(RRClass::IN), &mut connection)
+                .unwrap()
+                .is_empty()
+        );
+
+        let incremental = Transfer::Incremental {
+            soa: get_soa_rdata(&soa(&zone, 7)).unwrap(),
+            changes: vec![
+                Change {
+                    from: 5,
+                    to: 6,
+                    deleted: vec![record(&www, 1)],
+                    added: vec![record(&www, 3)],
+                },
+                Change {
+                    from: 6,
+                    to: 7,
+                    deleted: vec![record(&www, 2)],
+                    added: vec![],
+                },
+            ],
+        };
+        apply_transfer(&zone, incremental, &mut connection).unwrap();
+        assert_eq!(records(&mut connection), vec![record(&www, 3)]);
+        assert_eq!(get_serial(&zone, &mut connection).unwrap(), 7);
+
+        // The primary was reset to a lower serial
+        let full = Transfer::Full {
+            soa: get_soa_rdata(&soa(&zone, 2)).unwrap(),
+            records: vec![],
+        };
+        apply_transfer(&zone, full, &mut connection).unwrap();
+        assert!(records(&mut connection).is_empty());
+        assert_eq!(get_serial(&zone, &mut connection).unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_request_transfer() {
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let www = zone.prepend("www".to_string());
+        let key = TsigKey {
+            name: zone.prepend("tsig".to_string()),
+            algorithm: TsigAlgorithm::HmacSha256,
+            secret: b"zeus".to_vec(),
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let primary = listener.local_addr().unwrap();
+        let (server_zone, server_key, server_www) = (zone.clone(), key.clone(), www.clone());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut raw = vec![0; stream.read_u16().await.unwrap() as usize];
+            stream.read_exact(&mut raw).await.unwrap();
+
+            let tsig = Tsig::from_message(&raw).unwrap();
+            tsig.verify(&server_key, None, now().unwrap()).unwrap();
+            let request = Message::from_bytes(&mut Reader::new(&raw)).unwrap();
+            assert_eq!(request.question[0].qtype, Type::Type(RRType::IXFR));
+            assert_eq!(get_soa_rdata(&request.authority[0]).unwrap().serial, 1);
+
+            // The answer is split over two messages, which are both signed
+            let mut signer = TsigStream::new(server_key, tsig.mac());
+            let answers = [
+                vec![soa(&server_zone, 2), record(&server_www, 1)],
+                vec![soa(&server_zone, 2)],
+            ];
+            for answer in answers {
+                let mut response = get_message(Some(server_zone.clone()));
+                response.header.id = request.header.id;
+                response.answer = vec![];
+                response.authority = vec![];
+                response.additional = vec![];
+                response.header.ancount = 0;
+                response.header.nscount = 0;
+                response.header.arcount = 0;
+                response.set_response(RCODE::NOERROR);
+                response.extend_answer(answer);
+                let mut response = Message::to_bytes(response);
+                signer
+                    .sign(&mut response, now().unwrap(), TsigError::NOERROR, vec![])
+                    .unwrap();
+                stream.write_u16(response.len() as u16).await.unwrap();
+                stream.write_all(&response).await.unwrap();
+            }
+        });
+
+        let transfer = request_transfer(primary, &zone, Some(1), key)
+            .await
+            .unwrap();
+        server.await.unwrap();
+        assert_eq!(
+            transfer,
+            Transfer::Full {
+                soa: get_soa_rdata(&soa(&zone, 2)).unwrap(),
+                records: vec![record(&www, 1)]
+            }
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use std::fmt::Display;
+
+use diesel::PgConnection;
+use zns::{
+    errors::ZNSError,
+    labelstring::LabelString,
+    parser::parse_types,
+    structs::{RRType, Type, RR},
+};
+
+use crate::{
+    auth::owners::user_zone,
+    config::Config,
+    db::models::{get_zone_type_policy, set_zone_type_policy, ZoneTypePolicyRecord},
+};
+
+/// Types which are managed by the server or only used in messages, these can never be added
+static ILLEGAL_TYPES: [RRType; 6] = [
+    RRType::SOA,
+    RRType::DS,
+    RRType::OPT,
+    RRType::TSIG,
+    RRType::AXFR,
+    RRType::ANY,
+];
+
+/// Types which can be added to a zone: the global policy combined with the policy of the zone
+#[derive(Debug, PartialEq)]
+pub struct TypePolicy {
+    /// Empty if all types are allowed
+    pub allowed: Vec<Type>,
+    pub denied: Vec<Type>,
+    /// NS records can be added to delegate zones, see `ZNS_DELEGATION`
+    pub delegation: bool,
+}
+
+fn format_types(types: &[Type]) -> String {
+    types
+        .iter()
+        .map(Type::to_string)
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+impl Display for TypePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let allowed = match self.allowed.is_empty() {
+            true => String::from("all"),
+            false => format_types(&self.allowed),
+        };
+        write!(
+            f,
+            "allowed: {}, denied: {}",
+            allowed,
+            format_types(&self.denied)
+        )
+    }
+}
+
+/// Zone below the authoritative zone to which the policy applies
+fn policy_zone(zone: &LabelString) -> Result<LabelString, ZNSError> {
+    user_zone(zone).ok_or(ZNSError::Refused {
+        message: format!(
+            "Zone {} is not below {}",
+            zone,
+            Config::get().authoritative_zone
+        ),
+    })
+}
+
+impl TypePolicy {
+    /// The allowed types of the zone replace the global allowed types,
+    /// types denied globally or by the zone are always denied.
+    pub fn get(zone: &LabelString, connection: &mut PgConnection) -> Result<Self, ZNSError> {
+        let record = get_zone_type_policy(&policy_zone(zone)?, connection)?;
+        let parse = |types: &str| {
+            parse_types(types).map_err(|e| ZNSError::Servfail {
+                message: format!("Invalid type policy for {}: {}", zone, e),
+            })
+        };
+
+        let allowed = match record.as_ref().and_then(|r| r.allowed.as_ref()) {
+            Some(types) => parse(types)?,
+            None => Config::get().allowed_types.clone(),
+        };
+        let mut denied = Config::get().denied_types.clone();
+        if let Some(types) = record.as_ref().and_then(|r| r.denied.as_ref()) {
+            denied.extend(parse(types)?);
+        }
+
+        Ok(TypePolicy {
+            allowed,
+            denied,
+            delegation: Config::get().delegation,
+        })
+    }
+
+    /// Refuses records which may not be added to the zone
+    pub fn check(&self, rr: &RR, zone: &LabelString) -> Result<(), ZNSError> {
+        let refuse = |reason: String| {
+            Err(ZNSError::Refused {
+                message: format!("{} {} can't be added: {}", rr.name, rr._type, reason),
+            })
+        };
+
+        if let Type::Type(rrtype) = &rr._type {
+            if ILLEGAL_TYPES.contains(rrtype) {
+                return refuse(String::from("type is managed by the server"));
+            }
+        }
+
+        let delegation = rr._type == Type::Type(RRType::NS);
+        if delegation && !self.delegation {
+            return refuse(String::from("delegation is not enabled"));
+        }
+
+        if self.denied.contains(&rr._type) {
+            return refuse(format!("type is denied in {}", policy_zone(zone)?));
+        }
+
+        // NS records are allowed when delegation is enabled, unless they are denied explicitly
+        if !delegation && !self.allowed.is_empty() && !self.allowed.contains(&rr._type) {
+            return refuse(format!("type is not allowed in {}", policy_zone(zone)?));
+        }
+
+        let apex = policy_zone(zone)?;
+        // The zone of a user is served by ZNS, only the zones below it can be delegated
+        if delegation && rr.name.len() <= apex.len() {
+            return refuse(String::from(
+                "only zones below the zone apex can be delegated",
+            ));
+        }
+
+        if !Config::get().apex_wildcards
+            && rr.name.len() == apex.len() + 1
+            && rr.name.as_slice()[0] == "*"
+            && rr.name.is_subdomain_of(&apex)
+        {
+            return refuse(String::from(
+                "wildcards at the apex of a zone are not allowed",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Overrides the type policy of the zone, `None` uses the global allowed types and only the
+/// globally denied types
+pub fn set_type_policy(
+    zone: &LabelString,
+    allowed: Option<Vec<Type>>,
+    denied: Option<Vec<Type>>,
+    connection: &mut PgConnection,
+) -> Result<(), ZNSError> {
+    set_zone_type_policy(
+        &ZoneTypePolicyRecord {
+            zone: policy_zone(zone)?.to_string(),
+            allowed: allowed.as_deref().map(format_types),
+            denied: denied.as_deref().map(format_types),
+        },
+        connection,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use zns::test_utils::get_rr;
+
+    use crate::db::lib::tests::get_test_connection;
+
+    use super::*;
+
+    fn get_record(name: &LabelString, _type: RRType) -> RR {
+        RR {
+            name: name.clone(),
+            _type: Type::Type(_type),
+            ..get_rr(None)
+        }
+    }
+
+    #[test]
+    fn test_type_policy() {
+        let mut connection = get_test_connection();
+        let zone = Config::get().authoritative_zone.prepend("bob".to_string());
+        let home = zone.prepend("home".to_string());
+
+        let policy = TypePolicy {
+            delegation: false,
+            ..TypePolicy::get(&home, &mut connection).unwrap()
+        };
+        assert_eq!(policy.allowed, Config::get().allowed_types);
+        assert!(policy.check(&get_record(&home, RRType::A), &home).is_ok());
+        assert!(policy
+            .check(&get_record(&home, RRType::SOA), &home)
+            .is_err());
+        assert!(policy.check(&get_record(&home, RRType::DS), &home).is_err());
+        assert!(policy.check(&get_record(&home, RRType::NS), &home).is_err());
+
+        // Zones below the zone of the user can be delegated
+        let delegating = TypePolicy {
+            delegation: true,
+            ..TypePolicy::get(&home, &mut connection).unwrap()
+        };
+        assert!(delegating
+            .check(&get_record(&home, RRType::NS), &home)
+            .is_ok());
+        assert!(delegating
+            .check(&get_record(&zone, RRType::NS), &zone)
+            .is_err());
+        assert!(policy
+            .check(&get_record(&home, RRType::PTR), &home)
+            .is_err());
+
+        // Wildcards are allowed below the apex
+        let wildcard = zone.prepend("*".to_string());
+        assert!(policy
+            .check(&get_record(&wildcard, RRType::A), &zone)
+            .is_err());
+        assert!(policy
+            .check(
+                &get_record(&home.prepend("*".to_string()), RRType::A),
+                &zone
+            )
+            .is_ok());
+
+        set_type_policy(
+            &home,
+            Some(vec![Type::Type(RRType::A), Type::Type(RRType::PTR)]),
+            Some(vec![Type::Type(RRType::A)]),
+            &mut connection,
+        )
+        .unwrap();
+        let policy = TypePolicy::get(&zone, &mut connection).unwrap();
+        assert!(policy.check(&get_record(&home, RRType::PTR), &home).is_ok());
+        assert!(policy.check(&get_record(&home, RRType::A), &home).is_err());
+        assert!(policy
+            .check(&get_record(&home, RRType::TXT), &home)
+            .is_err());
+
+        set_type_policy(&zone, None, None, &mut connection).unwrap();
+        assert_eq!(
+            TypePolicy::get(&zone, &mut connection).unwrap().allowed,
+            Config::get().allowed_types
+        );
+    }
+}
@@ -0,0 +1,234 @@
+//! Replication of a zone between two daemons on localhost: the secondary transfers the zone
+//! with AXFR when it starts, and the changes with IXFR after a NOTIFY of the primary.
+//!
+//! The secondary needs its own database with the migrations, in `SECONDARY_DATABASE_URL`.
+
+use std::{
+    env,
+    net::UdpSocket,
+    process::{Child, Command, Stdio},
+    thread::sleep,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use diesel::{sql_query, Connection, PgConnection, RunQueryDsl};
+use zns::{
+    labelstring::LabelString,
+    parser::{FromBytes, ToBytes},
+    reader::Reader,
+    structs::{Class, Header, Message, Opcode, Question, RData, RRClass, RRType, Type, RR},
+    tsig::{sign_request, TsigAlgorithm, TsigKey},
+};
+use zns_daemon::auth::add_tsig_key;
+
+const USER: &str = "transfer-test";
+const SECRET: &str = "zeus";
+
+/// Time to wait for a change to reach the secondary
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Daemon which is killed when the test ends
+struct Daemon(Child);
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn start_daemon(port: u16, envs: &[(&str, String)]) -> Daemon {
+    Daemon(
+        Command::new(env!("CARGO_BIN_EXE_zns-daemon"))
+            .env("ZNS_PORT", port.to_string())
+            .env("ZNS_ADDRESS", "127.0.0.1")
+            .envs(envs.iter().map(|(key, value)| (key, value)))
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("zns-daemon should start"),
+    )
+}
+
+fn free_port() -> u16 {
+    UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// Removes the records, versions and keys of the zone from the database
+fn clear_zone(connection: &mut PgConnection, zone: &LabelString) {
+    let pattern = format!("%.{}", zone);
+    for statement in [
+        format!(
+            "DELETE FROM records WHERE name = '{}' OR name LIKE '{}'",
+            zone, pattern
+        ),
+        format!("DELETE FROM zone_version_records WHERE zone = '{}'", zone),
+        format!("DELETE FROM zone_versions WHERE zone = '{}'", zone),
+        format!("DELETE FROM update_log WHERE zone = '{}'", zone),
+        format!("DELETE FROM tsig_keys WHERE zone = '{}'", zone),
+    ] {
+        sql_query(statement).execute(connection).unwrap();
+    }
+}
+
+/// Clears the zone in the database and adds the TSIG key of its owner
+fn prepare_database(url: &str, zone: &LabelString) -> PgConnection {
+    let mut connection = PgConnection::establish(url).unwrap();
+    clear_zone(&mut connection, zone);
+    add_tsig_key(
+        &LabelString::from(&format!("tsig.{}", zone)),
+        zone,
+        "hmac-sha256",
+        Some(SECRET.as_bytes().to_vec()),
+        &mut connection,
+    )
+    .unwrap();
+    connection
+}
+
+fn send(port: u16, message: &[u8]) -> Option<Message> {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+    socket.send_to(message, ("127.0.0.1", port)).ok()?;
+    let mut buf = [0; 4096];
+    let length = socket.recv(&mut buf).ok()?;
+    Message::from_bytes(&mut Reader::new(&buf[..length])).ok()
+}
+
+fn address(name: &LabelString, address: [u8; 4]) -> RR {
+    RR {
+        name: name.clone(),
+        _type: Type::Type(RRType::A),
+        class: Class::Class(RRClass::IN),
+        ttl: 300,
+        rdlength: 4,
+        rdata: RData::Vec(address.to_vec()),
+    }
+}
+
+fn update(port: u16, zone: &LabelString, key: &TsigKey, rr: RR) {
+    let message = Message {
+        header: Header {
+            id: 1,
+            flags: (Opcode::UPDATE as u16) << 11,
+            qdcount: 1,
+            ancount: 0,
+            nscount: 1,
+            arcount: 0,
+        },
+        question: vec![Question {
+            qname: zone.clone(),
+            qtype: Type::Type(RRType::SOA),
+            qclass: Class::Class(RRClass::IN),
+        }],
+        answer: vec![],
+        authority: vec![rr],
+        additional: vec![],
+    };
+    let mut bytes = Message::to_bytes(message);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    sign_request(&mut bytes, key, now).unwrap();
+
+    let response = send(port, &bytes).expect("the primary should answer the update");
+    assert_eq!(response.header.flags & 0b1111, 0, "update was refused");
+}
+
+/// Answers of the A query, or `None` if the daemon did not answer (yet)
+fn query(port: u16, name: &LabelString) -> Option<Vec<RR>> {
+    let message = Message {
+        header: Header {
+            id: 2,
+            flags: 0,
+            qdcount: 1,
+            ancount: 0,
+            nscount: 0,
+            arcount: 0,
+        },
+        question: vec![Question {
+            qname: name.clone(),
+            qtype: Type::Type(RRType::A),
+            qclass: Class::Class(RRClass::IN),
+        }],
+        answer: vec![],
+        authority: vec![],
+        additional: vec![],
+    };
+    send(port, &Message::to_bytes(message)).map(|response| response.answer)
+}
+
+fn wait_for(port: u16, rr: &RR) {
+    let start = Instant::now();
+    while start.elapsed() < TIMEOUT {
+        if query(port, &rr.name).is_some_and(|answer| answer.contains(rr)) {
+            return;
+        }
+        sleep(Duration::from_millis(200));
+    }
+    panic!("{} was not replicated to the secondary", rr.name);
+}
+
+#[test]
+fn test_replication() {
+    let primary_db = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let secondary_db =
+        env::var("SECONDARY_DATABASE_URL").expect("SECONDARY_DATABASE_URL must be set");
+    let zone = LabelString::from(&format!(
+        "{}.{}",
+        USER,
+        env::var("ZONE").expect("ZONE must be set")
+    ));
+    let key = TsigKey {
+        name: LabelString::from(&format!("tsig.{}", zone)),
+        algorithm: TsigAlgorithm::HmacSha256,
+        secret: SECRET.as_bytes().to_vec(),
+    };
+    let mut primary_connection = prepare_database(&primary_db, &zone);
+    let mut secondary_connection = prepare_database(&secondary_db, &zone);
+
+    let (primary_port, secondary_port) = (free_port(), free_port());
+    let _primary = start_daemon(
+        primary_port,
+        &[
+            ("DATABASE_URL", primary_db.clone()),
+            ("ZNS_NOTIFY", format!("127.0.0.1:{}", secondary_port)),
+            ("ZNS_NOTIFY_INTERVAL", String::from("0")),
+        ],
+    );
+
+    let start = Instant::now();
+    while query(primary_port, &zone).is_none() {
+        assert!(start.elapsed() < TIMEOUT, "the primary did not start");
+        sleep(Duration::from_millis(200));
+    }
+    let www = address(&LabelString::from(&format!("www.{}", zone)), [10, 0, 0, 1]);
+    update(primary_port, &zone, &key, www.clone());
+
+    // The zone has no versions on the secondary yet, so it is transferred with AXFR
+    let _secondary = start_daemon(
+        secondary_port,
+        &[
+            ("DATABASE_URL", secondary_db.clone()),
+            ("ZNS_PRIMARY", format!("127.0.0.1:{}", primary_port)),
+            ("ZNS_PRIMARY_TSIG", key.name.to_string()),
+            ("ZNS_SECONDARY_ZONES", zone.to_string()),
+        ],
+    );
+    wait_for(secondary_port, &www);
+
+    // The NOTIFY of the primary makes the secondary transfer the change with IXFR
+    let mail = address(&LabelString::from(&format!("mail.{}", zone)), [10, 0, 0, 2]);
+    update(primary_port, &zone, &key, mail.clone());
+    wait_for(secondary_port, &mail);
+    assert_eq!(query(secondary_port, &www.name), Some(vec![www]));
+
+    clear_zone(&mut primary_connection, &zone);
+    clear_zone(&mut secondary_connection, &zone);
+}
@@ -0,0 +1,93 @@
+use base64::prelude::*;
+
+use crate::{errors::ZNSError, labelstring::LabelString};
+
+fn key_error(message: &str) -> ZNSError {
+    ZNSError::Key {
+        message: message.to_string(),
+    }
+}
+
+/// Key tag of a DNSKEY/KEY RDATA
+/// https://datatracker.ietf.org/doc/html/rfc4034#appendix-B
+pub fn key_tag(rdata: &[u8]) -> u16 {
+    let mut ac: u32 = 0;
+
+    for (i, byte) in rdata.iter().enumerate() {
+        ac += if i & 1 == 1 {
+            *byte as u32
+        } else {
+            (*byte as u32) << 8
+        };
+    }
+
+    ac += (ac >> 16) & 0xFFFF;
+    (ac & 0xFFFF) as u16
+}
+
+/// Parses a KEY/DNSKEY record in the format of a `.key` file: owner name and RDATA
+pub fn parse_key_record(content: &str) -> Result<(LabelString, Vec<u8>), ZNSError> {
+    let record = content
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with(';'))
+        .ok_or_else(|| key_error("No public key record found"))?;
+
+    let fields: Vec<&str> = record.split_ascii_whitespace().collect();
+    let position = fields
+        .iter()
+        .position(|field| ["KEY", "DNSKEY"].contains(field))
+        .filter(|position| fields.len() > position + 4)
+        .ok_or_else(|| key_error("Invalid public key record"))?;
+
+    let mut rdata = vec![];
+    for (field, size) in fields[position + 1..position + 4].iter().zip([2, 1, 1]) {
+        let value: u16 = field
+            .parse()
+            .map_err(|_| key_error("Invalid public key record"))?;
+        rdata.extend(&u16::to_be_bytes(value)[2 - size..]);
+    }
+
+    rdata.extend(
+        BASE64_STANDARD
+            .decode(fields[position + 4..].concat())
+            .map_err(|e| key_error(&e.to_string()))?,
+    );
+
+    Ok((LabelString::from(fields[0].trim_end_matches('.')), rdata))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_tag() {
+        // Example taken from https://datatracker.ietf.org/doc/html/rfc4509#section-2.3
+        let mut rdata = vec![1, 0, 3, 5];
+        rdata.extend(
+            BASE64_STANDARD
+                .decode(
+                    "AQOeiiR0GOMYkDshWoSKz9XzfwJr1AYtsmx3TGkJaNXVbfi/\
+                     2pHm822aJ5iI9BMzNXxeYCmZDRD99WYwYqUSdjMmmAphXdvx\
+                     egXd/M5+X7OrzKBaMbCVdFLUUh6DhweJBjEVv5f2wwjM9Xzc\
+                     nOf+EPbtG9DMBmADjFDc2w/rljwvFw==",
+                )
+                .unwrap(),
+        );
+
+        assert_eq!(key_tag(&rdata), 60485);
+    }
+
+    #[test]
+    fn test_parse_key_record() {
+        let content = "; This is a key\nuser.zeus.gent. 3600 IN KEY 512 3 15 AQID\n";
+        let (name, rdata) = parse_key_record(content).unwrap();
+        assert_eq!(name, LabelString::from("user.zeus.gent"));
+        assert_eq!(rdata, vec![2, 0, 3, 15, 1, 2, 3]);
+
+        assert!(parse_key_record("; Only a comment").is_err());
+        assert!(parse_key_record("user.zeus.gent. IN KEY 512 3").is_err());
+        assert!(parse_key_record("user.zeus.gent. IN KEY 512 3 15 !!").is_err());
+    }
+}
@@ -0,0 +1,93 @@
+use crate::structs::{Class, Message, RData, RRType, Type, RR};
+
+/// UDP payload size advertised in the OPT record of responses
+pub const UDP_PAYLOAD_SIZE: u16 = 1232;
+
+/// https://datatracker.ietf.org/doc/html/rfc8914#section-2
+const EXTENDED_ERROR_OPTION: u16 = 15;
+
+/// INFO-CODE of an Extended DNS Error
+/// https://datatracker.ietf.org/doc/html/rfc8914#section-4
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExtendedError {
+    Other = 0,
+    Prohibited = 18,
+}
+
+pub fn has_opt(message: &Message) -> bool {
+    message
+        .additional
+        .iter()
+        .any(|rr| rr._type == Type::Type(RRType::OPT))
+}
+
+/// OPT pseudo-RR with an Extended DNS Error option
+/// https://datatracker.ietf.org/doc/html/rfc6891#section-6.1.2
+pub fn extended_error_rr(code: ExtendedError, text: &str) -> RR {
+    let mut rdata = vec![];
+    rdata.extend(u16::to_be_bytes(EXTENDED_ERROR_OPTION));
+    rdata.extend(u16::to_be_bytes(2 + text.len() as u16));
+    rdata.extend(u16::to_be_bytes(code as u16));
+    rdata.extend(text.as_bytes());
+
+    RR {
+        name: Vec::new().into(),
+        _type: Type::Type(RRType::OPT),
+        class: Class::Other(UDP_PAYLOAD_SIZE),
+        ttl: 0,
+        rdlength: rdata.len() as u16,
+        rdata: RData::Vec(rdata),
+    }
+}
+
+/// Replaces the OPT record of the message with one containing the Extended DNS Error.
+/// The error is only added if the message has an OPT record, as requests without EDNS
+/// can't have EDNS options in the response.
+pub fn set_extended_error(message: &mut Message, code: ExtendedError, text: &str) {
+    if has_opt(message) {
+        message
+            .additional
+            .retain(|rr| rr._type != Type::Type(RRType::OPT));
+        message.additional.push(extended_error_rr(code, text));
+        message.header.arcount = message.additional.len() as u16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser::{FromBytes, ToBytes},
+        reader::Reader,
+        test_utils::get_message,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_extended_error() {
+        let mut message = get_message(None);
+        set_extended_error(&mut message, ExtendedError::Other, "quota");
+        assert!(!has_opt(&message));
+
+        message
+            .additional
+            .push(extended_error_rr(ExtendedError::Other, ""));
+        message.header.arcount = message.additional.len() as u16;
+        set_extended_error(&mut message, ExtendedError::Prohibited, "quota");
+
+        let bytes = Message::to_bytes(message);
+        let message = Message::from_bytes(&mut Reader::new(&bytes)).unwrap();
+        let opt: Vec<&RR> = message
+            .additional
+            .iter()
+            .filter(|rr| rr._type == Type::Type(RRType::OPT))
+            .collect();
+        assert_eq!(opt.len(), 1);
+        assert_eq!(opt[0].class, Class::Other(UDP_PAYLOAD_SIZE));
+        assert_eq!(
+            Vec::from(opt[0].rdata.clone()),
+            vec![0, 15, 0, 7, 0, 18, b'q', b'u', b'o', b't', b'a']
+        );
+    }
+}
@@ -40,6 +40,12 @@ impl LabelString {
         self.len() == 0
     }
 
+    /// Checks if the name is equal to or below the given zone
+    pub fn is_subdomain_of(&self, zone: &LabelString) -> bool {
+        self.len() >= zone.len()
+            && labels_equal(&self.as_slice()[self.len() - zone.len()..].into(), zone)
+    }
+
     #[cfg(feature = "test-utils")]
     pub fn prepend(&self, element: String) -> Self {
         let mut vec = self.0.clone();
@@ -89,4 +95,14 @@ mod tests {
             &LabelString::from("oNEe.two")
         ));
     }
+
+    #[test]
+    fn test_is_subdomain_of() {
+        let zone = LabelString::from("user.zeus.gent");
+
+        assert!(LabelString::from("bob.User.zeus.gent").is_subdomain_of(&zone));
+        assert!(LabelString::from("user.zeus.gent").is_subdomain_of(&zone));
+        assert!(!LabelString::from("zeus.gent").is_subdomain_of(&zone));
+        assert!(!LabelString::from("bob.users.zeus.gent").is_subdomain_of(&zone));
+    }
 }
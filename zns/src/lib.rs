@@ -1,8 +1,11 @@
+pub mod dnssec;
+pub mod edns;
 pub mod errors;
 pub mod labelstring;
 pub mod message;
 pub mod parser;
 pub mod reader;
 pub mod structs;
+pub mod tsig;
 
 pub mod test_utils;
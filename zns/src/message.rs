@@ -1,12 +1,24 @@
 use crate::{
+    errors::ZNSError,
     labelstring::LabelString,
-    structs::{Message, Opcode, RRType, Type, RCODE, RR},
+    parser::FromBytes,
+    reader::Reader,
+    structs::{Header, Message, Opcode, Question, RRType, Type, RCODE, RR},
 };
 
 impl Message {
+    /// Marks the message as an authoritative response with the RCODE. Messages which are
+    /// already marked as a response keep their AA flag, e.g. referrals.
     pub fn set_response(&mut self, rcode: RCODE) {
-        self.header.flags =
-            (self.header.flags | 0b1000_0100_0000_0000 | rcode as u16) & 0b1111_1101_0111_1111;
+        let authoritative = match self.header.flags & 0b1000_0000_0000_0000 {
+            0 => 0b0000_0100_0000_0000,
+            _ => self.header.flags & 0b0000_0100_0000_0000,
+        };
+        self.header.flags = ((self.header.flags & !0b0000_0100_0000_0000)
+            | 0b1000_0000_0000_0000
+            | authoritative
+            | rcode as u16)
+            & 0b1111_1101_0111_1111;
 
         self.remove_signature();
     }
@@ -45,6 +57,40 @@ impl Message {
         }
     }
 
+    /// Splits a raw message into the last record of the additional section
+    /// and the raw message without that record (with decreased arcount).
+    /// Used to validate signatures (SIG(0) and TSIG) which are computed over the message without the signature record.
+    pub fn split_last_additional(raw: &[u8]) -> Result<(Vec<u8>, RR), ZNSError> {
+        let mut reader = Reader::new(raw);
+        let header = Header::from_bytes(&mut reader)?;
+
+        let arcount = header.arcount.checked_sub(1).ok_or(ZNSError::Formerr {
+            message: String::from("Additional section is empty"),
+        })?;
+
+        for _ in 0..header.qdcount {
+            Question::from_bytes(&mut reader)?;
+        }
+
+        for _ in 0..(header.ancount as usize + header.nscount as usize + arcount as usize) {
+            RR::from_bytes(&mut reader)?;
+        }
+
+        let offset = reader.position();
+        let rr = RR::from_bytes(&mut reader)?;
+
+        if reader.unread_bytes() != 0 {
+            return Err(ZNSError::Formerr {
+                message: String::from("Unexpected data after additional section"),
+            });
+        }
+
+        let mut message = raw[..offset].to_vec();
+        message[10..12].copy_from_slice(&u16::to_be_bytes(arcount));
+
+        Ok((message, rr))
+    }
+
     pub fn extend_answer(&mut self, rrs: Vec<RR>) {
         self.header.ancount += rrs.len() as u16;
         self.answer.extend(rrs);
@@ -82,6 +128,58 @@ mod tests {
         assert!((message.header.flags & (1 << 15)) > 0);
 
         assert_eq!(message.get_rcode().unwrap(), RCODE::NOTIMP);
+
+        message.header.flags = 0b0010_0100_0000_0000;
+        assert_eq!(message.get_opcode().unwrap() as u8, Opcode::NOTIFY as u8);
+    }
+
+    #[test]
+    fn test_set_response() {
+        let mut message = get_message(None);
+        message.set_response(RCODE::NOERROR);
+        assert!(message.header.flags & (1 << 15) > 0);
+        assert!(message.header.flags & (1 << 10) > 0);
+
+        // Responses which are not authoritative stay so
+        message.header.flags &= !(1 << 10);
+        message.set_response(RCODE::NOERROR);
+        assert_eq!(message.header.flags & (1 << 10), 0);
+    }
+
+    #[test]
+    fn test_split_last_additional() {
+        use crate::{parser::ToBytes, test_utils::get_rr};
+
+        let mut message = get_message(None);
+        let raw = Message::to_bytes(message.clone());
+
+        let last = message.additional.pop().unwrap();
+        message.header.arcount -= 1;
+
+        let (stripped, rr) = Message::split_last_additional(&raw).unwrap();
+        assert_eq!(rr, last);
+        assert_eq!(stripped, Message::to_bytes(message.clone()));
+
+        // No additional records left
+        assert!(Message::split_last_additional(&stripped).is_err());
+
+        // Trailing data
+        let mut trailing = raw.clone();
+        trailing.push(0);
+        assert!(Message::split_last_additional(&trailing).is_err());
+
+        // Truncated
+        assert!(Message::split_last_additional(&raw[..raw.len() - 1]).is_err());
+        assert!(Message::split_last_additional(&raw[..5]).is_err());
+
+        // Owner of the last record is not the root
+        message.extend_answer(vec![]);
+        message
+            .additional
+            .push(get_rr(Some(LabelString::from("sig.example.org"))));
+        message.header.arcount += 1;
+        let (_, rr) = Message::split_last_additional(&Message::to_bytes(message)).unwrap();
+        assert_eq!(rr.name, LabelString::from("sig.example.org"));
     }
 
     #[test]
@@ -1,11 +1,12 @@
-use std::mem::size_of;
+use std::{fmt::Display, mem::size_of, str::FromStr};
 
 use crate::{
     errors::ZNSError,
     labelstring::LabelString,
     reader::Reader,
     structs::{
-        Class, Header, Message, Opcode, Question, RData, RRClass, RRType, SoaRData, Type, RR,
+        Class, DsRData, Header, Message, Opcode, Question, RData, RRClass, RRType, SigRData,
+        SoaRData, TsigRData, Type, RR,
     },
 };
 
@@ -50,6 +51,65 @@ impl From<u16> for Type {
     }
 }
 
+impl FromStr for Type {
+    type Err = String;
+
+    /// Parses the mnemonic of a type or the generic `TYPE<number>` notation.
+    /// https://datatracker.ietf.org/doc/html/rfc3597#section-5
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_uppercase();
+        let rrtype = match name.as_str() {
+            "A" => RRType::A,
+            "NS" => RRType::NS,
+            "CNAME" => RRType::CNAME,
+            "SOA" => RRType::SOA,
+            "PTR" => RRType::PTR,
+            "MX" => RRType::MX,
+            "TXT" => RRType::TXT,
+            "SIG" => RRType::SIG,
+            "KEY" => RRType::KEY,
+            "AAAA" => RRType::AAAA,
+            "SRV" => RRType::SRV,
+            "OPT" => RRType::OPT,
+            "DS" => RRType::DS,
+            "DNSKEY" => RRType::DNSKEY,
+            "CDS" => RRType::CDS,
+            "CDNSKEY" => RRType::CDNSKEY,
+            "TSIG" => RRType::TSIG,
+            "IXFR" => RRType::IXFR,
+            "AXFR" => RRType::AXFR,
+            "ANY" => RRType::ANY,
+            "CAA" => RRType::CAA,
+            _ => {
+                return name
+                    .strip_prefix("TYPE")
+                    .and_then(|number| number.parse::<u16>().ok())
+                    .map(Type::from)
+                    .ok_or(format!("Unknown type: {}", s));
+            }
+        };
+        Ok(Type::Type(rrtype))
+    }
+}
+
+/// Mnemonic of the type, or `TYPE<number>` if the type is unknown
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Type(rrtype) => write!(f, "{:?}", rrtype),
+            Type::Other(x) => write!(f, "TYPE{}", x),
+        }
+    }
+}
+
+/// Parses a list of types separated by commas or whitespace, e.g. `A, AAAA TXT`
+pub fn parse_types(s: &str) -> std::result::Result<Vec<Type>, String> {
+    s.split(|c: char| c == ',' || c.is_ascii_whitespace())
+        .filter(|name| !name.is_empty())
+        .map(Type::from_str)
+        .collect()
+}
+
 impl From<u16> for Class {
     fn from(value: u16) -> Self {
         match RRClass::try_from(value) {
@@ -59,12 +119,23 @@ impl From<u16> for Class {
     }
 }
 
+/// Mnemonic of the class, or `CLASS<number>` if the class is unknown
+impl Display for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Class::Class(rrclass) => write!(f, "{:?}", rrclass),
+            Class::Other(x) => write!(f, "CLASS{}", x),
+        }
+    }
+}
+
 impl TryFrom<u16> for Opcode {
     type Error = String;
 
     fn try_from(value: u16) -> std::result::Result<Self, String> {
         match value {
             x if x == Opcode::QUERY as u16 => Ok(Opcode::QUERY),
+            x if x == Opcode::NOTIFY as u16 => Ok(Opcode::NOTIFY),
             x if x == Opcode::UPDATE as u16 => Ok(Opcode::UPDATE),
             _ => Err(format!("Invalid Opcode value: {}", value)),
         }
@@ -211,13 +282,32 @@ impl From<RData> for Vec<u8> {
 
 impl RData {
     pub fn from(reader: &mut Reader, rdlength: u16, rr_type: &Type) -> Result<Self> {
-        match rr_type {
-            Type::Type(RRType::CNAME) => Ok(Self::LabelString(LabelString::from_bytes(reader)?)),
-            _ => {
-                let data = reader.read(rdlength as usize)?;
-                Ok(Self::Vec(data.to_vec()))
+        let start = reader.position();
+        let rdata = match rr_type {
+            // Empty RDATA is used to delete RRsets in updates
+            _ if rdlength == 0 => Self::Vec(vec![]),
+            Type::Type(RRType::CNAME) => Self::LabelString(LabelString::from_bytes(reader)?),
+            // Names in the RDATA of these types can be compressed, they are stored uncompressed
+            // https://datatracker.ietf.org/doc/html/rfc3597#section-4
+            Type::Type(RRType::NS) | Type::Type(RRType::PTR) => {
+                Self::Vec(LabelString::to_bytes(LabelString::from_bytes(reader)?))
+            }
+            Type::Type(RRType::SOA) => Self::Vec(SoaRData::to_bytes(SoaRData::from_bytes(reader)?)),
+            Type::Type(RRType::MX) => {
+                let mut data = reader.read(2)?;
+                data.extend(LabelString::to_bytes(LabelString::from_bytes(reader)?));
+                Self::Vec(data)
             }
+            _ => Self::Vec(reader.read(rdlength as usize)?),
+        };
+
+        if reader.position() - start != rdlength as usize {
+            return Err(ZNSError::Parse {
+                object: String::from("RData"),
+                message: format!("RDATA of {} does not match rdlength", rr_type),
+            });
         }
+        Ok(rdata)
     }
 
     pub fn from_safe(data: &[u8], rr_type: &Type) -> Result<Self> {
@@ -254,13 +344,15 @@ impl FromBytes for RR {
                 message: String::from("len of rest of bytes not equal to rdlength"),
             })
         } else {
+            let rdata = RData::from(reader, rdlength, &_type)?;
             Ok(RR {
                 name,
-                rdata: RData::from(reader, rdlength, &_type)?,
+                // Length of the uncompressed RDATA
+                rdlength: Vec::from(rdata.clone()).len() as u16,
+                rdata,
                 _type,
                 class,
                 ttl,
-                rdlength,
             })
         }
     }
@@ -334,6 +426,20 @@ impl ToBytes for Message {
     }
 }
 
+impl FromBytes for SoaRData {
+    fn from_bytes(reader: &mut Reader) -> Result<Self> {
+        Ok(SoaRData {
+            mname: LabelString::from_bytes(reader)?,
+            rname: LabelString::from_bytes(reader)?,
+            serial: reader.read_u32()?,
+            refresh: reader.read_i32()?,
+            retry: reader.read_i32()?,
+            expire: reader.read_i32()?,
+            minimum: reader.read_u32()?,
+        })
+    }
+}
+
 impl ToBytes for SoaRData {
     fn to_bytes(rdata: Self) -> Vec<u8> {
         let mut result = LabelString::to_bytes(rdata.mname);
@@ -347,12 +453,187 @@ impl ToBytes for SoaRData {
     }
 }
 
+impl FromBytes for DsRData {
+    fn from_bytes(reader: &mut Reader) -> Result<Self> {
+        if reader.unread_bytes() < 5 {
+            Err(ZNSError::Parse {
+                object: String::from("DsRData"),
+                message: String::from("len of rest bytes smaller then minimum size"),
+            })
+        } else {
+            Ok(DsRData {
+                key_tag: reader.read_u16()?,
+                algorithm: reader.read_u8()?,
+                digest_type: reader.read_u8()?,
+                digest: reader.read(reader.unread_bytes())?,
+            })
+        }
+    }
+}
+
+impl ToBytes for DsRData {
+    fn to_bytes(rdata: Self) -> Vec<u8> {
+        let mut result = u16::to_be_bytes(rdata.key_tag).to_vec();
+        result.push(rdata.algorithm);
+        result.push(rdata.digest_type);
+        result.extend(rdata.digest);
+        result
+    }
+}
+
+impl FromBytes for SigRData {
+    fn from_bytes(reader: &mut Reader) -> Result<Self> {
+        if reader.unread_bytes() < 18 {
+            Err(ZNSError::Parse {
+                object: String::from("SigRData"),
+                message: String::from("invalid rdata"),
+            })
+        } else {
+            Ok(SigRData {
+                type_covered: reader.read_u16()?,
+                algorithm: reader.read_u8()?,
+                labels: reader.read_u8()?,
+                original_ttl: reader.read_u32()?,
+                signature_expiration: reader.read_u32()?,
+                signature_inception: reader.read_u32()?,
+                key_tag: reader.read_u16()?,
+                signer: LabelString::from_bytes(reader)?,
+                signature: reader.read(reader.unread_bytes())?,
+            })
+        }
+    }
+}
+
+impl ToBytes for SigRData {
+    fn to_bytes(rdata: Self) -> Vec<u8> {
+        let mut result = u16::to_be_bytes(rdata.type_covered).to_vec();
+        result.push(rdata.algorithm);
+        result.push(rdata.labels);
+        result.extend(u32::to_be_bytes(rdata.original_ttl));
+        result.extend(u32::to_be_bytes(rdata.signature_expiration));
+        result.extend(u32::to_be_bytes(rdata.signature_inception));
+        result.extend(u16::to_be_bytes(rdata.key_tag));
+        result.extend(LabelString::to_bytes(rdata.signer));
+        result.extend(rdata.signature);
+        result
+    }
+}
+
+impl FromBytes for TsigRData {
+    fn from_bytes(reader: &mut Reader) -> Result<Self> {
+        let algorithm = LabelString::from_bytes(reader)?;
+        let time_signed = ((reader.read_u16()? as u64) << 32) | reader.read_u32()? as u64;
+        let fudge = reader.read_u16()?;
+        let mac_size = reader.read_u16()?;
+        let mac = reader.read(mac_size as usize)?;
+        let original_id = reader.read_u16()?;
+        let error = reader.read_u16()?;
+        let other_len = reader.read_u16()?;
+        let other = reader.read(other_len as usize)?;
+
+        if reader.unread_bytes() != 0 {
+            return Err(ZNSError::Parse {
+                object: String::from("TsigRData"),
+                message: String::from("unexpected data after other data"),
+            });
+        }
+
+        Ok(TsigRData {
+            algorithm,
+            time_signed,
+            fudge,
+            mac,
+            original_id,
+            error,
+            other,
+        })
+    }
+}
+
+impl ToBytes for TsigRData {
+    fn to_bytes(rdata: Self) -> Vec<u8> {
+        let mut result = LabelString::to_bytes(rdata.algorithm);
+        result.extend(&u64::to_be_bytes(rdata.time_signed)[2..]);
+        result.extend(u16::to_be_bytes(rdata.fudge));
+        result.extend(u16::to_be_bytes(rdata.mac.len() as u16));
+        result.extend(rdata.mac);
+        result.extend(u16::to_be_bytes(rdata.original_id));
+        result.extend(u16::to_be_bytes(rdata.error));
+        result.extend(u16::to_be_bytes(rdata.other.len() as u16));
+        result.extend(rdata.other);
+        result
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use crate::test_utils::{get_message, get_rr};
 
     use super::*;
 
+    #[test]
+    fn test_parse_type() {
+        assert_eq!("aaaa".parse(), Ok(Type::Type(RRType::AAAA)));
+        assert_eq!("TXT".parse(), Ok(Type::Type(RRType::TXT)));
+        assert_eq!("TYPE28".parse(), Ok(Type::Type(RRType::AAAA)));
+        assert_eq!("TYPE65280".parse(), Ok(Type::Other(65280)));
+        assert!("TYPE".parse::<Type>().is_err());
+        assert!("FOO".parse::<Type>().is_err());
+        assert_eq!(Type::Type(RRType::AAAA).to_string(), "AAAA");
+        assert_eq!(Type::Other(65280).to_string(), "TYPE65280");
+        assert_eq!(Class::Class(RRClass::NONE).to_string(), "NONE");
+        assert_eq!(Class::Other(3).to_string(), "CLASS3");
+
+        assert_eq!(
+            parse_types("A, aaaa TXT"),
+            Ok(vec![
+                Type::Type(RRType::A),
+                Type::Type(RRType::AAAA),
+                Type::Type(RRType::TXT)
+            ])
+        );
+        assert_eq!(parse_types(""), Ok(vec![]));
+        assert!(parse_types("A,FOO").is_err());
+    }
+
+    #[test]
+    fn test_parse_compressed_rdata() {
+        let zone = LabelString::from("example.org");
+        let mut message = vec![0, 1, 0x28, 0, 0, 1, 0, 0, 0, 1, 0, 0];
+        message.extend(LabelString::to_bytes(zone.clone()));
+        message.extend([0, 6, 0, 1]);
+
+        // MX record with a pointer to the name in the question
+        let mut rr = vec![0xc0, 12, 0, 15, 0, 1, 0, 0, 1, 44, 0, 4, 0, 10, 0xc0, 12];
+        let mut bytes = message.clone();
+        bytes.extend(rr.clone());
+        let parsed = Message::from_bytes(&mut Reader::new(&bytes)).unwrap();
+
+        let mut expected = vec![0, 10];
+        expected.extend(LabelString::to_bytes(zone.clone()));
+        assert_eq!(parsed.authority[0].rdlength as usize, expected.len());
+        assert_eq!(Vec::from(parsed.authority[0].rdata.clone()), expected);
+
+        // SOA record with pointers to the name in the question
+        let mut soa = vec![0xc0, 12, 0, 6, 0, 1, 0, 0, 1, 44, 0, 24, 0xc0, 12, 0xc0, 12];
+        soa.extend([0, 0, 0, 42]);
+        soa.extend([0; 16]);
+        let mut bytes = message.clone();
+        bytes.extend(soa);
+        let parsed = Message::from_bytes(&mut Reader::new(&bytes)).unwrap();
+        let rdata = Vec::from(parsed.authority[0].rdata.clone());
+        let parsed = SoaRData::from_bytes(&mut Reader::new(&rdata)).unwrap();
+        assert_eq!(parsed.rname, zone);
+        assert_eq!(parsed.serial, 42);
+
+        // rdlength is longer than the name
+        rr[11] = 5;
+        rr.push(0);
+        let mut bytes = message;
+        bytes.extend(rr);
+        assert!(Message::from_bytes(&mut Reader::new(&bytes)).is_err());
+    }
+
     #[test]
     fn test_parse_header() {
         let header = Header {
@@ -448,6 +729,67 @@ pub mod tests {
         assert!(parsed.is_err());
     }
 
+    #[test]
+    fn test_parse_ds() {
+        let ds = DsRData {
+            key_tag: 60485,
+            algorithm: 5,
+            digest_type: 2,
+            digest: vec![1; 32],
+        };
+
+        let bytes = DsRData::to_bytes(ds.clone());
+        let parsed = DsRData::from_bytes(&mut Reader::new(&bytes));
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap(), ds);
+
+        assert!(DsRData::from_bytes(&mut Reader::new(&bytes[..4])).is_err());
+    }
+
+    #[test]
+    fn test_parse_sig() {
+        let sig = SigRData {
+            type_covered: 0,
+            algorithm: 15,
+            labels: 0,
+            original_ttl: 0,
+            signature_expiration: 1000,
+            signature_inception: 700,
+            key_tag: 60485,
+            signer: LabelString::from("example.org"),
+            signature: vec![1; 64],
+        };
+
+        let bytes = SigRData::to_bytes(sig.clone());
+        let parsed = SigRData::from_bytes(&mut Reader::new(&bytes));
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap(), sig);
+
+        assert!(SigRData::from_bytes(&mut Reader::new(&bytes[..17])).is_err());
+    }
+
+    #[test]
+    fn test_parse_tsig() {
+        let tsig = TsigRData {
+            algorithm: LabelString::from("hmac-sha256"),
+            time_signed: 0x0102_0304_0506,
+            fudge: 300,
+            mac: vec![1; 32],
+            original_id: 42,
+            error: 0,
+            other: vec![],
+        };
+
+        let bytes = TsigRData::to_bytes(tsig.clone());
+        assert_eq!(bytes[13..19], [1, 2, 3, 4, 5, 6]);
+
+        let parsed = TsigRData::from_bytes(&mut Reader::new(&bytes));
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap(), tsig);
+
+        assert!(TsigRData::from_bytes(&mut Reader::new(&bytes[..bytes.len() - 1])).is_err());
+    }
+
     #[test]
     fn test_parse_message() {
         let message = get_message(None);
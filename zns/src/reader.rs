@@ -8,13 +8,17 @@ pub struct Reader<'a> {
 type Result<T> = std::result::Result<T, ZNSError>;
 
 impl<'a> Reader<'a> {
-    pub fn new(buffer: &[u8]) -> Reader {
+    pub fn new(buffer: &[u8]) -> Reader<'_> {
         Reader {
             buffer,
             position: 0,
         }
     }
 
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
     pub fn unread_bytes(&self) -> usize {
         self.buffer.len() - self.position
     }
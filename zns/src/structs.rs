@@ -17,11 +17,23 @@ pub enum RRType {
     NS = 2,
     CNAME = 5,
     SOA = 6,
+    PTR = 12,
+    MX = 15,
+    TXT = 16,
+    AAAA = 28,
+    SRV = 33,
+    IXFR = 251,
     AXFR = 252,
     SIG = 24,
+    KEY = 25,
+    DS = 43,
     DNSKEY = 48,
+    CDS = 59,
+    CDNSKEY = 60,
     OPT = 41,
+    TSIG = 250,
     ANY = 255,
+    CAA = 257,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -59,6 +71,8 @@ pub enum RCODE {
 
 pub enum Opcode {
     QUERY = 0,
+    /// https://datatracker.ietf.org/doc/html/rfc1996
+    NOTIFY = 4,
     UPDATE = 5,
 }
 
@@ -109,6 +123,7 @@ pub struct RR {
     pub rdata: RData,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct SoaRData {
     pub mname: LabelString,
     pub rname: LabelString,
@@ -118,3 +133,38 @@ pub struct SoaRData {
     pub expire: i32,
     pub minimum: u32,
 }
+
+/// https://datatracker.ietf.org/doc/html/rfc4034#section-5.1
+#[derive(Debug, Clone, PartialEq)]
+pub struct DsRData {
+    pub key_tag: u16,
+    pub algorithm: u8,
+    pub digest_type: u8,
+    pub digest: Vec<u8>,
+}
+
+/// https://datatracker.ietf.org/doc/html/rfc2535#section-4.1
+#[derive(Debug, Clone, PartialEq)]
+pub struct SigRData {
+    pub type_covered: u16,
+    pub algorithm: u8,
+    pub labels: u8,
+    pub original_ttl: u32,
+    pub signature_expiration: u32,
+    pub signature_inception: u32,
+    pub key_tag: u16,
+    pub signer: LabelString,
+    pub signature: Vec<u8>,
+}
+
+/// https://datatracker.ietf.org/doc/html/rfc8945#section-4.2
+#[derive(Debug, Clone, PartialEq)]
+pub struct TsigRData {
+    pub algorithm: LabelString,
+    pub time_signed: u64, // 48 bits
+    pub fudge: u16,
+    pub mac: Vec<u8>,
+    pub original_id: u16,
+    pub error: u16,
+    pub other: Vec<u8>,
+}
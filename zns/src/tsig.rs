@@ -0,0 +1,472 @@
+use int_enum::IntEnum;
+use ring::hmac;
+
+use crate::{
+    errors::ZNSError,
+    labelstring::LabelString,
+    parser::{FromBytes, ToBytes},
+    reader::Reader,
+    structs::{Class, Message, RData, RRClass, RRType, TsigRData, Type, RR},
+};
+
+/// Recommended fudge: https://datatracker.ietf.org/doc/html/rfc8945#section-10
+pub const DEFAULT_FUDGE: u16 = 300;
+
+/// https://datatracker.ietf.org/doc/html/rfc8945#section-6
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TsigAlgorithm {
+    HmacSha256,
+    HmacSha384,
+    HmacSha512,
+}
+
+impl TsigAlgorithm {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim_end_matches('.').to_lowercase().as_str() {
+            "hmac-sha256" => Some(TsigAlgorithm::HmacSha256),
+            "hmac-sha384" => Some(TsigAlgorithm::HmacSha384),
+            "hmac-sha512" => Some(TsigAlgorithm::HmacSha512),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> LabelString {
+        LabelString::from(match self {
+            TsigAlgorithm::HmacSha256 => "hmac-sha256",
+            TsigAlgorithm::HmacSha384 => "hmac-sha384",
+            TsigAlgorithm::HmacSha512 => "hmac-sha512",
+        })
+    }
+
+    fn hmac(&self) -> hmac::Algorithm {
+        match self {
+            TsigAlgorithm::HmacSha256 => hmac::HMAC_SHA256,
+            TsigAlgorithm::HmacSha384 => hmac::HMAC_SHA384,
+            TsigAlgorithm::HmacSha512 => hmac::HMAC_SHA512,
+        }
+    }
+}
+
+/// https://datatracker.ietf.org/doc/html/rfc8945#section-3
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, IntEnum)]
+pub enum TsigError {
+    NOERROR = 0,
+    BADSIG = 16,
+    BADKEY = 17,
+    BADTIME = 18,
+}
+
+#[derive(Debug, Clone)]
+pub struct TsigKey {
+    pub name: LabelString,
+    pub algorithm: TsigAlgorithm,
+    pub secret: Vec<u8>,
+}
+
+impl TsigKey {
+    fn hmac_key(&self) -> hmac::Key {
+        hmac::Key::new(self.algorithm.hmac(), &self.secret)
+    }
+}
+
+/// Data covered by the MAC: https://datatracker.ietf.org/doc/html/rfc8945#section-4.3
+fn digest_data(previous_mac: Option<&[u8]>, message: &[u8], variables: &[u8]) -> Vec<u8> {
+    let mut result = vec![];
+    if let Some(mac) = previous_mac {
+        result.extend(u16::to_be_bytes(mac.len() as u16));
+        result.extend(mac);
+    }
+    result.extend(message);
+    result.extend(variables);
+    result
+}
+
+/// TSIG record at the end of a message
+/// https://datatracker.ietf.org/doc/html/rfc8945#section-4
+#[derive(Debug)]
+pub struct Tsig {
+    pub name: LabelString,
+    pub rdata: TsigRData,
+    /// Message without the TSIG record, with the original ID
+    message: Vec<u8>,
+}
+
+fn canonical(name: &LabelString) -> Vec<u8> {
+    LabelString::to_bytes(
+        name.as_slice()
+            .iter()
+            .map(|label| label.to_lowercase())
+            .collect::<Vec<String>>()
+            .into(),
+    )
+}
+
+/// TSIG variables: https://datatracker.ietf.org/doc/html/rfc8945#section-4.3.3
+fn variables(name: &LabelString, rdata: &TsigRData) -> Vec<u8> {
+    let mut result = canonical(name);
+    result.extend(u16::to_be_bytes(RRClass::ANY as u16));
+    result.extend(u32::to_be_bytes(0));
+    result.extend(canonical(&rdata.algorithm));
+    result.extend(timers(rdata));
+    result.extend(u16::to_be_bytes(rdata.error));
+    result.extend(u16::to_be_bytes(rdata.other.len() as u16));
+    result.extend(&rdata.other);
+    result
+}
+
+/// Only the timers are covered by the MAC of subsequent messages in a multi-message response
+/// https://datatracker.ietf.org/doc/html/rfc8945#section-5.3.1
+fn timers(rdata: &TsigRData) -> Vec<u8> {
+    let mut result = u64::to_be_bytes(rdata.time_signed)[2..].to_vec();
+    result.extend(u16::to_be_bytes(rdata.fudge));
+    result
+}
+
+fn append(message: &mut Vec<u8>, name: &LabelString, rdata: TsigRData) -> Result<(), ZNSError> {
+    let arcount = message
+        .get(10..12)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .and_then(|arcount| arcount.checked_add(1))
+        .ok_or(ZNSError::Servfail {
+            message: String::from("Could not add TSIG to message"),
+        })?;
+    message[10..12].copy_from_slice(&u16::to_be_bytes(arcount));
+
+    let rdata = TsigRData::to_bytes(rdata);
+    message.extend(RR::to_bytes(RR {
+        name: name.clone(),
+        _type: Type::Type(RRType::TSIG),
+        class: Class::Class(RRClass::ANY),
+        ttl: 0,
+        rdlength: rdata.len() as u16,
+        rdata: RData::Vec(rdata),
+    }));
+    Ok(())
+}
+
+impl Tsig {
+    /// Parses the TSIG record, which must be the last record of the additional section
+    pub fn from_message(raw: &[u8]) -> Result<Self, ZNSError> {
+        let (mut message, rr) = Message::split_last_additional(raw)?;
+
+        if rr._type != Type::Type(RRType::TSIG)
+            || rr.class != Class::Class(RRClass::ANY)
+            || rr.ttl != 0
+        {
+            return Err(ZNSError::Formerr {
+                message: String::from("Last record of additional section is not a valid TSIG"),
+            });
+        }
+
+        let data: Vec<u8> = rr.rdata.into();
+        let rdata = TsigRData::from_bytes(&mut Reader::new(&data))?;
+        message[0..2].copy_from_slice(&u16::to_be_bytes(rdata.original_id));
+
+        Ok(Tsig {
+            name: rr.name,
+            rdata,
+            message,
+        })
+    }
+
+    /// Verifies a request, or the first message of a response with the MAC of the request.
+    /// https://datatracker.ietf.org/doc/html/rfc8945#section-5.2
+    pub fn verify(
+        &self,
+        key: &TsigKey,
+        request_mac: Option<&[u8]>,
+        now: u64,
+    ) -> Result<(), TsigError> {
+        let data = variables(&self.name, &self.rdata);
+        self.verify_mac(key, request_mac, &data, now)
+    }
+
+    fn verify_mac(
+        &self,
+        key: &TsigKey,
+        previous_mac: Option<&[u8]>,
+        variables: &[u8],
+        now: u64,
+    ) -> Result<(), TsigError> {
+        if self.name != key.name
+            || TsigAlgorithm::from_name(&self.rdata.algorithm.to_string()) != Some(key.algorithm)
+        {
+            return Err(TsigError::BADKEY);
+        }
+
+        // Truncated MACs are not supported
+        let data = digest_data(previous_mac, &self.message, variables);
+        if hmac::verify(&key.hmac_key(), &data, &self.rdata.mac).is_err() {
+            return Err(TsigError::BADSIG);
+        }
+
+        if now.abs_diff(self.rdata.time_signed) > self.rdata.fudge as u64 {
+            return Err(TsigError::BADTIME);
+        }
+
+        Ok(())
+    }
+
+    pub fn mac(&self) -> &[u8] {
+        &self.rdata.mac
+    }
+
+    /// Appends a TSIG record with an error and without MAC to the response,
+    /// for requests signed with an unknown key or an invalid MAC.
+    /// https://datatracker.ietf.org/doc/html/rfc8945#section-5.3.2
+    pub fn append_error(&self, response: &mut Vec<u8>, error: TsigError) -> Result<(), ZNSError> {
+        let original_id = response
+            .get(0..2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+            .unwrap_or_default();
+
+        append(
+            response,
+            &self.name,
+            TsigRData {
+                algorithm: self.rdata.algorithm.clone(),
+                time_signed: self.rdata.time_signed,
+                fudge: self.rdata.fudge,
+                mac: vec![],
+                original_id,
+                error: error as u16,
+                other: vec![],
+            },
+        )
+    }
+}
+
+/// Signs a request and returns the MAC, which is needed to verify the response
+pub fn sign_request(
+    message: &mut Vec<u8>,
+    key: &TsigKey,
+    time_signed: u64,
+) -> Result<Vec<u8>, ZNSError> {
+    let mut stream = TsigStream {
+        key: key.clone(),
+        previous_mac: None,
+        first: true,
+    };
+    stream.sign(message, time_signed, TsigError::NOERROR, vec![])?;
+    Ok(stream.previous_mac.unwrap_or_default())
+}
+
+/// Signs or verifies the messages of a response, the MAC of each message covers the MAC of the previous one.
+/// A response which consists of one message is signed the same way.
+/// https://datatracker.ietf.org/doc/html/rfc8945#section-5.3.1
+pub struct TsigStream {
+    key: TsigKey,
+    previous_mac: Option<Vec<u8>>,
+    first: bool,
+}
+
+impl TsigStream {
+    pub fn new(key: TsigKey, request_mac: &[u8]) -> Self {
+        TsigStream {
+            key,
+            previous_mac: Some(request_mac.to_vec()),
+            first: true,
+        }
+    }
+
+    pub fn key(&self) -> &TsigKey {
+        &self.key
+    }
+
+    /// Appends a TSIG record to the next message.
+    /// `other` is only used for BADTIME errors, which contain the time of the server.
+    pub fn sign(
+        &mut self,
+        message: &mut Vec<u8>,
+        time_signed: u64,
+        error: TsigError,
+        other: Vec<u8>,
+    ) -> Result<(), ZNSError> {
+        let original_id = message
+            .get(0..2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+            .ok_or(ZNSError::Servfail {
+                message: String::from("Could not sign message"),
+            })?;
+
+        let mut rdata = TsigRData {
+            algorithm: self.key.algorithm.name(),
+            time_signed,
+            fudge: DEFAULT_FUDGE,
+            mac: vec![],
+            original_id,
+            error: error as u16,
+            other,
+        };
+
+        let data = if self.first {
+            variables(&self.key.name, &rdata)
+        } else {
+            timers(&rdata)
+        };
+        rdata.mac = hmac::sign(
+            &self.key.hmac_key(),
+            &digest_data(self.previous_mac.as_deref(), message, &data),
+        )
+        .as_ref()
+        .to_vec();
+
+        self.previous_mac = Some(rdata.mac.clone());
+        self.first = false;
+
+        append(message, &self.key.name, rdata)
+    }
+
+    /// Verifies the next message of the response
+    pub fn verify(&mut self, raw: &[u8], now: u64) -> Result<(), TsigError> {
+        let tsig = Tsig::from_message(raw).map_err(|_| TsigError::BADSIG)?;
+
+        let data = if self.first {
+            variables(&tsig.name, &tsig.rdata)
+        } else {
+            timers(&tsig.rdata)
+        };
+        tsig.verify_mac(&self.key, self.previous_mac.as_deref(), &data, now)?;
+
+        self.previous_mac = Some(tsig.rdata.mac);
+        self.first = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::get_message;
+
+    use super::*;
+
+    fn get_key() -> TsigKey {
+        TsigKey {
+            name: LabelString::from("bob.user.zeus.gent"),
+            algorithm: TsigAlgorithm::HmacSha256,
+            secret: b"zeus".to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_sign_verify_request() {
+        let key = get_key();
+        let unsigned = Message::to_bytes(get_message(None));
+        let mut request = unsigned.clone();
+        let mac = sign_request(&mut request, &key, 1_000_000).unwrap();
+
+        let tsig = Tsig::from_message(&request).unwrap();
+        assert_eq!(tsig.mac(), mac);
+        assert_eq!(tsig.message, unsigned);
+        assert_eq!(tsig.verify(&key, None, 1_000_000), Ok(()));
+        assert_eq!(tsig.verify(&key, None, 1_000_300), Ok(()));
+        assert_eq!(tsig.verify(&key, None, 1_000_301), Err(TsigError::BADTIME));
+
+        let other = TsigKey {
+            secret: b"other".to_vec(),
+            ..get_key()
+        };
+        assert_eq!(tsig.verify(&other, None, 1_000_000), Err(TsigError::BADSIG));
+
+        let other = TsigKey {
+            name: LabelString::from("alice.user.zeus.gent"),
+            ..get_key()
+        };
+        assert_eq!(tsig.verify(&other, None, 1_000_000), Err(TsigError::BADKEY));
+
+        // Changed message
+        let mut changed = request.clone();
+        changed[3] ^= 1;
+        let tsig = Tsig::from_message(&changed).unwrap();
+        assert_eq!(tsig.verify(&key, None, 1_000_000), Err(TsigError::BADSIG));
+    }
+
+    #[test]
+    fn test_verify_vector() {
+        use base64::prelude::*;
+
+        // UPDATE for bob.user.zeus.gent, signed with HMAC-SHA256 key `zeus` at 1700000000
+        let request = BASE64_STANDARD
+            .decode(
+                "EjQoAAABAAAAAAABA2JvYgR1c2VyBHpldXMEZ2VudAAABgABA2JvYgR1c2VyBHpldXMEZ2VudAAA+gD/\
+                 AAAAAAA9C2htYWMtc2hhMjU2AAAAZVPxAAEsACCaVjJPTtF6uYHDXrtKrVVEuyAwok82VqGOzexD4ERv\
+                 sBI0AAAAAA==",
+            )
+            .unwrap();
+
+        let tsig = Tsig::from_message(&request).unwrap();
+        assert_eq!(tsig.verify(&get_key(), None, 1_700_000_000), Ok(()));
+
+        let (mut resigned, _) = Message::split_last_additional(&request).unwrap();
+        assert_eq!(
+            sign_request(&mut resigned, &get_key(), 1_700_000_000).unwrap(),
+            tsig.mac()
+        );
+        assert_eq!(resigned, request);
+    }
+
+    #[test]
+    fn test_original_id() {
+        let key = get_key();
+        let mut request = Message::to_bytes(get_message(None));
+        sign_request(&mut request, &key, 1_000_000).unwrap();
+
+        // ID can be changed by forwarders
+        request[0..2].copy_from_slice(&[0xAB, 0xCD]);
+        let tsig = Tsig::from_message(&request).unwrap();
+        assert_eq!(tsig.verify(&key, None, 1_000_000), Ok(()));
+    }
+
+    #[test]
+    fn test_multi_message() {
+        let key = get_key();
+        let mut request = Message::to_bytes(get_message(None));
+        let request_mac = sign_request(&mut request, &key, 1_000_000).unwrap();
+
+        let mut signer = TsigStream::new(key.clone(), &request_mac);
+        let mut messages = vec![];
+        for _ in 0..3 {
+            let mut message = Message::to_bytes(get_message(None));
+            signer
+                .sign(&mut message, 1_000_000, TsigError::NOERROR, vec![])
+                .unwrap();
+            messages.push(message);
+        }
+
+        let mut verifier = TsigStream::new(key.clone(), &request_mac);
+        for message in &messages {
+            assert_eq!(verifier.verify(message, 1_000_000), Ok(()));
+        }
+
+        // Messages must be verified in order
+        let mut verifier = TsigStream::new(key.clone(), &request_mac);
+        assert_eq!(
+            verifier.verify(&messages[1], 1_000_000),
+            Err(TsigError::BADSIG)
+        );
+
+        // Response to another request
+        let mut verifier = TsigStream::new(key, &[0; 32]);
+        assert_eq!(
+            verifier.verify(&messages[0], 1_000_000),
+            Err(TsigError::BADSIG)
+        );
+    }
+
+    #[test]
+    fn test_append_error() {
+        let key = get_key();
+        let mut request = Message::to_bytes(get_message(None));
+        sign_request(&mut request, &key, 1_000_000).unwrap();
+        let tsig = Tsig::from_message(&request).unwrap();
+
+        let mut response = Message::to_bytes(get_message(None));
+        tsig.append_error(&mut response, TsigError::BADKEY).unwrap();
+
+        let error = Tsig::from_message(&response).unwrap();
+        assert_eq!(error.name, key.name);
+        assert_eq!(error.rdata.error, TsigError::BADKEY as u16);
+        assert!(error.mac().is_empty());
+    }
+}